@@ -0,0 +1,492 @@
+use bevy::{asset::Error, prelude::Vec3, render::render_resource::Extent3d};
+use bevy_ray_marching::{
+    model::{AutoResolution, DetailTarget, DistanceError, Model},
+    ray_marching::{Ktx2Format, ShapeImage},
+    volume::{Endian, NrrdHeader, RawHeader, ScalarVolume, ValueType},
+};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::Instant,
+};
+
+const USAGE: &str = "\
+usage: sdf-bake <input> <output> [options]
+
+arguments:
+    <input>                 mesh to bake (.ply) or scalar volume to convert (.nrrd, .nhdr, .raw)
+    <output>                baked distance field (.sdf, .ktx2), png slices are written
+                            next to it as <output>_<z>.png
+
+options:
+    -r, --resolution <n>    texels per axis of meshes, either <n>, <w>x<h>x<d> or auto to choose
+                            them and the padding from the median edge length and the thin
+                            features of the mesh (default: 64)
+    -v, --voxel-size <s>    texel size of meshes in model units, overrides --resolution
+        --max-error <e>     choose the resolution and padding of meshes from a distance error
+                            budget in model units, overrides --resolution
+        --memory <mib>      memory budget of automatic resolutions in MiB (default: 64)
+    -p, --padding <n>       texels of padding around the mesh bounds (default: 4)
+    -f, --format <format>   output format: sdf, ktx2 (32 bit float), ktx2-half (16 bit float)
+                            or png (default: from the output extension, otherwise sdf)
+        --redistance        recompute the distances from the zero level set after baking
+        --iso <value>       iso value of scalar volumes, values above it are inside
+                            (default: the iso key of the header or the middle of the value range)
+        --raw-size <size>   texels per axis of raw volumes, <w>x<h>x<d>
+        --raw-type <type>   value type of raw volumes, e.g. uint8, int16 or float32
+        --raw-endian <e>    byte order of raw volumes: little or big (default: little)
+        --raw-spacing <s>   texel size of raw volumes, either <s> or <x>,<y>,<z> (default: 1)
+                            the size and the type default to the ones in file names like
+                            <name>_<w>x<h>x<d>_<type>.raw
+        --quality <samples> compare the baked distances of meshes to the exact ones at the
+                            given number of random points and print the errors
+    -h, --help              print this message";
+
+#[derive(Clone, Copy)]
+enum Format {
+    Sdf,
+    Ktx2(Ktx2Format),
+    Png,
+}
+
+enum Resolution {
+    Extent(Extent3d),
+    VoxelSize(f32),
+    Auto(DetailTarget),
+}
+
+struct Options {
+    input: PathBuf,
+    output: PathBuf,
+    resolution: Resolution,
+    memory_budget: usize,
+    padding: Option<u32>,
+    format: Option<Format>,
+    redistance: bool,
+    iso: Option<f32>,
+    quality_samples: Option<usize>,
+    raw: RawOptions,
+}
+
+#[derive(Default)]
+struct RawOptions {
+    resolution: Option<Extent3d>,
+    value_type: Option<ValueType>,
+    endian: Option<Endian>,
+    spacing: Option<Vec3>,
+}
+
+fn main() -> ExitCode {
+    let options = match parse_options(env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(error) => {
+            eprintln!("error: {error}\n\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match bake(&options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Option<Options>, Error> {
+    let mut input = None;
+    let mut output = None;
+    let mut resolution = Resolution::Extent(Extent3d {
+        width: 64,
+        height: 64,
+        depth_or_array_layers: 64,
+    });
+    let mut memory_budget = AutoResolution::default().memory_budget;
+    let mut padding = None;
+    let mut format = None;
+    let mut redistance = false;
+    let mut iso = None;
+    let mut quality_samples = None;
+    let mut raw = RawOptions::default();
+
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| Error::msg(format!("missing value for {arg}")))
+        };
+
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-r" | "--resolution" => {
+                resolution = match value()?.as_str() {
+                    "auto" => Resolution::Auto(AutoResolution::default().target),
+                    extent => Resolution::Extent(parse_extent(extent)?),
+                }
+            }
+            "-v" | "--voxel-size" => {
+                let size = value()?.parse::<f32>()?;
+                if size.is_nan() || size <= 0.0 {
+                    return Err(Error::msg("voxel size must be positive"));
+                }
+                resolution = Resolution::VoxelSize(size);
+            }
+            "--max-error" => {
+                let error = value()?.parse::<f32>()?;
+                if error.is_nan() || error <= 0.0 {
+                    return Err(Error::msg("max error must be positive"));
+                }
+                resolution = Resolution::Auto(DetailTarget::ErrorBudget(error));
+            }
+            "--memory" => memory_budget = value()?.parse::<usize>()? * 1024 * 1024,
+            "-p" | "--padding" => padding = Some(value()?.parse()?),
+            "-f" | "--format" => {
+                format = Some(match value()?.as_str() {
+                    "sdf" => Format::Sdf,
+                    "ktx2" => Format::Ktx2(Ktx2Format::R32Float),
+                    "ktx2-half" => Format::Ktx2(Ktx2Format::R16Float),
+                    "png" => Format::Png,
+                    format => return Err(Error::msg(format!("unknown format: {format}"))),
+                })
+            }
+            "--redistance" => redistance = true,
+            "--iso" => iso = Some(value()?.parse()?),
+            "--quality" => quality_samples = Some(value()?.parse()?),
+            "--raw-size" => raw.resolution = Some(parse_extent(&value()?)?),
+            "--raw-type" => raw.value_type = Some(ValueType::from_name(&value()?)?),
+            "--raw-endian" => {
+                raw.endian = Some(match value()?.as_str() {
+                    "little" => Endian::Little,
+                    "big" => Endian::Big,
+                    endian => return Err(Error::msg(format!("unknown endian: {endian}"))),
+                })
+            }
+            "--raw-spacing" => raw.spacing = Some(parse_spacing(&value()?)?),
+            _ if arg.starts_with('-') => return Err(Error::msg(format!("unknown option: {arg}"))),
+            _ if input.is_none() => input = Some(PathBuf::from(arg)),
+            _ if output.is_none() => output = Some(PathBuf::from(arg)),
+            _ => return Err(Error::msg(format!("unexpected argument: {arg}"))),
+        }
+    }
+
+    Ok(Some(Options {
+        input: input.ok_or_else(|| Error::msg("missing input path"))?,
+        output: output.ok_or_else(|| Error::msg("missing output path"))?,
+        resolution,
+        memory_budget,
+        padding,
+        format,
+        redistance,
+        iso,
+        quality_samples,
+        raw,
+    }))
+}
+
+fn parse_extent(string: &str) -> Result<Extent3d, Error> {
+    let split = string
+        .split('x')
+        .map(|value| value.parse::<u32>())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match split[..] {
+        [size] => Ok(Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: size,
+        }),
+        [width, height, depth] => Ok(Extent3d {
+            width,
+            height,
+            depth_or_array_layers: depth,
+        }),
+        _ => Err(Error::msg(format!("invalid resolution: {string}"))),
+    }
+}
+
+fn parse_spacing(string: &str) -> Result<Vec3, Error> {
+    let split = string
+        .split(',')
+        .map(|value| value.parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let spacing = match split[..] {
+        [size] => Vec3::splat(size),
+        [x, y, z] => Vec3::new(x, y, z),
+        _ => return Err(Error::msg(format!("invalid spacing: {string}"))),
+    };
+    if !spacing.cmpgt(Vec3::ZERO).all() {
+        return Err(Error::msg("spacing must be positive"));
+    }
+    Ok(spacing)
+}
+
+fn read_input(path: &Path) -> Result<Vec<u8>, Error> {
+    fs::read(path).map_err(|error| Error::msg(format!("{}: {error}", path.display())))
+}
+
+fn bake(options: &Options) -> Result<(), Error> {
+    let (mut shape_image, model) = match options
+        .input
+        .extension()
+        .and_then(|extension| extension.to_str())
+    {
+        Some("ply") => {
+            let (shape_image, model) = bake_model(options)?;
+            (shape_image, Some(model))
+        }
+        Some("nrrd" | "nhdr" | "raw") => (convert_volume(options)?, None),
+        _ => {
+            return Err(Error::msg(format!(
+                "unsupported input format: {}",
+                options.input.display()
+            )))
+        }
+    };
+    print_gradient_deviation(&shape_image);
+
+    if options.redistance {
+        let start = Instant::now();
+        shape_image.redistance();
+        println!("redistanced in {:.2?}", start.elapsed());
+        print_gradient_deviation(&shape_image);
+    }
+
+    if let Some(samples) = options.quality_samples {
+        match &model {
+            Some(model) => print_quality(model, &shape_image, samples),
+            None => eprintln!("warning: quality metrics need a mesh as input"),
+        }
+    }
+
+    println!(
+        "size: {}, texel size: {}",
+        shape_image.size,
+        shape_image.texel_size()
+    );
+
+    let format = options.format.unwrap_or_else(|| {
+        match options
+            .output
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some("ktx2") => Format::Ktx2(Ktx2Format::R32Float),
+            Some("png") => Format::Png,
+            _ => Format::Sdf,
+        }
+    });
+    match format {
+        Format::Sdf => write_output(&options.output, &Box::<[u8]>::from(shape_image))?,
+        Format::Ktx2(ktx2_format) => {
+            write_output(&options.output, &shape_image.to_ktx2(ktx2_format))?
+        }
+        Format::Png => {
+            let stem = options.output.with_extension("");
+            for (z, bytes) in shape_image.to_png_slices()?.iter().enumerate() {
+                let mut path = stem.clone().into_os_string();
+                path.push(format!("_{z:03}.png"));
+                write_output(Path::new(&path), bytes)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn write_output(path: &Path, bytes: &[u8]) -> Result<(), Error> {
+    fs::write(path, bytes).map_err(|error| Error::msg(format!("{}: {error}", path.display())))?;
+    println!("wrote {}", path.display());
+    Ok(())
+}
+
+fn bake_model(options: &Options) -> Result<(ShapeImage, Model), Error> {
+    let start = Instant::now();
+    let model = Model::from_ply(String::from_utf8(read_input(&options.input)?)?)?;
+    println!(
+        "loaded {} ({} triangles) in {:.2?}",
+        options.input.display(),
+        model.triangle_count(),
+        start.elapsed()
+    );
+    println!("bounds: {} - {}", model.min(), model.max());
+
+    let padding = options.padding.unwrap_or(4);
+    let (resolution, padding) = match options.resolution {
+        Resolution::Extent(extent) => (extent, padding),
+        Resolution::VoxelSize(voxel_size) => {
+            let texels = |size: f32| (size / voxel_size).ceil() as u32 + 2 * padding + 1;
+            let size = model.max() - model.min();
+            (
+                Extent3d {
+                    width: texels(size.x),
+                    height: texels(size.y),
+                    depth_or_array_layers: texels(size.z),
+                },
+                padding,
+            )
+        }
+        Resolution::Auto(target) => {
+            if options.padding.is_some() {
+                return Err(Error::msg(
+                    "the padding is chosen along with automatic resolutions",
+                ));
+            }
+
+            let start = Instant::now();
+            let choice = model.choose_resolution(&AutoResolution {
+                target,
+                memory_budget: options.memory_budget,
+                ..Default::default()
+            })?;
+            println!("chose resolution in {:.2?}", start.elapsed());
+            println!(
+                "median edge length: {}, thin features: {}, voxel size: {}",
+                choice.median_edge_length,
+                choice
+                    .thin_feature_size
+                    .map_or(String::from("none"), |size| size.to_string()),
+                choice.voxel_size
+            );
+            println!(
+                "memory: {:.1} MiB{}",
+                choice.memory as f32 / (1024.0 * 1024.0),
+                if choice.limited {
+                    " (voxels enlarged to fit the memory budget)"
+                } else {
+                    ""
+                }
+            );
+            (choice.resolution, choice.padding)
+        }
+    };
+
+    let min_resolution = 2 * padding + 2;
+    if resolution.width < min_resolution
+        || resolution.height < min_resolution
+        || resolution.depth_or_array_layers < min_resolution
+    {
+        return Err(Error::msg(format!(
+            "resolution must be at least {min_resolution} per axis with a padding of {padding}"
+        )));
+    }
+
+    println!(
+        "resolution: {}x{}x{}, padding: {padding}",
+        resolution.width, resolution.height, resolution.depth_or_array_layers
+    );
+
+    let start = Instant::now();
+    let shape_image = model.to_shape_image(resolution, padding)?;
+    println!("baked in {:.2?}", start.elapsed());
+    Ok((shape_image, model))
+}
+
+fn convert_volume(options: &Options) -> Result<ShapeImage, Error> {
+    let start = Instant::now();
+    let bytes = read_input(&options.input)?;
+    let extension = options
+        .input
+        .extension()
+        .and_then(|extension| extension.to_str());
+    let (volume, header_iso_value) = if extension == Some("raw") {
+        (ScalarVolume::from_raw(&bytes, &raw_header(options)?)?, None)
+    } else {
+        let (header, offset) = NrrdHeader::parse(&bytes)?;
+        let volume = match header.data_path(&options.input) {
+            Some(data_path) => header.load(&read_input(&data_path)?)?,
+            None => header.load(&bytes[offset..])?,
+        };
+        (volume, header.iso_value)
+    };
+    println!(
+        "loaded {} in {:.2?}",
+        options.input.display(),
+        start.elapsed()
+    );
+
+    let resolution = volume.resolution();
+    let (min, max) = volume.value_range();
+    let iso_value = options
+        .iso
+        .or(header_iso_value)
+        .unwrap_or((min + max) / 2.0);
+    println!(
+        "resolution: {}x{}x{}, spacing: {}",
+        resolution.width,
+        resolution.height,
+        resolution.depth_or_array_layers,
+        volume.spacing()
+    );
+    println!("values: {min} - {max}, iso value: {iso_value}");
+
+    let start = Instant::now();
+    let shape_image = volume.to_shape_image(iso_value)?;
+    println!("converted in {:.2?}", start.elapsed());
+    Ok(shape_image)
+}
+
+// The flags override the values in the file name
+fn raw_header(options: &Options) -> Result<RawHeader, Error> {
+    let file_name = options
+        .input
+        .file_name()
+        .and_then(|file_name| file_name.to_str())
+        .unwrap_or_default();
+    let from_file_name = RawHeader::from_file_name(file_name);
+
+    let (resolution, value_type) = match (options.raw.resolution, options.raw.value_type) {
+        (Some(resolution), Some(value_type)) => (resolution, value_type),
+        (resolution, value_type) => {
+            let header = from_file_name.map_err(|error| {
+                Error::msg(format!("{error} or the --raw-size and --raw-type options"))
+            })?;
+            (
+                resolution.unwrap_or(header.resolution),
+                value_type.unwrap_or(header.value_type),
+            )
+        }
+    };
+
+    Ok(RawHeader {
+        resolution,
+        spacing: options.raw.spacing.unwrap_or(Vec3::ONE),
+        value_type,
+        endian: options.raw.endian.unwrap_or(Endian::Little),
+    })
+}
+
+fn print_gradient_deviation(shape_image: &ShapeImage) {
+    let deviation = shape_image.gradient_deviation();
+    println!(
+        "gradient: {:.3} - {:.3}, mean deviation: {:.4}, rms deviation: {:.4}",
+        deviation.min_gradient,
+        deviation.max_gradient,
+        deviation.mean_deviation,
+        deviation.rms_deviation
+    );
+}
+
+fn print_quality(model: &Model, shape_image: &ShapeImage, samples: usize) {
+    let start = Instant::now();
+    let quality = model.bake_quality(shape_image, samples, 0);
+    println!("measured quality in {:.2?}", start.elapsed());
+
+    let print_error = |name: &str, error: &DistanceError| {
+        println!(
+            "{name} error: max {:.5}, mean {:.5}, rms {:.5}, sign mismatches {:.2}%",
+            error.max_error,
+            error.mean_error,
+            error.rms_error,
+            error.sign_mismatch_rate * 100.0
+        )
+    };
+    print_error("near surface", &quality.near_surface);
+    print_error("volume", &quality.volume);
+    println!("hausdorff distance: {:.5}", quality.hausdorff_distance);
+}
@@ -0,0 +1,3 @@
+pub mod model;
+pub mod ray_marching;
+pub mod volume;
@@ -1,25 +1,16 @@
-mod model;
-mod ray_marching;
 mod user_interface;
 use bevy::diagnostic::FrameTimeDiagnosticsPlugin;
 use bevy::prelude::*;
-use bevy::render::render_resource::Extent3d;
-use bevy::utils::HashMap;
+use bevy::render::{
+    render_resource::{Extent3d, TextureDimension, TextureFormat},
+    texture::Image as TextureImage,
+};
 use bevy::{diagnostic::LogDiagnosticsPlugin, input::mouse::MouseWheel};
 use bevy_egui::EguiPlugin;
-use model::Model;
+use bevy_ray_marching::ray_marching;
 use ray_marching::RayMarching;
-use ray_marching::{
-    Environment, Material,
-    Operation::Intersection,
-    Primitive::{Cube, Image, Plane, Sphere},
-    RayMarchingPlugin, Shape, ShapeImage,
-    ShapeType::{Compound, Primitive},
-};
+use ray_marching::{Environment, RayMarchingPlugin, ShapeImage, ShapeOctree};
 use std::f32::consts;
-use std::fs::File;
-use std::io::Read;
-use std::io::Write;
 use user_interface::UIPlugin;
 
 #[derive(Component)]
@@ -32,15 +23,23 @@ struct OrbitControls {
 #[derive(Resource, Deref, DerefMut, Default)]
 pub struct Images(Vec<(String, Handle<ShapeImage>)>);
 
-fn main() {
-    //    generate();
-    //    return;
+#[derive(Resource, Deref, DerefMut, Default)]
+pub struct Octrees(Vec<(String, Handle<ShapeOctree>)>);
 
+#[derive(Resource, Deref, DerefMut, Default)]
+pub struct Heightfields(Vec<(String, Handle<TextureImage>)>);
+
+fn main() {
     App::new()
         .init_resource::<Images>()
-        .add_plugins(DefaultPlugins)
+        .init_resource::<Octrees>()
+        .init_resource::<Heightfields>()
+        .add_plugins(DefaultPlugins.set(AssetPlugin {
+            watch_for_changes: true,
+            ..default()
+        }))
         .add_plugin(LogDiagnosticsPlugin::default())
-        .add_plugin(FrameTimeDiagnosticsPlugin::default())
+        .add_plugin(FrameTimeDiagnosticsPlugin)
         .add_plugin(EguiPlugin)
         .add_plugin(RayMarchingPlugin)
         .add_plugin(UIPlugin)
@@ -50,33 +49,21 @@ fn main() {
         .run();
 }
 
-fn generate() {
-    let resolution = 128;
-    let padding = 8;
-
-    let model = Model::from_ply(include_str!("../assets/bunny.ply").into())
-        .unwrap()
-        .to_shape_image(
-            Extent3d {
-                width: resolution,
-                height: resolution,
-                depth_or_array_layers: resolution,
-            },
-            padding,
-        );
-
-    dbg!(model.size, model.resolution);
-
-    let mut file = File::create(format!("assets/bunny_{resolution}.sdf")).unwrap();
-    file.write_all(&Box::<[u8]>::from(model)).unwrap();
-}
-
-fn setup(mut commands: Commands, mut images: ResMut<Images>, asset_server: Res<AssetServer>) {
+fn setup(
+    mut commands: Commands,
+    mut images: ResMut<Images>,
+    mut octrees: ResMut<Octrees>,
+    mut heightfields: ResMut<Heightfields>,
+    mut textures: ResMut<Assets<TextureImage>>,
+    asset_server: Res<AssetServer>,
+) {
     let bunny = asset_server.load("bunny_128.sdf");
     let ico = asset_server.load("ico.ply");
 
     images.push(("Bunny".into(), bunny.clone()));
     images.push(("Icosahedron".into(), ico.clone()));
+    octrees.push(("Icosahedron".into(), asset_server.load("ico.octree.ply")));
+    heightfields.push(("Hills".into(), textures.add(hills(128))));
 
     commands.spawn((
         Camera3dBundle::default(),
@@ -258,12 +245,7 @@ fn orbit_controller(
     for event in scroll_event.iter() {
         for mut orbit in orbits.iter_mut() {
             orbit.rotation -= Vec2::new(event.x, event.y) * 0.0025;
-            if orbit.rotation.y < 0.0 {
-                orbit.rotation.y = 0.0;
-            }
-            if orbit.rotation.y > consts::PI {
-                orbit.rotation.y = consts::PI;
-            }
+            orbit.rotation.y = orbit.rotation.y.clamp(0.0, consts::PI);
         }
     }
 }
@@ -276,3 +258,26 @@ fn orbit_updater(mut orbits: Query<(&mut Transform, &OrbitControls)>) {
         transform.rotation = rotation;
     }
 }
+
+// Rolling hills for the heightfields, with the heights in the red channel
+fn hills(resolution: u32) -> TextureImage {
+    let data = (0..resolution * resolution)
+        .map(|index| {
+            let x = (index % resolution) as f32 / resolution as f32 * consts::TAU;
+            let y = (index / resolution) as f32 / resolution as f32 * consts::TAU;
+            let height =
+                0.5 + 0.25 * (2.0 * x).sin() * (3.0 * y).cos() + 0.1 * (5.0 * (x + y)).sin();
+            (height * 255.0) as u8
+        })
+        .collect();
+    TextureImage::new(
+        Extent3d {
+            width: resolution,
+            height: resolution,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::R8Unorm,
+    )
+}
@@ -0,0 +1,114 @@
+use bevy::{prelude::Vec3, utils::HashMap};
+
+use crate::ray_marching::ShapeImage;
+
+use super::Model;
+
+// Surface nets of the zero level set, one vertex per cell between texel centers
+pub fn surface_nets(shape_image: &ShapeImage) -> Model {
+    let width = shape_image.resolution.width;
+    let height = shape_image.resolution.height;
+    let depth = shape_image.resolution.depth_or_array_layers;
+
+    let mut vertices = HashMap::new();
+    for z in 0..depth.saturating_sub(1) {
+        for y in 0..height.saturating_sub(1) {
+            for x in 0..width.saturating_sub(1) {
+                if let Some(vertex) = cell_vertex(shape_image, x, y, z) {
+                    vertices.insert((x, y, z), vertex);
+                }
+            }
+        }
+    }
+
+    let mut model = Model::new();
+    for z in 0..depth {
+        for y in 0..height {
+            for x in 0..width {
+                let value = shape_image.texel(x, y, z);
+                // Every edge with a sign change is surrounded by four cells forming a quad
+                for axis in 0..3 {
+                    let (next, cells) = match axis {
+                        0 if x + 1 < width && y > 0 && z > 0 => (
+                            (x + 1, y, z),
+                            [(x, y - 1, z - 1), (x, y, z - 1), (x, y, z), (x, y - 1, z)],
+                        ),
+                        1 if y + 1 < height && x > 0 && z > 0 => (
+                            (x, y + 1, z),
+                            [(x - 1, y, z - 1), (x - 1, y, z), (x, y, z), (x, y, z - 1)],
+                        ),
+                        2 if z + 1 < depth && x > 0 && y > 0 => (
+                            (x, y, z + 1),
+                            [(x - 1, y - 1, z), (x, y - 1, z), (x, y, z), (x - 1, y, z)],
+                        ),
+                        _ => continue,
+                    };
+                    let next_value = shape_image.texel(next.0, next.1, next.2);
+                    if (value < 0.0) == (next_value < 0.0) {
+                        continue;
+                    }
+
+                    let Some(quad) = cells
+                        .iter()
+                        .map(|cell| vertices.get(cell).copied())
+                        .collect::<Option<Vec<_>>>()
+                    else {
+                        continue;
+                    };
+                    if value < 0.0 {
+                        model.push_triangle(quad[0], quad[1], quad[2]);
+                        model.push_triangle(quad[0], quad[2], quad[3]);
+                    } else {
+                        model.push_triangle(quad[0], quad[2], quad[1]);
+                        model.push_triangle(quad[0], quad[3], quad[2]);
+                    }
+                }
+            }
+        }
+    }
+    model
+}
+
+// The average of the crossings on the edges of the cell
+fn cell_vertex(shape_image: &ShapeImage, x: u32, y: u32, z: u32) -> Option<Vec3> {
+    let corner = |corner: u32| {
+        (
+            x + (corner & 1),
+            y + (corner >> 1 & 1),
+            z + (corner >> 2 & 1),
+        )
+    };
+
+    let mut sum = Vec3::ZERO;
+    let mut count = 0;
+    for (from, to) in [
+        (0, 1),
+        (2, 3),
+        (4, 5),
+        (6, 7),
+        (0, 2),
+        (1, 3),
+        (4, 6),
+        (5, 7),
+        (0, 4),
+        (1, 5),
+        (2, 6),
+        (3, 7),
+    ] {
+        let (x0, y0, z0) = corner(from);
+        let (x1, y1, z1) = corner(to);
+        let value0 = shape_image.texel(x0, y0, z0);
+        let value1 = shape_image.texel(x1, y1, z1);
+        if (value0 < 0.0) == (value1 < 0.0) {
+            continue;
+        }
+
+        let factor = value0 / (value0 - value1);
+        sum += shape_image
+            .texel_position(x0, y0, z0)
+            .lerp(shape_image.texel_position(x1, y1, z1), factor);
+        count += 1;
+    }
+
+    (count > 0).then(|| sum / count as f32)
+}
@@ -1,13 +1,29 @@
+mod meshing;
+mod octree;
 mod ply;
+mod quality;
+mod resolution;
 mod shape;
+
+pub use quality::{BakeQuality, DistanceError};
+pub use resolution::{AutoResolution, DetailTarget, ResolutionChoice};
+
 use bevy::{asset::Error, prelude::Vec3, render::render_resource::Extent3d};
 
-use crate::ray_marching::ShapeImage;
+use crate::ray_marching::{ShapeImage, ShapeOctree};
 
 pub struct Model {
     min: Vec3,
     max: Vec3,
     triangles: Vec<Triangle>,
+    // Running sum of the triangle areas
+    areas: Vec<f32>,
+}
+
+impl Default for Model {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Model {
@@ -16,6 +32,7 @@ impl Model {
             min: Vec3::ZERO,
             max: Vec3::ZERO,
             triangles: Vec::new(),
+            areas: Vec::new(),
         }
     }
 
@@ -24,6 +41,7 @@ impl Model {
             min: Vec3::ZERO,
             max: Vec3::ZERO,
             triangles: Vec::with_capacity(capacity),
+            areas: Vec::with_capacity(capacity),
         }
     }
 
@@ -31,10 +49,30 @@ impl Model {
         ply::load(string)
     }
 
-    pub fn to_shape_image(&self, resolution: Extent3d, padding: u32) -> ShapeImage {
+    // Meshes the zero level set of the image, in the space of the image
+    pub fn from_shape_image(shape_image: &ShapeImage) -> Self {
+        meshing::surface_nets(shape_image)
+    }
+
+    pub fn to_shape_image(&self, resolution: Extent3d, padding: u32) -> Result<ShapeImage, Error> {
         shape::build(self, resolution, padding)
     }
 
+    // Adaptive distance field, subdivided where the trilinear interpolation of a node is off by
+    // more than the tolerance, centered on the model like to_shape_image
+    pub fn to_shape_octree(&self, tolerance: f32, max_depth: u32) -> ShapeOctree {
+        octree::build(self, tolerance, max_depth)
+    }
+
+    pub fn to_shape_image_auto(
+        &self,
+        settings: &AutoResolution,
+    ) -> Result<(ShapeImage, ResolutionChoice), Error> {
+        let choice = self.choose_resolution(settings)?;
+        let shape_image = shape::build(self, choice.resolution, choice.padding)?;
+        Ok((shape_image, choice))
+    }
+
     pub fn min(&self) -> Vec3 {
         self.min
     }
@@ -66,7 +104,14 @@ impl Model {
             self.max.y = self.max.y.max(max.y);
             self.max.z = self.max.z.max(max.z);
         }
-        self.triangles.push(Triangle::new(p1, p2, p3));
+        let triangle = Triangle::new(p1, p2, p3);
+        let area = self.areas.last().copied().unwrap_or(0.0) + triangle.norm.length() / 2.0;
+        self.triangles.push(triangle);
+        self.areas.push(area);
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.triangles.len()
     }
 
     pub fn distance(&self, pnt: Vec3) -> f32 {
@@ -89,6 +134,16 @@ impl Model {
             -dist
         }
     }
+
+    pub fn unsigned_distance(&self, pnt: Vec3) -> f32 {
+        let mut dist = f32::INFINITY;
+        for triangle in self.triangles.iter() {
+            if triangle.dist_approx(pnt) < dist {
+                dist = f32::min(dist, triangle.dist(pnt));
+            }
+        }
+        dist
+    }
 }
 
 struct Triangle {
@@ -145,7 +200,7 @@ impl Triangle {
         let d2 = pnt - self.p2;
         let d3 = pnt - self.p3;
 
-        return f32::sqrt(
+        f32::sqrt(
             if self.c21.dot(d1).signum() + self.c32.dot(d2).signum() + self.c13.dot(d3).signum()
                > -2.0
             {
@@ -164,40 +219,135 @@ impl Triangle {
             } else {
                 self.norm.dot(d1) * self.norm.dot(d1) / self.norm.length_squared()
             },
-        );
+        )
     }
 
     fn intersects(&self, pnt: Vec3, dir: Vec3) -> bool {
+        self.intersection(pnt, dir).is_some()
+    }
+
+    // Distance along the ray to the triangle
+    fn intersection(&self, pnt: Vec3, dir: Vec3) -> Option<f32> {
         let to_center = self.center - pnt;
         let doc = dir.dot(to_center);
         if to_center.length_squared() - doc * doc > self.radius * self.radius {
-            return false;
+            return None;
         }
 
         let nod = self.norm.dot(dir);
         if nod == 0.0 {
-            return false;
+            return None;
         }
 
         let t = -(self.norm.dot(pnt) - self.norm.dot(self.p1)) / nod;
         if t < 0.0 {
-            return false;
+            return None;
         }
 
         let p = pnt + dir * t;
 
         if self.norm.dot(self.e21.cross(p - self.p1)) < 0.0 {
-            return false;
+            return None;
         }
 
         if self.norm.dot(self.e32.cross(p - self.p2)) < 0.0 {
-            return false;
+            return None;
         }
 
         if self.norm.dot(self.e13.cross(p - self.p3)) < 0.0 {
-            return false;
+            return None;
+        }
+
+        Some(t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::prelude::{BVec3, Quat};
+
+    const CUBOID: Vec3 = Vec3::new(1.0, 0.83, 0.61);
+
+    // A box around the origin, the corners are ordered like the corners of octree nodes. The
+    // faces are fans around a point off their center, the rays of distance run through the
+    // centers of the faces and would hit the edges of split quads
+    fn cuboid(half_size: Vec3, rotation: Quat) -> Model {
+        let corner = |index: usize| {
+            Vec3::select(
+                BVec3::new(index & 1 != 0, index & 2 != 0, index & 4 != 0),
+                half_size,
+                -half_size,
+            )
+        };
+        let faces = [
+            [0, 2, 3, 1],
+            [4, 5, 7, 6],
+            [0, 1, 5, 4],
+            [2, 6, 7, 3],
+            [0, 4, 6, 2],
+            [1, 3, 7, 5],
+        ];
+
+        let mut model = Model::new();
+        for face in faces {
+            let [a, b, c, d] = face.map(|index| rotation * corner(index));
+            let fan = a.lerp(c, 0.5).lerp(b, 0.3).lerp(a, 0.15);
+            for (from, to) in [(a, b), (b, c), (c, d), (d, a)] {
+                model.push_triangle(fan, from, to);
+            }
         }
+        model
+    }
+
+    fn cuboid_distance(half_size: Vec3, pnt: Vec3) -> f32 {
+        let q = pnt.abs() - half_size;
+        q.max(Vec3::ZERO).length() + q.max_element().min(0.0)
+    }
+
+    #[test]
+    fn measures_the_distance_to_a_cuboid() {
+        let model = cuboid(CUBOID, Quat::IDENTITY);
+        assert_eq!(model.triangle_count(), 24);
+        assert_eq!(model.min(), -CUBOID);
+        assert_eq!(model.max(), CUBOID);
+
+        for pnt in [
+            Vec3::new(0.3, 0.1, -0.2),
+            Vec3::new(0.9, -0.4, 0.35),
+            Vec3::new(1.5, 0.2, 0.1),
+            Vec3::new(-2.0, 1.5, 1.25),
+            Vec3::new(0.15, -3.0, 0.05),
+        ] {
+            let exact = cuboid_distance(CUBOID, pnt);
+            assert!((model.distance(pnt) - exact).abs() < 1e-5);
+            assert!((model.unsigned_distance(pnt) - exact.abs()).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn rejects_resolutions_without_room_inside_the_padding() {
+        let model = cuboid(CUBOID, Quat::IDENTITY);
+        let resolution = |width, height, depth_or_array_layers| Extent3d {
+            width,
+            height,
+            depth_or_array_layers,
+        };
+        assert!(model.to_shape_image(resolution(5, 8, 8), 2).is_err());
+        assert!(model.to_shape_image(resolution(8, 8, 0), 0).is_err());
+        assert!(model.to_shape_image(resolution(6, 6, 6), 2).is_ok());
+    }
+
+    #[test]
+    fn reads_ascii_ply_files() {
+        let ply = "ply\nformat ascii 1.0\nelement vertex 4\nproperty float x\n\
+                   property float y\nproperty float z\nelement face 2\n\
+                   property list uchar uint vertex_indices\nend_header\n\
+                   0 0 0\n1 0 0\n1 1 0\n0 1 0\n3 0 1 2\n3 0 2 3\n";
+        let model = Model::from_ply(String::from(ply)).unwrap();
+        assert_eq!(model.triangle_count(), 2);
+        assert_eq!(model.max(), Vec3::new(1.0, 1.0, 0.0));
 
-        true
+        assert!(Model::from_ply(String::from("ply\nformat binary_little_endian 1.0\n")).is_err());
     }
 }
@@ -0,0 +1,286 @@
+use bevy::{prelude::Vec3, utils::HashMap};
+use std::{collections::VecDeque, thread};
+
+use crate::ray_marching::ShapeOctree;
+
+use super::Model;
+
+// Space around the model inside the root cube, relative to the size of the model
+const ROOT_MARGIN: f32 = 0.25;
+// Depth up to which the nodes are always subdivided
+const MIN_DEPTH: u32 = 2;
+// Away from the surface the error may grow with the distance to the closest sample of a node,
+// the tracer takes long steps there
+const FAR_FIELD_TOLERANCE: f32 = 0.1;
+
+enum Node {
+    Leaf([f32; 8]),
+    Branch(Box<[Node; 8]>),
+}
+
+// Lattice of the deepest level, the corners of the nodes are cached on it
+struct Lattice<'a> {
+    model: &'a Model,
+    min: Vec3,
+    step: f32,
+    max_depth: u32,
+    tolerance: f32,
+    distances: HashMap<[u32; 3], f32>,
+}
+
+pub fn build(model: &Model, tolerance: f32, max_depth: u32) -> ShapeOctree {
+    let max_depth = max_depth.clamp(1, 16);
+    let center = (model.min + model.max) / 2.0;
+    let size = (model.max - model.min).max_element() * (1.0 + ROOT_MARGIN);
+    let lattice_size = 1 << max_depth;
+
+    let lattice = |model| Lattice {
+        model,
+        min: center - size / 2.0,
+        step: size / lattice_size as f32,
+        max_depth,
+        tolerance,
+        distances: HashMap::new(),
+    };
+
+    // The children of the root are built in parallel
+    let half = lattice_size / 2;
+    let children = thread::scope(|scope| {
+        let handles = (0..8)
+            .map(|corner| {
+                scope.spawn(move || {
+                    lattice(model).build_node(corner_offset([0; 3], corner, half), half, 1)
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+    let root = Node::Branch(Box::new(children.try_into().ok().unwrap()));
+
+    let (nodes, samples) = flatten(&root);
+    ShapeOctree {
+        size,
+        nodes,
+        samples,
+    }
+}
+
+impl<'a> Lattice<'a> {
+    fn distance(&mut self, pnt: [u32; 3]) -> f32 {
+        let (model, min, step) = (self.model, self.min, self.step);
+        *self.distances.entry(pnt).or_insert_with(|| {
+            model.distance(min + Vec3::new(pnt[0] as f32, pnt[1] as f32, pnt[2] as f32) * step)
+        })
+    }
+
+    fn build_node(&mut self, min: [u32; 3], size: u32, depth: u32) -> Node {
+        let corners = [0, 1, 2, 3, 4, 5, 6, 7].map(|corner| {
+            let pnt = corner_offset(min, corner, size);
+            self.distance(pnt)
+        });
+        if depth == self.max_depth {
+            return Node::Leaf(corners);
+        }
+
+        let half = size / 2;
+        if depth >= MIN_DEPTH {
+            // Compares the trilinear interpolation to the midpoints of the edges, faces and cell
+            let mut max_error = 0.0f32;
+            let mut samples = Vec::with_capacity(27);
+            for z in 0..3 {
+                for y in 0..3 {
+                    for x in 0..3 {
+                        let exact = self.distance([
+                            min[0] + x * half,
+                            min[1] + y * half,
+                            min[2] + z * half,
+                        ]);
+                        let interpolated =
+                            trilinear(&corners, Vec3::new(x as f32, y as f32, z as f32) / 2.0);
+                        max_error = max_error.max((exact - interpolated).abs());
+                        samples.push(exact);
+                    }
+                }
+            }
+            if max_error <= node_tolerance(self.tolerance, &samples) {
+                return Node::Leaf(corners);
+            }
+        }
+
+        Node::Branch(Box::new([0, 1, 2, 3, 4, 5, 6, 7].map(|corner| {
+            self.build_node(corner_offset(min, corner, half), half, depth + 1)
+        })))
+    }
+}
+
+// Nodes the surface passes through keep the tolerance, the others may be off by a part of the
+// distance to their closest sample
+fn node_tolerance(tolerance: f32, samples: &[f32]) -> f32 {
+    let inside = samples.iter().any(|sample| *sample < 0.0);
+    let outside = samples.iter().any(|sample| *sample >= 0.0);
+    if inside && outside {
+        return tolerance;
+    }
+    let min_distance = samples
+        .iter()
+        .fold(f32::INFINITY, |min, sample| min.min(sample.abs()));
+    tolerance.max(min_distance * FAR_FIELD_TOLERANCE)
+}
+
+// Breadth first, so the children of every node are next to each other
+fn flatten(root: &Node) -> (Vec<u32>, Vec<f32>) {
+    let mut nodes = vec![0];
+    let mut samples = Vec::new();
+    let mut queue = VecDeque::from([(0, root)]);
+    while let Some((index, node)) = queue.pop_front() {
+        match node {
+            Node::Leaf(corners) => {
+                nodes[index] = ShapeOctree::LEAF | (samples.len() / 8) as u32;
+                samples.extend_from_slice(corners);
+            }
+            Node::Branch(children) => {
+                nodes[index] = nodes.len() as u32;
+                for child in children.iter() {
+                    queue.push_back((nodes.len(), child));
+                    nodes.push(0);
+                }
+            }
+        }
+    }
+    (nodes, samples)
+}
+
+fn corner_offset(min: [u32; 3], corner: u32, size: u32) -> [u32; 3] {
+    [
+        min[0] + (corner & 1) * size,
+        min[1] + (corner >> 1 & 1) * size,
+        min[2] + (corner >> 2 & 1) * size,
+    ]
+}
+
+// Same as the interpolation of sdf_octree in tracing.wgsl
+fn trilinear(corners: &[f32], factor: Vec3) -> f32 {
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+    let row = |offset: usize| lerp(corners[offset], corners[offset + 1], factor.x);
+    let slice = |offset: usize| lerp(row(offset), row(offset + 2), factor.y);
+    lerp(slice(0), slice(4), factor.z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::prelude::{BVec3, Quat};
+
+    const CUBOID: Vec3 = Vec3::new(1.0, 0.83, 0.61);
+
+    // A box around the origin, the corners are ordered like the corners of octree nodes. The
+    // faces are fans around a point off their center, the rays of distance run through the
+    // centers of the faces and would hit the edges of split quads
+    fn cuboid(half_size: Vec3, rotation: Quat) -> Model {
+        let corner = |index: usize| {
+            Vec3::select(
+                BVec3::new(index & 1 != 0, index & 2 != 0, index & 4 != 0),
+                half_size,
+                -half_size,
+            )
+        };
+        let faces = [
+            [0, 2, 3, 1],
+            [4, 5, 7, 6],
+            [0, 1, 5, 4],
+            [2, 6, 7, 3],
+            [0, 4, 6, 2],
+            [1, 3, 7, 5],
+        ];
+
+        let mut model = Model::new();
+        for face in faces {
+            let [a, b, c, d] = face.map(|index| rotation * corner(index));
+            let fan = a.lerp(c, 0.5).lerp(b, 0.3).lerp(a, 0.15);
+            for (from, to) in [(a, b), (b, c), (c, d), (d, a)] {
+                model.push_triangle(fan, from, to);
+            }
+        }
+        model
+    }
+
+    fn cuboid_distance(half_size: Vec3, pnt: Vec3) -> f32 {
+        let q = pnt.abs() - half_size;
+        q.max(Vec3::ZERO).length() + q.max_element().min(0.0)
+    }
+
+    #[test]
+    fn interpolates_the_corners() {
+        let corners = [0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0];
+        for corner in 0..8 {
+            let factor = Vec3::new(
+                (corner & 1) as f32,
+                (corner >> 1 & 1) as f32,
+                (corner >> 2 & 1) as f32,
+            );
+            assert_eq!(trilinear(&corners, factor), corners[corner]);
+        }
+        assert_eq!(trilinear(&corners, Vec3::splat(0.5)), 3.5);
+    }
+
+    #[test]
+    fn relaxes_the_tolerance_away_from_the_surface() {
+        assert_eq!(node_tolerance(0.01, &[2.0, 1.0, 3.0]), 0.1);
+        assert_eq!(node_tolerance(0.01, &[-2.0, -1.0, -3.0]), 0.1);
+        assert_eq!(node_tolerance(0.01, &[0.05, 0.2]), 0.01);
+        // The center is far from the surface, but the corners are on both sides of it
+        assert_eq!(node_tolerance(0.01, &[-1.0, 3.0, 1.0]), 0.01);
+    }
+
+    #[test]
+    fn flattens_breadth_first() {
+        let leaf = |value: f32| Node::Leaf([value; 8]);
+        let root = Node::Branch(Box::new([
+            Node::Branch(Box::new([0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0].map(leaf))),
+            leaf(8.0),
+            leaf(9.0),
+            leaf(10.0),
+            leaf(11.0),
+            leaf(12.0),
+            leaf(13.0),
+            leaf(14.0),
+        ]));
+
+        let (nodes, samples) = flatten(&root);
+        assert_eq!(nodes.len(), 17);
+        assert_eq!(nodes[0], 1);
+        assert_eq!(nodes[1], 9);
+        assert_eq!(nodes[2], ShapeOctree::LEAF);
+        assert_eq!(nodes[9], ShapeOctree::LEAF | 7);
+        assert_eq!(samples.len(), 15 * 8);
+        assert_eq!(samples[7 * 8], 0.0);
+    }
+
+    #[test]
+    fn stays_within_the_tolerance_near_the_surface() {
+        let model = cuboid(CUBOID, Quat::IDENTITY);
+        let tolerance = 0.01;
+        let octree = build(&model, tolerance, 6);
+        assert!(octree.leaf_count() < 8usize.pow(6));
+        assert_eq!(octree.size, 2.0 * CUBOID.x * (1.0 + ROOT_MARGIN));
+
+        // The corners of every node are exact, so the error is only the one of the interpolation
+        let step = octree.size / 64.0;
+        let mut max_error = 0.0f32;
+        for z in -40..=40 {
+            for y in -40..=40 {
+                for x in -40..=40 {
+                    let pnt = Vec3::new(x as f32, y as f32, z as f32) * 0.031;
+                    let exact = cuboid_distance(CUBOID, pnt);
+                    if exact.abs() < step {
+                        max_error = max_error.max((octree.distance(pnt) - exact).abs());
+                    }
+                }
+            }
+        }
+        assert!(max_error < 2.0 * tolerance, "max error: {max_error}");
+    }
+}
@@ -78,7 +78,7 @@ pub fn load(string: String) -> Result<Model, Error> {
                 ));
             } else {
                 if split[0] != "3" {
-                    return Err(Error::msg(format!("non triangular face")));
+                    return Err(Error::msg("non triangular face"));
                 }
                 model.push_triangle(
                     vertices[split[1].parse::<usize>()?],
@@ -0,0 +1,270 @@
+use bevy::prelude::Vec3;
+use std::thread;
+
+use crate::ray_marching::ShapeImage;
+
+use super::Model;
+
+// Texels around the surface the near surface samples are taken from
+const SURFACE_BAND: f32 = 2.0;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DistanceError {
+    pub max_error: f32,
+    pub mean_error: f32,
+    pub rms_error: f32,
+    pub sign_mismatch_rate: f32,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BakeQuality {
+    pub near_surface: DistanceError,
+    pub volume: DistanceError,
+    pub hausdorff_distance: f32,
+}
+
+impl Model {
+    // Compares the shape image to the exact distances of the model at random points, the image
+    // is expected to be centered on the model like the ones of to_shape_image
+    pub fn bake_quality(&self, shape_image: &ShapeImage, samples: usize, seed: u64) -> BakeQuality {
+        if self.triangles.is_empty() {
+            return BakeQuality::default();
+        }
+
+        let offset = (self.min + self.max) / 2.0;
+        let texel_size = shape_image.texel_size();
+        let band = texel_size.max_element() * SURFACE_BAND;
+        let bounds = (shape_image.size - texel_size) / 2.0;
+        let mut random = Random::new(seed);
+
+        let near_surface = (0..samples)
+            .map(|_| {
+                let (pnt, norm) = self.surface_point(&mut random);
+                pnt + norm * band * (random.next_f32() * 2.0 - 1.0)
+            })
+            .collect::<Vec<_>>();
+        let volume = (0..samples)
+            .map(|_| offset + bounds * (random.next_vec3() * 2.0 - 1.0))
+            .collect::<Vec<_>>();
+        let error = |points: &[Vec3]| {
+            distance_error(&parallel_map(points, |pnt| {
+                (self.distance(pnt), shape_image.sample(pnt - offset))
+            }))
+        };
+
+        // Symmetric Hausdorff distance estimated from points on both surfaces
+        let mesh = Model::from_shape_image(shape_image);
+        let hausdorff_distance = if mesh.triangles.is_empty() {
+            f32::INFINITY
+        } else {
+            let model_points = (0..samples)
+                .map(|_| self.surface_point(&mut random).0 - offset)
+                .collect::<Vec<_>>();
+            let mesh_points = (0..samples)
+                .map(|_| mesh.surface_point(&mut random).0 + offset)
+                .collect::<Vec<_>>();
+            parallel_map(&model_points, |pnt| mesh.unsigned_distance(pnt))
+                .into_iter()
+                .chain(parallel_map(&mesh_points, |pnt| {
+                    self.unsigned_distance(pnt)
+                }))
+                .fold(0.0, f32::max)
+        };
+
+        BakeQuality {
+            near_surface: error(&near_surface),
+            volume: error(&volume),
+            hausdorff_distance,
+        }
+    }
+
+    // A random point on the surface, uniformly distributed over its area, and its normal
+    fn surface_point(&self, random: &mut Random) -> (Vec3, Vec3) {
+        let total_area = self.areas.last().copied().unwrap_or(0.0);
+        let area = random.next_f32() * total_area;
+        let index = self
+            .areas
+            .partition_point(|sum| *sum < area)
+            .min(self.triangles.len() - 1);
+        let triangle = &self.triangles[index];
+
+        let (mut u, mut v) = (random.next_f32(), random.next_f32());
+        if u + v > 1.0 {
+            (u, v) = (1.0 - u, 1.0 - v);
+        }
+        (
+            triangle.p1 + triangle.e21 * u - triangle.e13 * v,
+            triangle.norm.normalize_or_zero(),
+        )
+    }
+}
+
+fn distance_error(distances: &[(f32, f32)]) -> DistanceError {
+    if distances.is_empty() {
+        return DistanceError::default();
+    }
+
+    let mut error = DistanceError::default();
+    let mut sign_mismatches = 0;
+    for (exact, baked) in distances {
+        let difference = (exact - baked).abs();
+        error.max_error = error.max_error.max(difference);
+        error.mean_error += difference;
+        error.rms_error += difference * difference;
+        if (*exact < 0.0) != (*baked < 0.0) {
+            sign_mismatches += 1;
+        }
+    }
+
+    let count = distances.len() as f32;
+    error.mean_error /= count;
+    error.rms_error = (error.rms_error / count).sqrt();
+    error.sign_mismatch_rate = sign_mismatches as f32 / count;
+    error
+}
+
+fn parallel_map<T, F>(points: &[Vec3], function: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(Vec3) -> T + Sync,
+{
+    let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+    let chunk_len = points.len().div_ceil(threads).max(1);
+
+    thread::scope(|scope| {
+        let function = &function;
+        points
+            .chunks(chunk_len)
+            .map(|chunk| {
+                scope.spawn(move || chunk.iter().map(|pnt| function(*pnt)).collect::<Vec<_>>())
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+// Splitmix64, so the samples are reproducible for a seed
+struct Random(u64);
+
+impl Random {
+    fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut value = self.0;
+        value = (value ^ (value >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        value = (value ^ (value >> 27)).wrapping_mul(0x94D049BB133111EB);
+        value ^ (value >> 31)
+    }
+
+    fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    fn next_vec3(&mut self) -> Vec3 {
+        Vec3::new(self.next_f32(), self.next_f32(), self.next_f32())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::{
+        prelude::{BVec3, EulerRot, Quat},
+        render::render_resource::Extent3d,
+    };
+
+    const CUBOID: Vec3 = Vec3::new(1.0, 0.83, 0.61);
+
+    // A box around the origin. The faces are fans around a point off their center, the rays of
+    // distance run through the centers of the faces and would hit the edges of split quads
+    fn cuboid(half_size: Vec3, rotation: Quat) -> Model {
+        let corner = |index: usize| {
+            Vec3::select(
+                BVec3::new(index & 1 != 0, index & 2 != 0, index & 4 != 0),
+                half_size,
+                -half_size,
+            )
+        };
+        let faces = [
+            [0, 2, 3, 1],
+            [4, 5, 7, 6],
+            [0, 1, 5, 4],
+            [2, 6, 7, 3],
+            [0, 4, 6, 2],
+            [1, 3, 7, 5],
+        ];
+
+        let mut model = Model::new();
+        for face in faces {
+            let [a, b, c, d] = face.map(|index| rotation * corner(index));
+            let fan = a.lerp(c, 0.5).lerp(b, 0.3).lerp(a, 0.15);
+            for (from, to) in [(a, b), (b, c), (c, d), (d, a)] {
+                model.push_triangle(fan, from, to);
+            }
+        }
+        model
+    }
+
+    #[test]
+    fn sums_up_distance_errors() {
+        let error = distance_error(&[(1.0, 1.0), (0.5, -0.5), (-2.0, -1.0), (0.0, 0.0)]);
+        assert_eq!(error.max_error, 1.0);
+        assert_eq!(error.mean_error, 0.5);
+        assert!((error.rms_error - 0.5f32.sqrt()).abs() < 1e-6);
+        assert_eq!(error.sign_mismatch_rate, 0.25);
+
+        assert_eq!(distance_error(&[]).max_error, 0.0);
+    }
+
+    #[test]
+    fn samples_reproducibly() {
+        let mut first = Random::new(7);
+        let mut second = Random::new(7);
+        for _ in 0..100 {
+            let value = first.next_f32();
+            assert_eq!(value, second.next_f32());
+            assert!((0.0..1.0).contains(&value));
+        }
+    }
+
+    #[test]
+    fn measures_the_quality_of_baked_shapes() {
+        // Rotated, so the texels don't line up with the corners
+        let model = cuboid(CUBOID, Quat::from_euler(EulerRot::XYZ, 0.3, 0.5, 0.2));
+        let resolution = Extent3d {
+            width: 24,
+            height: 24,
+            depth_or_array_layers: 24,
+        };
+        let shape_image = model.to_shape_image(resolution, 4).unwrap();
+        let texel_size = shape_image.texel_size().max_element();
+
+        let quality = model.bake_quality(&shape_image, 500, 0);
+        assert!(quality.near_surface.max_error < texel_size);
+        assert!(quality.near_surface.sign_mismatch_rate < 0.05);
+        assert!(quality.volume.mean_error < texel_size / 4.0);
+        assert!(quality.hausdorff_distance < texel_size);
+        assert_eq!(
+            model.bake_quality(&shape_image, 500, 0).volume.max_error,
+            quality.volume.max_error
+        );
+
+        // Every distance is off by the same amount, which moves the corners the furthest
+        let mut shifted = shape_image.clone();
+        shifted
+            .data
+            .iter_mut()
+            .for_each(|distance| *distance += 0.1);
+        let shifted_quality = model.bake_quality(&shifted, 500, 0);
+        assert!((shifted_quality.volume.mean_error - 0.1).abs() < 0.01);
+        assert!(shifted_quality.hausdorff_distance > 0.1);
+        assert!(
+            shifted_quality.hausdorff_distance < 0.1 * 3.0f32.sqrt() + quality.hausdorff_distance
+        );
+    }
+}
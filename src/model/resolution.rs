@@ -0,0 +1,221 @@
+use bevy::{asset::Error, prelude::Vec3, render::render_resource::Extent3d};
+
+use super::Model;
+
+// The largest trilinear interpolation error of a baked mesh, measured at sharp features
+const MAX_ERROR_PER_TEXEL: f32 = 0.5;
+// Texels across the thinnest walls and gaps, so their inside and outside don't blend together
+const THIN_FEATURE_TEXELS: f32 = 2.0;
+// Percentile of the wall and gap thicknesses taken as the thinnest feature
+const THIN_FEATURE_PERCENTILE: f32 = 0.1;
+const THICKNESS_SAMPLES: usize = 256;
+// One texel for the interpolation at the surface and one for the normals
+const MIN_PADDING: u32 = 2;
+const MAX_TEXTURE_DIMENSION: u32 = 2048;
+
+#[derive(Clone, Copy, Debug)]
+pub enum DetailTarget {
+    // The maximum distance error in model units
+    ErrorBudget(f32),
+    // Texels per median edge length
+    FeatureSize(f32),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct AutoResolution {
+    pub target: DetailTarget,
+    // Bytes of the 32 bit float texture
+    pub memory_budget: usize,
+    // Distance from the surface the image has to be accurate to, which pads the image for the
+    // largest blend k the shape is expected to be blended with
+    pub distance_range: f32,
+}
+
+impl Default for AutoResolution {
+    fn default() -> Self {
+        Self {
+            target: DetailTarget::FeatureSize(1.0),
+            memory_budget: 64 * 1024 * 1024,
+            distance_range: 0.4,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ResolutionChoice {
+    pub resolution: Extent3d,
+    pub padding: u32,
+    pub voxel_size: f32,
+    pub median_edge_length: f32,
+    pub thin_feature_size: Option<f32>,
+    pub memory: usize,
+    // Whether the memory budget or the texture size limit made the voxels larger than the target
+    pub limited: bool,
+}
+
+impl Model {
+    pub fn choose_resolution(&self, settings: &AutoResolution) -> Result<ResolutionChoice, Error> {
+        // The voxels can only grow until the model fits into a single one
+        let min_memory = (2 * MIN_PADDING as usize + 2).pow(3) * std::mem::size_of::<f32>();
+        if settings.memory_budget < min_memory {
+            return Err(Error::msg(format!(
+                "the memory budget of {} bytes is below the minimum of {min_memory} bytes",
+                settings.memory_budget
+            )));
+        }
+
+        let median_edge_length = self.median_edge_length();
+        let thin_feature_size = self.thin_feature_size();
+
+        let target_voxel_size = match settings.target {
+            DetailTarget::ErrorBudget(error) => error / MAX_ERROR_PER_TEXEL,
+            DetailTarget::FeatureSize(texels) => median_edge_length / texels,
+        };
+        let mut voxel_size = thin_feature_size
+            .map_or(target_voxel_size, |size| {
+                target_voxel_size.min(size / THIN_FEATURE_TEXELS)
+            })
+            .max(f32::EPSILON);
+
+        // Grow the voxels until the texture fits, the padding shrinks along with them
+        let size = self.max - self.min;
+        let mut limited = false;
+        loop {
+            if !voxel_size.is_finite() {
+                return Err(Error::msg("no resolution fits the memory budget"));
+            }
+            let padding = ((settings.distance_range / voxel_size).ceil() as u32).max(MIN_PADDING);
+            let texels = |size: f32| (size / voxel_size).ceil() as u32 + 2 * padding + 1;
+            let resolution = Extent3d {
+                width: texels(size.x),
+                height: texels(size.y),
+                depth_or_array_layers: texels(size.z),
+            };
+            let memory = resolution.width as usize
+                * resolution.height as usize
+                * resolution.depth_or_array_layers as usize
+                * std::mem::size_of::<f32>();
+            let max_dimension = resolution
+                .width
+                .max(resolution.height)
+                .max(resolution.depth_or_array_layers);
+
+            if memory <= settings.memory_budget && max_dimension <= MAX_TEXTURE_DIMENSION {
+                return Ok(ResolutionChoice {
+                    resolution,
+                    padding,
+                    voxel_size,
+                    median_edge_length,
+                    thin_feature_size,
+                    memory,
+                    limited,
+                });
+            }
+
+            let memory_factor = (memory as f32 / settings.memory_budget.max(1) as f32).cbrt();
+            let dimension_factor = max_dimension as f32 / MAX_TEXTURE_DIMENSION as f32;
+            voxel_size *= memory_factor.max(dimension_factor).max(1.01);
+            limited = true;
+        }
+    }
+
+    fn median_edge_length(&self) -> f32 {
+        let mut lengths = self
+            .triangles
+            .iter()
+            .flat_map(|triangle| {
+                [
+                    triangle.e21.length(),
+                    triangle.e32.length(),
+                    triangle.e13.length(),
+                ]
+            })
+            .collect::<Vec<_>>();
+        if lengths.is_empty() {
+            return (self.max - self.min).max_element();
+        }
+        let middle = lengths.len() / 2;
+        *lengths.select_nth_unstable_by(middle, f32::total_cmp).1
+    }
+
+    // Thickness of the thin walls and gaps, from rays cast along the normals of sampled triangles
+    fn thin_feature_size(&self) -> Option<f32> {
+        let step = (self.triangles.len() / THICKNESS_SAMPLES).max(1);
+        let mut thicknesses = self
+            .triangles
+            .iter()
+            .step_by(step)
+            .filter_map(|triangle| {
+                let norm = triangle.norm.normalize_or_zero();
+                if norm == Vec3::ZERO {
+                    return None;
+                }
+                let min_distance = triangle.radius * 1e-3;
+                [norm, -norm]
+                    .iter()
+                    .flat_map(|dir| {
+                        self.triangles.iter().filter_map(|other| {
+                            other
+                                .intersection(triangle.center, *dir)
+                                .filter(|distance| *distance > min_distance)
+                        })
+                    })
+                    .min_by(f32::total_cmp)
+            })
+            .collect::<Vec<_>>();
+        if thicknesses.is_empty() {
+            return None;
+        }
+        let index = (thicknesses.len() as f32 * THIN_FEATURE_PERCENTILE) as usize;
+        Some(*thicknesses.select_nth_unstable_by(index, f32::total_cmp).1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tetrahedron() -> Model {
+        let [a, b, c, d] = [Vec3::ZERO, Vec3::X, Vec3::Y, Vec3::Z];
+        let mut model = Model::new();
+        model.push_triangle(a, c, b);
+        model.push_triangle(a, b, d);
+        model.push_triangle(a, d, c);
+        model.push_triangle(b, c, d);
+        model
+    }
+
+    #[test]
+    fn fits_the_memory_budget() {
+        let model = tetrahedron();
+        let settings = AutoResolution {
+            target: DetailTarget::ErrorBudget(0.001),
+            memory_budget: 1024 * 1024,
+            ..Default::default()
+        };
+        let choice = model.choose_resolution(&settings).unwrap();
+        assert!(choice.limited);
+        assert!(choice.memory <= settings.memory_budget);
+        assert!(choice.padding >= MIN_PADDING);
+
+        let choice = model
+            .choose_resolution(&AutoResolution {
+                memory_budget: 6 * 6 * 6 * 4,
+                ..settings
+            })
+            .unwrap();
+        assert_eq!(choice.resolution.width, 2 * MIN_PADDING + 2);
+    }
+
+    #[test]
+    fn rejects_budgets_below_a_single_voxel() {
+        let model = tetrahedron();
+        for memory_budget in [0, 6 * 6 * 6 * 4 - 1] {
+            let settings = AutoResolution {
+                memory_budget,
+                ..Default::default()
+            };
+            assert!(model.choose_resolution(&settings).is_err());
+        }
+    }
+}
@@ -0,0 +1,1130 @@
+use super::{
+    heightfield::{
+        height_scale, heightfield_distance, heightfield_slope, heightfield_uv, sample_height,
+    },
+    metaballs::{metaballs_distance, metaballs_half_size, MetaballPoint, MetaballPoints},
+    shape::{get_inverse_transform, tube_taper_scale, Blend, Domain, Modifiers, MAX_TEXTURES},
+    Material, Operation, Primitive, Profile, Shape, ShapeImage, ShapeOctree, ShapeType,
+};
+use bevy::{
+    asset::Error,
+    ecs::system::Command,
+    hierarchy::{despawn_with_children_recursive, BuildWorldChildren},
+    prelude::{
+        warn, App, Assets, BVec3, Children, Commands, Component, Entity, GlobalTransform, Handle,
+        Image, Mat4, Name, Plugin, Query, Transform, UVec3, Vec2, Vec3, World,
+    },
+    render::render_resource::Extent3d,
+    tasks::{AsyncComputeTaskPool, Task},
+    utils::HashMap,
+};
+use futures_lite::future;
+
+const FAR: f32 = 64.0;
+
+pub struct BakingPlugin;
+
+impl Plugin for BakingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(finish_bakes);
+    }
+}
+
+// Bakes the subtree of a shape into an image in a task and replaces the subtree with it once it's
+// done
+pub struct BakeShape {
+    pub entity: Entity,
+    pub resolution: u32,
+    pub padding: u32,
+}
+
+// Restores the subtree of a shape baked with BakeShape
+pub struct RestoreShape {
+    pub entity: Entity,
+}
+
+#[derive(Component)]
+pub struct BakedShape {
+    shape_type: ShapeType,
+    offset: Vec3,
+    children: Vec<ShapeTree>,
+}
+
+type BakeResult = Result<(ShapeImage, Vec3), Error>;
+
+// The tree is baked as it was when the bake started
+#[derive(Component)]
+struct BakeTask(Task<(ShapeTree, BakeResult)>);
+
+struct FinishBake {
+    entity: Entity,
+    tree: ShapeTree,
+    image: ShapeImage,
+    offset: Vec3,
+}
+
+// The assets the primitives of a tree refer to, copied out of the world so that the tree can be
+// baked in a task
+#[derive(Default)]
+pub struct ShapeAssets {
+    pub images: HashMap<Handle<ShapeImage>, ShapeImage>,
+    pub octrees: HashMap<Handle<ShapeOctree>, ShapeOctree>,
+    pub textures: HashMap<Handle<Image>, Image>,
+}
+
+impl ShapeAssets {
+    pub fn from_world(world: &World, tree: &ShapeTree) -> Self {
+        let mut assets = Self::default();
+        assets.add_tree(world, tree);
+        assets
+    }
+
+    fn add_tree(&mut self, world: &World, tree: &ShapeTree) {
+        match &tree.shape.shape_type {
+            ShapeType::Primitive(Primitive::Image(handle), _) => {
+                if let Some(image) = world.resource::<Assets<ShapeImage>>().get(handle) {
+                    self.images.insert(handle.clone(), image.clone());
+                }
+            }
+            ShapeType::Primitive(Primitive::Octree(handle), _) => {
+                if let Some(octree) = world.resource::<Assets<ShapeOctree>>().get(handle) {
+                    self.octrees.insert(handle.clone(), octree.clone());
+                }
+            }
+            ShapeType::Primitive(Primitive::Heightfield(handle), _) => {
+                if let Some(texture) = world.resource::<Assets<Image>>().get(handle) {
+                    self.textures.insert(handle.clone(), texture.clone());
+                }
+            }
+            _ => {}
+        }
+        for child in tree.children.iter() {
+            self.add_tree(world, child);
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ShapeTree {
+    pub name: Option<Name>,
+    pub shape: Shape,
+    pub transform: Transform,
+    pub metaball_points: Option<MetaballPoints>,
+    pub children: Vec<ShapeTree>,
+}
+
+impl ShapeTree {
+    pub fn from_world(world: &World, entity: Entity) -> Option<Self> {
+        Some(Self {
+            name: world.get::<Name>(entity).cloned(),
+            shape: world.get::<Shape>(entity)?.clone(),
+            transform: world.get::<Transform>(entity).cloned().unwrap_or_default(),
+            metaball_points: world.get::<MetaballPoints>(entity).cloned(),
+            children: world
+                .get::<Children>(entity)
+                .map(|children| {
+                    children
+                        .iter()
+                        .filter_map(|child| Self::from_world(world, *child))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        })
+    }
+
+    pub fn spawn(&self, world: &mut World) -> Entity {
+        let mut entity = world.spawn((
+            self.shape.clone(),
+            self.transform,
+            GlobalTransform::default(),
+        ));
+        if let Some(name) = &self.name {
+            entity.insert(name.clone());
+        }
+        if let Some(metaball_points) = &self.metaball_points {
+            entity.insert(metaball_points.clone());
+        }
+        let entity = entity.id();
+
+        for child in self.children.iter() {
+            let child = child.spawn(world);
+            world.entity_mut(entity).add_child(child);
+        }
+        entity
+    }
+
+    // Bakes the tree in the local space of its root, the image is centered around the returned offset
+    pub fn bake(
+        &self,
+        assets: &ShapeAssets,
+        resolution: u32,
+        padding: u32,
+    ) -> Result<(ShapeImage, Vec3), Error> {
+        let group = BakeGroup::new(assets, self, &GlobalTransform::IDENTITY, true);
+        let (min, max) = group
+            .bounds(assets)?
+            .ok_or_else(|| Error::msg("the shape has no bounded primitives"))?;
+
+        let size = max - min;
+        let texel_size =
+            size.max_element() / (resolution.max(2 * padding + 2) - 2 * padding - 1) as f32;
+        let texels = |size: f32| (size / texel_size).ceil() as u32 + 2 * padding + 1;
+        let resolution = Extent3d {
+            width: texels(size.x),
+            height: texels(size.y),
+            depth_or_array_layers: texels(size.z),
+        };
+
+        let size = texel_size
+            * Vec3::new(
+                resolution.width as f32,
+                resolution.height as f32,
+                resolution.depth_or_array_layers as f32,
+            );
+        let offset = (min + max) / 2.0;
+
+        Ok((
+            ShapeImage::from_fn_parallel(size, resolution, |pnt| {
+                group.distance(assets, pnt + offset)
+            })?,
+            offset,
+        ))
+    }
+
+    fn material(&self) -> Option<Material> {
+        match &self.shape.shape_type {
+            ShapeType::Primitive(_, material) => Some(material.clone()),
+            ShapeType::Compound(_) => self.children.iter().find_map(|child| child.material()),
+        }
+    }
+
+    fn has_one_material(&self) -> bool {
+        fn add_materials<'a>(tree: &'a ShapeTree, materials: &mut Vec<&'a Material>) {
+            if let ShapeType::Primitive(_, material) = &tree.shape.shape_type {
+                if !materials.contains(&material) {
+                    materials.push(material);
+                }
+            }
+            for child in tree.children.iter() {
+                add_materials(child, materials);
+            }
+        }
+
+        let mut materials = Vec::new();
+        add_materials(self, &mut materials);
+        materials.len() <= 1
+    }
+}
+
+impl Command for BakeShape {
+    fn write(self, world: &mut World) {
+        let Some(tree) = ShapeTree::from_world(world, self.entity) else {
+            warn!("Only shapes can be baked");
+            return;
+        };
+        if tree.children.is_empty() {
+            warn!("Only shapes with children can be baked");
+            return;
+        }
+        if world.get::<BakeTask>(self.entity).is_some() {
+            warn!("The shape is already being baked");
+            return;
+        }
+        if !has_free_texture(world, self.entity) {
+            warn!("Too many different images are in the scene to bake the shape");
+            return;
+        }
+        if !tree.has_one_material() {
+            warn!("The baked shape only keeps the first material of its subtree");
+        }
+
+        let assets = ShapeAssets::from_world(world, &tree);
+        let (resolution, padding) = (self.resolution, self.padding);
+        let task = AsyncComputeTaskPool::get().spawn(async move {
+            let result = tree.bake(&assets, resolution, padding);
+            (tree, result)
+        });
+        world.entity_mut(self.entity).insert(BakeTask(task));
+    }
+}
+
+// The image of the baked shape needs a texture that isn't used by the shapes outside of the subtree
+fn has_free_texture(world: &mut World, entity: Entity) -> bool {
+    let mut subtree = vec![entity];
+    let mut index = 0;
+    while index < subtree.len() {
+        if let Some(children) = world.get::<Children>(subtree[index]) {
+            subtree.extend(children.iter());
+        }
+        index += 1;
+    }
+
+    let mut handles = Vec::new();
+    for (entity, shape) in world.query::<(Entity, &Shape)>().iter(world) {
+        if let ShapeType::Primitive(Primitive::Image(handle), _) = &shape.shape_type {
+            if !subtree.contains(&entity) && !handles.contains(&handle) {
+                handles.push(handle);
+            }
+        }
+    }
+    handles.len() < MAX_TEXTURES as usize
+}
+
+fn finish_bakes(mut commands: Commands, mut tasks: Query<(Entity, &mut BakeTask)>) {
+    for (entity, mut task) in tasks.iter_mut() {
+        let Some((tree, result)) = future::block_on(future::poll_once(&mut task.0)) else {
+            continue;
+        };
+        commands.entity(entity).remove::<BakeTask>();
+        match result {
+            Ok((image, offset)) => commands.add(FinishBake {
+                entity,
+                tree,
+                image,
+                offset,
+            }),
+            Err(error) => warn!("Failed to bake shape: {error}"),
+        }
+    }
+}
+
+impl Command for FinishBake {
+    fn write(self, world: &mut World) {
+        // The shape may have been removed while it was baked
+        if world.get_entity(self.entity).is_none() {
+            return;
+        }
+        let image = world.resource_mut::<Assets<ShapeImage>>().add(self.image);
+
+        for child in world
+            .get::<Children>(self.entity)
+            .map(|children| children.to_vec())
+            .unwrap_or_default()
+        {
+            despawn_with_children_recursive(world, child);
+        }
+
+        let mut entity = world.entity_mut(self.entity);
+        entity.remove::<Children>();
+        let material = self.tree.material().unwrap_or_default();
+        if let Some(mut shape) = entity.get_mut::<Shape>() {
+            shape.shape_type = ShapeType::Primitive(Primitive::Image(image), material);
+        }
+        if let Some(mut transform) = entity.get_mut::<Transform>() {
+            let translation = transform.transform_point(self.offset);
+            transform.translation = translation;
+        }
+        entity.insert(BakedShape {
+            shape_type: self.tree.shape.shape_type,
+            offset: self.offset,
+            children: self.tree.children,
+        });
+    }
+}
+
+impl Command for RestoreShape {
+    fn write(self, world: &mut World) {
+        let Some(baked_shape) = world.entity_mut(self.entity).take::<BakedShape>() else {
+            warn!("Only baked shapes can be restored");
+            return;
+        };
+
+        let mut entity = world.entity_mut(self.entity);
+        if let Some(mut shape) = entity.get_mut::<Shape>() {
+            shape.shape_type = baked_shape.shape_type;
+        }
+        if let Some(mut transform) = entity.get_mut::<Transform>() {
+            let translation = transform.transform_point(-baked_shape.offset);
+            transform.translation = translation;
+        }
+
+        for child in baked_shape.children.iter() {
+            let child = child.spawn(world);
+            world.entity_mut(self.entity).add_child(child);
+        }
+    }
+}
+
+struct BakeGroup {
+    operation: Operation,
+    negative: bool,
+    modifiers: Modifiers,
+    domain: Domain,
+    blend: Blend,
+    transform: GlobalTransform,
+    inv_transform: Mat4,
+    scale: f32,
+    primitives: Vec<BakePrimitive>,
+    children: Vec<BakeGroup>,
+}
+
+struct BakePrimitive {
+    primitive: Primitive,
+    modifiers: Modifiers,
+    transform: GlobalTransform,
+    inv_transform: Mat4,
+    scale: f32,
+    // The slopes of heightfields are measured once, instead of for every distance
+    height_scale: f32,
+    metaball_points: Vec<MetaballPoint>,
+}
+
+impl BakeGroup {
+    // Mirrors create_group, so the baked distances match the generated shader
+    fn new(
+        assets: &ShapeAssets,
+        tree: &ShapeTree,
+        transform: &GlobalTransform,
+        root: bool,
+    ) -> Self {
+        let mut primitives = Vec::new();
+        let operation = match &tree.shape.shape_type {
+            ShapeType::Primitive(primitive, _) => {
+                primitives.push(BakePrimitive::new(
+                    assets,
+                    primitive,
+                    tree.metaball_points.as_ref(),
+                    &Modifiers::default(),
+                    transform,
+                    false,
+                ));
+                Operation::Union
+            }
+            ShapeType::Compound(operation) => *operation,
+        };
+
+        // Like in create_group, a morph ignores its children after the second one
+        let child_count = match operation {
+            Operation::Morph => 2,
+            _ => tree.children.len(),
+        };
+        let mut children = Vec::new();
+        for (index, child) in tree.children.iter().take(child_count).enumerate() {
+            let child_transform = transform.mul_transform(child.transform);
+            let negative = child.shape.negative != (operation.subtracts() && index > 0);
+            if child.children.is_empty() && !(operation.has_base() && index == 0) {
+                if let ShapeType::Primitive(primitive, _) = &child.shape.shape_type {
+                    primitives.push(BakePrimitive::new(
+                        assets,
+                        primitive,
+                        child.metaball_points.as_ref(),
+                        &child.shape.modifiers,
+                        &child_transform,
+                        negative,
+                    ));
+                }
+            } else {
+                children.push(Self {
+                    negative,
+                    ..Self::new(assets, child, &child_transform, false)
+                });
+            }
+        }
+
+        // The modifiers of the root stay on the baked shape, while its domain only applies to its
+        // children and gets baked with them
+        let (inv_transform, scale) = get_inverse_transform(transform, false);
+        Self {
+            operation,
+            negative: !root && tree.shape.negative,
+            modifiers: if root {
+                Modifiers::default()
+            } else {
+                tree.shape.modifiers
+            },
+            domain: tree.shape.domain,
+            blend: tree.shape.blend,
+            transform: *transform,
+            inv_transform,
+            scale,
+            primitives,
+            children,
+        }
+    }
+
+    fn distance(&self, assets: &ShapeAssets, pnt: Vec3) -> f32 {
+        let modified = !self.modifiers.is_empty();
+        let local = self.inv_transform.transform_point3(pnt);
+        let pnt = if modified {
+            let elongated = self.modifiers.elongate(self.modifiers.deform(local));
+            self.transform.transform_point(elongated)
+        } else {
+            pnt
+        };
+        let pnt = if self.domain.is_empty() {
+            pnt
+        } else {
+            let folded = self.domain.fold(self.inv_transform.transform_point3(pnt));
+            self.transform.transform_point(folded)
+        };
+
+        let mut dist = match self.operation {
+            Operation::Union
+            | Operation::SmoothUnion
+            | Operation::ChamferUnion
+            | Operation::StairsUnion
+            | Operation::ColumnsUnion => FAR,
+            _ => -FAR,
+        };
+        let child_distance = |child: &BakeGroup| {
+            let child_dist = child.distance(assets, pnt);
+            if child.negative {
+                -child_dist
+            } else {
+                child_dist
+            }
+        };
+        let mut children = self.children.iter();
+        if self.operation.has_base() {
+            if let Some(base) = children.next() {
+                dist = apply_operation(
+                    Operation::Intersection,
+                    &self.blend,
+                    dist,
+                    child_distance(base),
+                );
+            }
+        }
+        for primitive in self.primitives.iter() {
+            dist = apply_operation(
+                self.operation,
+                &self.blend,
+                dist,
+                primitive.distance(assets, pnt),
+            );
+        }
+        for child in children {
+            dist = apply_operation(self.operation, &self.blend, dist, child_distance(child));
+        }
+        if modified {
+            dist = self.modifiers.modify(dist / self.scale, local) * self.scale;
+        }
+        dist
+    }
+
+    fn bounds(&self, assets: &ShapeAssets) -> Result<Option<(Vec3, Vec3)>, Error> {
+        // Only the base of an operation with one is bounded, apart from a morph, which is inside
+        // of both of its children
+        let base = self.operation.has_base() && self.operation != Operation::Morph;
+        if self.operation == Operation::Morph && self.primitives.len() + self.children.len() < 2 {
+            return Err(Error::msg("a morph needs two children"));
+        }
+        let mut bounds = None;
+        for primitive in self.primitives.iter() {
+            // The custom shapes can't be baked even when they're cut out
+            if let Primitive::Custom { .. } = primitive.primitive {
+                return Err(Error::msg("custom shapes can't be baked"));
+            }
+            if primitive.scale > 0.0 && !base {
+                bounds = union_bounds(bounds, primitive.bounds(assets)?);
+            }
+        }
+        for (index, child) in self.children.iter().enumerate() {
+            if !(child.negative || (base && index > 0)) {
+                if let Some(child_bounds) = child.bounds(assets)? {
+                    bounds = union_bounds(bounds, child_bounds);
+                }
+            }
+        }
+
+        // Some blends reach outside of the shapes, a smooth union is lowered the most where the
+        // distances of the shapes are equal
+        let growth = match self.operation {
+            Operation::SmoothUnion => self.blend.lowering(0.0),
+            Operation::ChamferUnion | Operation::StairsUnion | Operation::ColumnsUnion => {
+                self.blend.k.max(0.0)
+            }
+            Operation::Tongue => self.blend.depth.max(0.0),
+            _ => 0.0,
+        };
+        let bounds = bounds.map(|(min, max)| (min - growth, max + growth));
+        let bounds = match bounds {
+            Some(bounds) if !self.domain.is_empty() => Some(self.domain_bounds(bounds)?),
+            bounds => bounds,
+        };
+
+        // The deformations need the bounds in the local space of the group
+        if self.modifiers.deforms() {
+            return bounds
+                .map(|bounds| {
+                    let (min, max) = transform_bounds(self.inv_transform, bounds);
+                    let growth = self.modifiers.elongation + self.modifiers.padding();
+                    let bounds = deform_bounds(&self.modifiers, (min - growth, max + growth))?;
+                    Ok(transform_bounds(self.transform.compute_matrix(), bounds))
+                })
+                .transpose();
+        }
+
+        // Growing the bounds by the elongation along the axes of the group and the padding
+        let matrix = self.transform.affine().matrix3;
+        let elongation = Vec3::from(
+            matrix.x_axis.abs() * self.modifiers.elongation.x
+                + matrix.y_axis.abs() * self.modifiers.elongation.y
+                + matrix.z_axis.abs() * self.modifiers.elongation.z,
+        );
+        let growth = elongation + self.modifiers.padding() * self.scale;
+        Ok(bounds.map(|(min, max)| (min - growth, max + growth)))
+    }
+
+    // Grows the bounds by the copies the domain makes, in the reverse order of fold
+    fn domain_bounds(&self, bounds: (Vec3, Vec3)) -> Result<(Vec3, Vec3), Error> {
+        let domain = &self.domain;
+        let repeated = domain.spacing.cmpgt(Vec3::ZERO);
+        if (repeated & domain.count.cmpeq(UVec3::ZERO)).any() {
+            return Err(Error::msg("infinitely repeated shapes can't be baked"));
+        }
+
+        let (mut min, mut max) = transform_bounds(self.inv_transform, bounds);
+        let copies = domain.spacing * (domain.count.as_vec3() - 1.0) / 2.0;
+        let copies = Vec3::select(repeated, copies, Vec3::ZERO);
+        (min, max) = (min - copies, max + copies);
+
+        if domain.polar_count > 0 {
+            let radius = min.truncate().abs().max(max.truncate().abs()).length();
+            min = Vec3::new(-radius, -radius, min.z);
+            max = Vec3::new(radius, radius, max.z);
+        }
+
+        let extent = min.abs().max(max.abs());
+        min = Vec3::select(domain.mirror, -extent, min);
+        max = Vec3::select(domain.mirror, extent, max);
+        Ok(transform_bounds(self.transform.compute_matrix(), (min, max)))
+    }
+}
+
+impl BakePrimitive {
+    fn new(
+        assets: &ShapeAssets,
+        primitive: &Primitive,
+        metaball_points: Option<&MetaballPoints>,
+        modifiers: &Modifiers,
+        transform: &GlobalTransform,
+        negative: bool,
+    ) -> Self {
+        let (inv_transform, scale) = get_inverse_transform(transform, negative);
+        let height_scale = match primitive {
+            Primitive::Heightfield(handle) => assets
+                .textures
+                .get(handle)
+                .and_then(heightfield_slope)
+                .map_or(0.0, |slope| height_scale(transform, slope, scale)),
+            _ => 0.0,
+        };
+        Self {
+            primitive: primitive.clone(),
+            modifiers: *modifiers,
+            transform: *transform,
+            inv_transform,
+            scale,
+            height_scale,
+            metaball_points: metaball_points.map_or_else(Vec::new, |points| points.0.clone()),
+        }
+    }
+
+    fn distance(&self, assets: &ShapeAssets, pnt: Vec3) -> f32 {
+        let local = self.inv_transform.transform_point3(pnt);
+        let pnt = self.modifiers.elongate(self.modifiers.deform(local));
+        let dist = match &self.primitive {
+            Primitive::Plane => pnt.z,
+            Primitive::Sphere { radius } => pnt.length() - radius,
+            Primitive::Cube { size } => {
+                let q = pnt.abs() - *size / 2.0;
+                q.max(Vec3::ZERO).length() + q.max_element().min(0.0)
+            }
+            Primitive::Torus {
+                major_radius,
+                minor_radius,
+            } => Vec2::new(pnt.truncate().length() - major_radius, pnt.z).length() - minor_radius,
+            Primitive::Cylinder { radius, height } => {
+                let q = Vec2::new(pnt.truncate().length(), pnt.z).abs()
+                    - Vec2::new(*radius, height / 2.0);
+                q.max(Vec2::ZERO).length() + q.max_element().min(0.0)
+            }
+            Primitive::Capsule { radius, height } => {
+                let half_height = height / 2.0;
+                (pnt - Vec3::Z * pnt.z.clamp(-half_height, half_height)).length() - radius
+            }
+            Primitive::Cone { radius, height } => {
+                let half_height = height / 2.0;
+                let q = Vec2::new(pnt.truncate().length(), pnt.z);
+                let base = Vec2::new(
+                    q.x - q.x.min(if q.y < 0.0 { *radius } else { 0.0 }),
+                    q.y.abs() - half_height,
+                );
+                let side = Vec2::new(-radius, *height);
+                let apex = q - Vec2::new(0.0, half_height);
+                let slant = apex + side * ((-apex).dot(side) / side.dot(side)).clamp(0.0, 1.0);
+                let distance = base.length_squared().min(slant.length_squared()).sqrt();
+                if slant.x < 0.0 && base.y < 0.0 {
+                    -distance
+                } else {
+                    distance
+                }
+            }
+            Primitive::Image(handle) => assets
+                .images
+                .get(handle)
+                .map_or(FAR, |image| image.distance(pnt)),
+            Primitive::Octree(handle) => assets
+                .octrees
+                .get(handle)
+                .map_or(FAR, |octree| octree.distance(pnt)),
+            Primitive::Extrusion { profile, height } => {
+                let w = Vec2::new(
+                    profile_distance(profile, pnt.truncate()),
+                    pnt.z.abs() - height / 2.0,
+                );
+                w.max_element().min(0.0) + w.max(Vec2::ZERO).length()
+            }
+            Primitive::Revolution { profile, offset } => profile_distance(
+                profile,
+                Vec2::new(pnt.truncate().length() - offset, pnt.z),
+            ),
+            Primitive::Tube {
+                points,
+                start_radius,
+                end_radius,
+            } => {
+                let segment_count = points.len().saturating_sub(1) / 2;
+                let mut dist = FAR;
+                for i in 0..segment_count {
+                    let (curve_dist, t) =
+                        bezier_distance(pnt, points[2 * i], points[2 * i + 1], points[2 * i + 2]);
+                    let progress = (i as f32 + t) / segment_count as f32;
+                    let radius = start_radius + (end_radius - start_radius) * progress;
+                    dist = dist.min(curve_dist - radius);
+                }
+                dist * tube_taper_scale(points, *start_radius, *end_radius)
+            }
+            Primitive::Heightfield(handle) => assets
+                .textures
+                .get(handle)
+                .and_then(|image| sample_height(image, heightfield_uv(pnt)))
+                .map_or(FAR, |height| {
+                    heightfield_distance(pnt, height, self.height_scale)
+                }),
+            // The bounds of the group fail before the source, which only runs in the shader, is
+            // needed
+            Primitive::Custom { .. } => unreachable!("custom shapes can't be baked"),
+            Primitive::Metaballs { threshold } => {
+                metaballs_distance(&self.metaball_points, *threshold, pnt)
+            }
+        };
+        self.modifiers.modify(dist, local) * self.scale
+    }
+
+    fn bounds(&self, assets: &ShapeAssets) -> Result<(Vec3, Vec3), Error> {
+        let half_size = match &self.primitive {
+            Primitive::Plane => return Err(Error::msg("planes can't be baked")),
+            Primitive::Custom { .. } => unreachable!("custom shapes can't be baked"),
+            Primitive::Sphere { radius } => Vec3::splat(*radius),
+            Primitive::Cube { size } => *size / 2.0,
+            Primitive::Torus {
+                major_radius,
+                minor_radius,
+            } => Vec3::new(
+                major_radius + minor_radius,
+                major_radius + minor_radius,
+                *minor_radius,
+            ),
+            Primitive::Cylinder { radius, height } | Primitive::Cone { radius, height } => {
+                Vec3::new(*radius, *radius, height / 2.0)
+            }
+            Primitive::Capsule { radius, height } => {
+                Vec3::new(*radius, *radius, height / 2.0 + radius)
+            }
+            Primitive::Image(handle) => {
+                asset_half_size(assets.images.get(handle).map(|image| image.size))?
+            }
+            Primitive::Octree(handle) => asset_half_size(
+                assets
+                    .octrees
+                    .get(handle)
+                    .map(|octree| Vec3::splat(octree.size)),
+            )?,
+            Primitive::Extrusion { profile, height } => profile.half_size().extend(height / 2.0),
+            Primitive::Revolution { profile, offset } => {
+                let half_size = profile.half_size();
+                let radius = offset.abs() + half_size.x;
+                Vec3::new(radius, radius, half_size.y)
+            }
+            // The curves stay within the points
+            Primitive::Tube {
+                points,
+                start_radius,
+                end_radius,
+            } => {
+                points
+                    .iter()
+                    .fold(Vec3::ZERO, |half_size, point| half_size.max(point.abs()))
+                    + start_radius.max(*end_radius)
+            }
+            // The box of the heights is centered above the origin
+            Primitive::Heightfield(handle) => asset_half_size(
+                assets
+                    .textures
+                    .get(handle)
+                    .map(|_| Vec3::new(1.0, 1.0, 2.0)),
+            )?,
+            Primitive::Metaballs { threshold } => {
+                metaballs_half_size(&self.metaball_points, *threshold)
+            }
+        };
+
+        let half_size = half_size + self.modifiers.elongation + self.modifiers.padding();
+        let bounds = deform_bounds(&self.modifiers, (-half_size, half_size))?;
+        Ok(transform_bounds(self.transform.compute_matrix(), bounds))
+    }
+}
+
+// Same as sdf_profile in the shader
+fn profile_distance(profile: &Profile, pnt: Vec2) -> f32 {
+    match profile {
+        Profile::Circle { radius } => pnt.length() - radius,
+        Profile::Rectangle { size } => {
+            let q = pnt.abs() - *size / 2.0;
+            q.max(Vec2::ZERO).length() + q.max_element().min(0.0)
+        }
+        Profile::Polygon(points) => {
+            if points.is_empty() {
+                return FAR;
+            }
+            let mut distance = FAR * FAR;
+            let mut inside = false;
+            for (i, a) in points.iter().enumerate() {
+                let b = points[(i + points.len() - 1) % points.len()];
+                let edge = b - *a;
+                let w = pnt - *a;
+                let q = w - edge * (w.dot(edge) / edge.dot(edge).max(1e-12)).clamp(0.0, 1.0);
+                distance = distance.min(q.dot(q));
+                let crossing = [pnt.y >= a.y, pnt.y < b.y, edge.x * w.y > edge.y * w.x];
+                if crossing.iter().all(|c| *c) || !crossing.iter().any(|c| *c) {
+                    inside = !inside;
+                }
+            }
+            if inside {
+                -distance.sqrt()
+            } else {
+                distance.sqrt()
+            }
+        }
+        Profile::Arc {
+            radius,
+            angle,
+            thickness,
+        } => {
+            let aperture = Vec2::new((angle / 2.0).sin(), (angle / 2.0).cos());
+            let pnt = Vec2::new(pnt.x.abs(), pnt.y);
+            let distance = if aperture.y * pnt.x > aperture.x * pnt.y {
+                (pnt - aperture * *radius).length()
+            } else {
+                (pnt.length() - radius).abs()
+            };
+            distance - thickness / 2.0
+        }
+    }
+}
+
+// Same as sdf_bezier in the shader
+fn bezier_distance(pnt: Vec3, start: Vec3, control: Vec3, end: Vec3) -> (f32, f32) {
+    let a = control - start;
+    let b = start - 2.0 * control + end;
+    let c = a * 2.0;
+    let d = start - pnt;
+
+    if b.dot(b) < 1e-8 {
+        let line = end - start;
+        let t = ((-d).dot(line) / line.dot(line).max(1e-12)).clamp(0.0, 1.0);
+        return ((d + line * t).length(), t);
+    }
+
+    let kk = 1.0 / b.dot(b);
+    let kx = kk * a.dot(b);
+    let ky = kk * (2.0 * a.dot(a) + d.dot(b)) / 3.0;
+    let kz = kk * d.dot(a);
+    let p = ky - kx * kx;
+    let q = kx * (2.0 * kx * kx - 3.0 * ky) + kz;
+    let h = q * q + 4.0 * p * p * p;
+    let curve_distance = |t: f32| (d + (c + b * t) * t).length();
+
+    if h >= 0.0 {
+        let x = (Vec2::new(h.sqrt(), -h.sqrt()) - q) / 2.0;
+        let t = (x.x.cbrt() + x.y.cbrt() - kx).clamp(0.0, 1.0);
+        return (curve_distance(t), t);
+    }
+
+    let z = (-p).sqrt();
+    let v = (q / (p * z * 2.0)).acos() / 3.0;
+    let m = v.cos();
+    let n = v.sin() * 3f32.sqrt();
+    let first = ((m + m) * z - kx).clamp(0.0, 1.0);
+    let second = ((-n - m) * z - kx).clamp(0.0, 1.0);
+    let (first_distance, second_distance) = (curve_distance(first), curve_distance(second));
+    if second_distance < first_distance {
+        (second_distance, second)
+    } else {
+        (first_distance, first)
+    }
+}
+
+fn asset_half_size(size: Option<Vec3>) -> Result<Vec3, Error> {
+    size.map(|size| size / 2.0)
+        .ok_or_else(|| Error::msg("an asset of the shape isn't loaded"))
+}
+
+fn deform_bounds(modifiers: &Modifiers, bounds: (Vec3, Vec3)) -> Result<(Vec3, Vec3), Error> {
+    modifiers
+        .deform_bounds(bounds)
+        .ok_or_else(|| Error::msg("the taper makes the shape infinitely wide"))
+}
+
+fn transform_bounds(transform: Mat4, (min, max): (Vec3, Vec3)) -> (Vec3, Vec3) {
+    let mut bounds = None;
+    for corner in 0..8 {
+        let mask = BVec3::new(corner & 1 != 0, corner & 2 != 0, corner & 4 != 0);
+        let pnt = Vec3::select(mask, max, min);
+        let pnt = transform.transform_point3(pnt);
+        bounds = union_bounds(bounds, (pnt, pnt));
+    }
+    bounds.unwrap()
+}
+
+fn union_bounds(bounds: Option<(Vec3, Vec3)>, (min, max): (Vec3, Vec3)) -> Option<(Vec3, Vec3)> {
+    Some(match bounds {
+        Some((bounds_min, bounds_max)) => (bounds_min.min(min), bounds_max.max(max)),
+        None => (min, max),
+    })
+}
+
+// Same as the operations generated by generate_operation
+fn apply_operation(operation: Operation, blend: &Blend, left: f32, right: f32) -> f32 {
+    match operation {
+        Operation::Union => left.min(right),
+        Operation::Intersection => left.max(right),
+        Operation::SmoothUnion => blend.min(left, right),
+        Operation::Subtraction { .. } => left.max(right),
+        Operation::SmoothIntersection | Operation::SmoothSubtraction { .. } => {
+            blend.max(left, right)
+        }
+        Operation::ChamferUnion => blend.chamfer_union(left, right),
+        Operation::ChamferIntersection => blend.chamfer_intersection(left, right),
+        Operation::StairsUnion => blend.stairs_union(left, right),
+        Operation::StairsIntersection => blend.stairs_intersection(left, right),
+        Operation::ColumnsUnion => blend.columns_union(left, right),
+        Operation::ColumnsIntersection => blend.columns_intersection(left, right),
+        Operation::Engrave => blend.engrave(left, right),
+        Operation::Groove => blend.groove(left, right),
+        Operation::Tongue => blend.tongue(left, right),
+        Operation::Morph => blend.morph(left, right),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::{
+        asset::AssetPlugin,
+        core::TaskPoolPlugin,
+        prelude::{AddAsset, Quat},
+    };
+
+    fn tree(shape_type: ShapeType, translation: Vec3, children: Vec<ShapeTree>) -> ShapeTree {
+        ShapeTree {
+            name: None,
+            shape: Shape {
+                shape_type,
+                ..Shape::default()
+            },
+            transform: Transform::from_translation(translation),
+            metaball_points: None,
+            children,
+        }
+    }
+
+    fn primitive(primitive: Primitive, translation: Vec3) -> ShapeTree {
+        tree(
+            ShapeType::Primitive(primitive, Material::default()),
+            translation,
+            Vec::new(),
+        )
+    }
+
+    fn compound(operation: Operation, children: Vec<ShapeTree>) -> ShapeTree {
+        tree(ShapeType::Compound(operation), Vec3::ZERO, children)
+    }
+
+    fn negative(mut tree: ShapeTree) -> ShapeTree {
+        tree.shape.negative = true;
+        tree
+    }
+
+    fn cube() -> ShapeTree {
+        primitive(
+            Primitive::Cube {
+                size: Vec3::splat(2.0),
+            },
+            Vec3::ZERO,
+        )
+    }
+
+    fn sphere(translation: Vec3) -> ShapeTree {
+        primitive(Primitive::Sphere { radius: 0.75 }, translation)
+    }
+
+    fn bounds(tree: &ShapeTree) -> Option<(Vec3, Vec3)> {
+        let assets = ShapeAssets::default();
+        let group = BakeGroup::new(&assets, tree, &GlobalTransform::IDENTITY, true);
+        group.bounds(&assets).unwrap()
+    }
+
+    fn distance(tree: &ShapeTree, pnt: Vec3) -> f32 {
+        let assets = ShapeAssets::default();
+        let group = BakeGroup::new(&assets, tree, &GlobalTransform::IDENTITY, true);
+        group.distance(&assets, pnt)
+    }
+
+    // Points on a grid around the cube, off the planes the shapes are symmetric to
+    fn points() -> impl Iterator<Item = Vec3> {
+        (0..7 * 7 * 7).map(|index| {
+            let cell = Vec3::new(
+                (index % 7) as f32,
+                (index / 7 % 7) as f32,
+                (index / 49) as f32,
+            );
+            (cell - 3.0) * 0.45 + Vec3::new(0.011, 0.023, 0.017)
+        })
+    }
+
+    #[test]
+    fn bakes_the_bounds_around_the_offset() {
+        let tree = compound(
+            Operation::Union,
+            vec![cube(), sphere(Vec3::new(2.0, 0.0, 0.0))],
+        );
+        assert_eq!(
+            bounds(&tree),
+            Some((Vec3::new(-1.0, -1.0, -1.0), Vec3::new(2.75, 1.0, 1.0)))
+        );
+
+        let (image, offset) = tree.bake(&ShapeAssets::default(), 32, 2).unwrap();
+        assert_eq!(offset, Vec3::new(0.875, 0.0, 0.0));
+        // The longest side takes the resolution, the padding surrounds the bounds on every side
+        assert_eq!(image.resolution.width, 32);
+        let texel_size = image.size.x / 32.0;
+        assert!((texel_size - 3.75 / 27.0).abs() < 1e-5);
+        let padded = Vec3::new(3.75, 2.0, 2.0) + 4.0 * texel_size;
+        assert!(image.size.cmpge(padded).all());
+
+        for pnt in points() {
+            let baked = image.distance(pnt - offset);
+            assert!((baked - distance(&tree, pnt)).abs() < texel_size);
+        }
+    }
+
+    #[test]
+    fn only_bounds_the_base_of_subtractions() {
+        let tree = compound(
+            Operation::Subtraction {
+                cutter_material: false,
+            },
+            vec![cube(), sphere(Vec3::new(1.0, 0.0, 0.0))],
+        );
+        assert_eq!(bounds(&tree), Some((Vec3::splat(-1.0), Vec3::splat(1.0))));
+        assert!(distance(&tree, Vec3::new(-0.5, 0.0, 0.0)) < 0.0);
+        assert!(distance(&tree, Vec3::new(0.75, 0.0, 0.0)) > 0.0);
+
+        // A cut out base has nothing to bound
+        let tree = compound(
+            Operation::Subtraction {
+                cutter_material: false,
+            },
+            vec![negative(cube()), sphere(Vec3::ZERO)],
+        );
+        assert_eq!(bounds(&tree), None);
+    }
+
+    #[test]
+    fn cuts_out_negative_children() {
+        let cutter = || compound(Operation::Union, vec![sphere(Vec3::new(1.0, 0.0, 0.0))]);
+        let subtraction = compound(
+            Operation::Subtraction {
+                cutter_material: false,
+            },
+            vec![cube(), cutter()],
+        );
+        let negative_primitive = compound(
+            Operation::Intersection,
+            vec![cube(), negative(sphere(Vec3::new(1.0, 0.0, 0.0)))],
+        );
+        let negative_group = compound(Operation::Intersection, vec![cube(), negative(cutter())]);
+
+        for tree in [&negative_primitive, &negative_group] {
+            assert_eq!(bounds(tree), bounds(&subtraction));
+            for pnt in points() {
+                assert!((distance(tree, pnt) - distance(&subtraction, pnt)).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn morphs_only_the_first_two_children() {
+        let morph = |count| {
+            let children = [cube(), sphere(Vec3::ZERO), sphere(Vec3::X)];
+            compound(Operation::Morph, children[..count].to_vec())
+        };
+        for pnt in points() {
+            assert_eq!(distance(&morph(3), pnt), distance(&morph(2), pnt));
+        }
+        assert!(morph(1).bake(&ShapeAssets::default(), 8, 1).is_err());
+    }
+
+    #[test]
+    fn restores_the_baked_shape_where_it_was() {
+        let mut app = App::new();
+        app.add_plugin(TaskPoolPlugin::default())
+            .add_plugin(AssetPlugin::default())
+            .add_asset::<ShapeImage>();
+        let world = &mut app.world;
+
+        let mut tree = compound(
+            Operation::Union,
+            vec![cube(), sphere(Vec3::new(2.0, 0.0, 0.0))],
+        );
+        tree.transform = Transform::from_xyz(1.0, -2.0, 0.5)
+            .with_rotation(Quat::from_rotation_z(0.7))
+            .with_scale(Vec3::splat(1.5));
+        let entity = tree.spawn(world);
+        let (image, offset) = tree.bake(&ShapeAssets::default(), 16, 1).unwrap();
+
+        FinishBake {
+            entity,
+            tree: tree.clone(),
+            image,
+            offset,
+        }
+        .write(world);
+        // The image is centered on the offset in the space of the shape
+        let transform = *world.get::<Transform>(entity).unwrap();
+        assert!(transform
+            .translation
+            .abs_diff_eq(tree.transform.transform_point(offset), 1e-5));
+        assert_eq!(transform.rotation, tree.transform.rotation);
+        assert!(world.get::<Children>(entity).is_none());
+        assert!(matches!(
+            world.get::<Shape>(entity).unwrap().shape_type,
+            ShapeType::Primitive(Primitive::Image(_), _)
+        ));
+
+        RestoreShape { entity }.write(world);
+        let transform = *world.get::<Transform>(entity).unwrap();
+        assert!(transform
+            .translation
+            .abs_diff_eq(tree.transform.translation, 1e-5));
+        assert_eq!(
+            world.get::<Shape>(entity).unwrap().shape_type,
+            tree.shape.shape_type
+        );
+        assert_eq!(world.get::<Children>(entity).unwrap().len(), 2);
+        assert!(world.get::<BakedShape>(entity).is_none());
+    }
+}
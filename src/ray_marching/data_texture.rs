@@ -0,0 +1,127 @@
+use bevy::render::{
+    render_resource::*,
+    renderer::{RenderDevice, RenderQueue},
+};
+use std::num::NonZeroU32;
+
+// The widest 2D textures WebGL2 is guaranteed to support
+pub const DATA_TEXTURE_WIDTH: u32 = 2048;
+
+// WebGL2 has no storage buffers, so lists of data are stored in the rows of 2D textures instead.
+// The shader loads the element at an index with data_texel in tracing.wgsl. The last row is
+// padded with zeros and there's always one row, so empty lists can be bound too
+pub struct DataTexture {
+    label: &'static str,
+    format: TextureFormat,
+    rows: u32,
+    texture: Texture,
+    pub view: TextureView,
+}
+
+impl DataTexture {
+    // Returns None if there are more rows than the device supports
+    pub fn new(
+        device: &RenderDevice,
+        queue: &RenderQueue,
+        label: &'static str,
+        format: TextureFormat,
+        data: &[u8],
+    ) -> Option<Self> {
+        let rows = row_count(format, data);
+        if rows > device.limits().max_texture_dimension_2d {
+            return None;
+        }
+
+        let texture = device.create_texture_with_data(
+            queue,
+            &TextureDescriptor {
+                label: Some(label),
+                size: extent(rows),
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format,
+                usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            &padded(format, rows, data),
+        );
+        let view = texture.create_view(&TextureViewDescriptor::default());
+        Some(Self {
+            label,
+            format,
+            rows,
+            texture,
+            view,
+        })
+    }
+
+    // Writes the data in place if it takes as many rows, otherwise creates a new texture. Returns
+    // false and keeps the old data if there are more rows than the device supports
+    pub fn set(&mut self, device: &RenderDevice, queue: &RenderQueue, data: &[u8]) -> bool {
+        if row_count(self.format, data) != self.rows {
+            return match Self::new(device, queue, self.label, self.format, data) {
+                Some(texture) => {
+                    *self = texture;
+                    true
+                }
+                None => false,
+            };
+        }
+
+        queue.write_texture(
+            ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            &padded(self.format, self.rows, data),
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(row_size(self.format) as u32),
+                rows_per_image: None,
+            },
+            extent(self.rows),
+        );
+        true
+    }
+}
+
+fn row_size(format: TextureFormat) -> usize {
+    DATA_TEXTURE_WIDTH as usize * format.describe().block_size as usize
+}
+
+fn row_count(format: TextureFormat, data: &[u8]) -> u32 {
+    data.len().div_ceil(row_size(format)).max(1) as u32
+}
+
+fn padded(format: TextureFormat, rows: u32, data: &[u8]) -> Vec<u8> {
+    let mut padded = data.to_vec();
+    padded.resize(rows as usize * row_size(format), 0);
+    padded
+}
+
+fn extent(rows: u32) -> Extent3d {
+    Extent3d {
+        width: DATA_TEXTURE_WIDTH,
+        height: rows,
+        depth_or_array_layers: 1,
+    }
+}
+
+pub fn data_texture_layout_entry(
+    binding: u32,
+    sample_type: TextureSampleType,
+) -> BindGroupLayoutEntry {
+    BindGroupLayoutEntry {
+        binding,
+        visibility: ShaderStages::FRAGMENT,
+        ty: BindingType::Texture {
+            sample_type,
+            view_dimension: TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    }
+}
@@ -0,0 +1,327 @@
+use super::{shape::MAX_HEIGHTFIELD_TEXTURES, Primitive, Shape, ShapeType};
+use bevy::{
+    prelude::{
+        warn, AssetEvent, Assets, EventReader, FromWorld, GlobalTransform, Handle, Image, Query,
+        Res, ResMut, Resource, Vec2, Vec3,
+    },
+    render::{
+        render_asset::RenderAssets,
+        render_resource::*,
+        renderer::{RenderDevice, RenderQueue},
+        settings::WgpuFeatures,
+        Extract,
+    },
+    utils::HashMap,
+};
+
+// The images of the heightfields in the scene and the steepest slopes of their heights, which
+// are measured on the images of the main world
+#[derive(Resource)]
+pub struct ShapeHeightfields {
+    default_texture_view: TextureView,
+    slopes: HashMap<Handle<Image>, Vec2>,
+    handles: Vec<Handle<Image>>,
+}
+
+impl FromWorld for ShapeHeightfields {
+    fn from_world(world: &mut bevy::prelude::World) -> Self {
+        let device = world.resource::<RenderDevice>();
+        let queue = world.resource::<RenderQueue>();
+
+        let texture = device.create_texture_with_data(
+            queue,
+            &TextureDescriptor {
+                label: "default_heightfield_texture".into(),
+                size: Extent3d {
+                    width: 1,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::R8Unorm,
+                usage: TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            &[0u8],
+        );
+
+        Self {
+            default_texture_view: texture.create_view(&TextureViewDescriptor::default()),
+            slopes: HashMap::default(),
+            handles: Vec::new(),
+        }
+    }
+}
+
+impl ShapeHeightfields {
+    pub fn clear(&mut self) {
+        self.handles.clear();
+    }
+
+    // Returns the index of the texture of the image and its slopes, if it's loaded
+    pub fn add_heightfield(&mut self, image_handle: &Handle<Image>) -> Option<(u32, Vec2)> {
+        let slope = *self.slopes.get(image_handle)?;
+        let index = match self
+            .handles
+            .iter()
+            .position(|handle| handle == image_handle)
+        {
+            Some(index) => index,
+            None if self.handles.len() < MAX_HEIGHTFIELD_TEXTURES as usize => {
+                self.handles.push(image_handle.clone_weak());
+                self.handles.len() - 1
+            }
+            None => {
+                warn!("Too many different heightfield images are in the scene");
+                return None;
+            }
+        };
+        Some((index as u32, slope))
+    }
+
+    pub fn texture_view<'a>(
+        &'a self,
+        images: &'a RenderAssets<Image>,
+        index: usize,
+    ) -> &'a TextureView {
+        self.handles
+            .get(index)
+            .and_then(|handle| images.get(handle))
+            .map_or(&self.default_texture_view, |image| &image.texture_view)
+    }
+}
+
+pub(super) fn extract_heightfields(
+    mut heightfields: ResMut<ShapeHeightfields>,
+    mut events: Extract<EventReader<AssetEvent<Image>>>,
+    shapes: Extract<Query<&Shape>>,
+    images: Extract<Res<Assets<Image>>>,
+) {
+    for event in events.iter() {
+        if let AssetEvent::Modified { handle } | AssetEvent::Removed { handle } = event {
+            heightfields.slopes.remove(handle);
+        }
+    }
+
+    for shape in shapes.iter() {
+        let ShapeType::Primitive(Primitive::Heightfield(handle), _) = &shape.shape_type else {
+            continue;
+        };
+        if heightfields.slopes.contains_key(handle) {
+            continue;
+        }
+        let Some(image) = images.get(handle) else {
+            continue;
+        };
+        match heightfield_slope(image) {
+            Some(slope) => {
+                heightfields.slopes.insert(handle.clone_weak(), slope);
+            }
+            None => warn!(
+                "Heightfield images with the format {:?} aren't supported",
+                image.texture_descriptor.format
+            ),
+        }
+    }
+}
+
+// 16 bit images get loaded with uint formats, which can't be filtered. They're reinterpreted as
+// unorm when the device supports it and otherwise converted to half floats
+pub(super) fn convert_heightfield_images(
+    shapes: Query<&Shape>,
+    mut images: ResMut<Assets<Image>>,
+    device: Res<RenderDevice>,
+) {
+    let unorm16 = device
+        .features()
+        .contains(WgpuFeatures::TEXTURE_FORMAT_16BIT_NORM);
+    for shape in shapes.iter() {
+        let ShapeType::Primitive(Primitive::Heightfield(handle), _) = &shape.shape_type else {
+            continue;
+        };
+        let Some(image) = images.get(handle) else {
+            continue;
+        };
+        if !matches!(
+            image.texture_descriptor.format,
+            TextureFormat::R16Uint | TextureFormat::Rg16Uint | TextureFormat::Rgba16Uint
+        ) {
+            continue;
+        }
+        // Modifying the image makes the heightfields compute its slopes again
+        if let Some(image) = images.get_mut(handle) {
+            convert_uint16_image(image, unorm16);
+        }
+    }
+}
+
+fn convert_uint16_image(image: &mut Image, unorm16: bool) {
+    let (unorm_format, texel_size) = match image.texture_descriptor.format {
+        TextureFormat::R16Uint => (TextureFormat::R16Unorm, 2),
+        TextureFormat::Rg16Uint => (TextureFormat::Rg16Unorm, 4),
+        TextureFormat::Rgba16Uint => (TextureFormat::Rgba16Unorm, 8),
+        _ => return,
+    };
+    if unorm16 {
+        image.texture_descriptor.format = unorm_format;
+        return;
+    }
+    image.data = image
+        .data
+        .chunks_exact(texel_size)
+        .flat_map(|texel| {
+            let value = u16::from_le_bytes([texel[0], texel[1]]) as f32 / 65535.0;
+            half::f16::from_f32(value).to_le_bytes()
+        })
+        .collect();
+    image.texture_descriptor.format = TextureFormat::R16Float;
+}
+
+// The height is the first channel, only formats that can be filtered are supported
+pub(super) fn texel_height(image: &Image, x: u32, y: u32) -> Option<f32> {
+    let index = (y * image.texture_descriptor.size.width + x) as usize;
+    let data = &image.data;
+    let unorm = |texel_size: usize| data[index * texel_size] as f32 / 255.0;
+    let unorm16 = |texel_size: usize| {
+        let start = index * texel_size;
+        u16::from_le_bytes([data[start], data[start + 1]]) as f32 / 65535.0
+    };
+    let float = |texel_size: usize| {
+        let start = index * texel_size;
+        half::f16::from_le_bytes([data[start], data[start + 1]]).to_f32()
+    };
+    Some(match image.texture_descriptor.format {
+        TextureFormat::R8Unorm => unorm(1),
+        TextureFormat::Rg8Unorm => unorm(2),
+        TextureFormat::Rgba8Unorm => unorm(4),
+        TextureFormat::Rgba8UnormSrgb => srgb_to_linear(unorm(4)),
+        TextureFormat::R16Unorm => unorm16(2),
+        TextureFormat::Rg16Unorm => unorm16(4),
+        TextureFormat::Rgba16Unorm => unorm16(8),
+        TextureFormat::R16Float => float(2),
+        TextureFormat::Rgba16Float => float(8),
+        _ => return None,
+    })
+}
+
+// Same as sampling the texture with the shape sampler
+pub(super) fn sample_height(image: &Image, uv: Vec2) -> Option<f32> {
+    let size = image.texture_descriptor.size;
+    let max = Vec2::new(size.width as f32 - 1.0, size.height as f32 - 1.0);
+    let texel = uv * Vec2::new(size.width as f32, size.height as f32) - 0.5;
+    let (min, factor) = (texel.floor(), texel - texel.floor());
+    let height = |offset: Vec2| {
+        let texel = (min + offset).clamp(Vec2::ZERO, max);
+        texel_height(image, texel.x as u32, texel.y as u32)
+    };
+    let bottom = lerp(height(Vec2::ZERO)?, height(Vec2::X)?, factor.x);
+    let top = lerp(height(Vec2::Y)?, height(Vec2::ONE)?, factor.x);
+    Some(lerp(bottom, top, factor.y))
+}
+
+// The steepest slopes of the interpolated heights along x and y, with the image covering a
+// unit square
+pub(super) fn heightfield_slope(image: &Image) -> Option<Vec2> {
+    let size = image.texture_descriptor.size;
+    let mut slope = Vec2::ZERO;
+    for y in 0..size.height {
+        for x in 0..size.width {
+            let height = texel_height(image, x, y)?;
+            if x + 1 < size.width {
+                let difference = (texel_height(image, x + 1, y)? - height).abs();
+                slope.x = slope.x.max(difference * size.width as f32);
+            }
+            if y + 1 < size.height {
+                let difference = (texel_height(image, x, y + 1)? - height).abs();
+                slope.y = slope.y.max(difference * size.height as f32);
+            }
+        }
+    }
+    Some(slope)
+}
+
+// Scales the height above the surface, in the space of the heightfield, into a bound of the
+// distance in the units of the shape. The transform can rotate and scale the heightfield, so the
+// slopes are scaled like the axes before the distance gets bounded with them
+pub(super) fn height_scale(transform: &GlobalTransform, slope: Vec2, scale: f32) -> f32 {
+    let matrix = transform.affine().matrix3;
+    let axes = Vec3::new(
+        matrix.x_axis.length(),
+        matrix.y_axis.length(),
+        matrix.z_axis.length(),
+    );
+    let slope = slope * axes.z / axes.truncate();
+    axes.z / ((1.0 + slope.length_squared()).sqrt() * scale.abs())
+}
+
+// Same as sdf_heightfield in the shader, the footprint is a unit square around the origin and
+// the heights go from 0 to 1 up the z axis
+pub(super) fn heightfield_distance(pnt: Vec3, height: f32, height_scale: f32) -> f32 {
+    let q = (pnt - Vec3::Z * 0.5).abs() - 0.5;
+    let box_distance = q.max(Vec3::ZERO).length() + q.max_element().min(0.0);
+    box_distance.max((pnt.z - height) * height_scale)
+}
+
+// The uv coordinates of a point in the space of the heightfield, the top of the image points
+// along the y axis
+pub(super) fn heightfield_uv(pnt: Vec3) -> Vec2 {
+    Vec2::new(pnt.x + 0.5, 0.5 - pnt.y)
+}
+
+fn lerp(from: f32, to: f32, factor: f32) -> f32 {
+    from + (to - from) * factor
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(format: TextureFormat, data: Vec<u8>) -> Image {
+        let size = Extent3d {
+            width: 2,
+            height: 1,
+            depth_or_array_layers: 1,
+        };
+        Image::new(size, TextureDimension::D2, data, format)
+    }
+
+    #[test]
+    fn unorm16_heights() {
+        let data = [0u16, 65535, 13107, 7]
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        let image = image(TextureFormat::Rg16Unorm, data);
+        assert_eq!(texel_height(&image, 0, 0), Some(0.0));
+        assert_eq!(texel_height(&image, 1, 0), Some(0.2));
+    }
+
+    #[test]
+    fn converted_uint16_heights() {
+        let data: Vec<u8> = [0u16, 65535, 13107, 7, 65535, 0, 0, 0]
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        let mut unorm = image(TextureFormat::Rgba16Uint, data.clone());
+        convert_uint16_image(&mut unorm, true);
+        assert_eq!(unorm.texture_descriptor.format, TextureFormat::Rgba16Unorm);
+        assert_eq!(unorm.data, data);
+
+        let mut float = image(TextureFormat::Rgba16Uint, data);
+        convert_uint16_image(&mut float, false);
+        assert_eq!(float.texture_descriptor.format, TextureFormat::R16Float);
+        assert_eq!(float.data.len(), 4);
+        assert_eq!(texel_height(&float, 0, 0), Some(0.0));
+        assert_eq!(texel_height(&float, 1, 0), Some(1.0));
+    }
+}
@@ -0,0 +1,127 @@
+use super::{data_texture::DataTexture, Material};
+use bevy::{
+    prelude::{warn, Component, FromWorld, Resource, Vec3, Vec4},
+    render::{
+        render_resource::*,
+        renderer::{RenderDevice, RenderQueue},
+    },
+};
+
+const FAR: f32 = 64.0;
+
+// The steepest slope of the kernel (1 - x^2)^3, at x = 1 / sqrt(5)
+const KERNEL_GRADIENT: f32 = 1.7173002;
+
+// The points of the metaballs primitive of the same entity, in the space of the shape. It can be
+// changed every frame without generating a new shader
+#[derive(Component, Clone, Default, Debug)]
+pub struct MetaballPoints(pub Vec<MetaballPoint>);
+
+// The radius is the one of the point when it's alone, the points without a color have the color
+// of the material of the shape
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct MetaballPoint {
+    pub position: Vec3,
+    pub radius: f32,
+    pub color: Option<Vec3>,
+}
+
+// The points of all the metaballs in the scene, concatenated into a data texture. Each point
+// takes two texels, the position and the radius of influence, then the color
+#[derive(Resource)]
+pub struct ShapeMetaballs {
+    points: Vec<Vec4>,
+    pub texture: DataTexture,
+}
+
+impl FromWorld for ShapeMetaballs {
+    fn from_world(world: &mut bevy::prelude::World) -> Self {
+        let device = world.resource::<RenderDevice>();
+        let queue = world.resource::<RenderQueue>();
+        Self {
+            points: Vec::new(),
+            texture: DataTexture::new(
+                device,
+                queue,
+                "shape_metaball_points",
+                TextureFormat::Rgba32Float,
+                &[],
+            )
+            .unwrap(),
+        }
+    }
+}
+
+impl ShapeMetaballs {
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+
+    // Returns the offset of the points in the texture
+    pub fn add_points(
+        &mut self,
+        points: &[MetaballPoint],
+        threshold: f32,
+        material: &Material,
+    ) -> u32 {
+        let threshold = clamp_threshold(threshold);
+        let offset = (self.points.len() / 2) as u32;
+        for point in points {
+            let influence = influence_radius(point.radius, threshold);
+            self.points.push(point.position.extend(influence));
+            self.points
+                .push(point.color.unwrap_or(material.color).extend(0.0));
+        }
+        offset
+    }
+
+    pub fn prepare(&mut self, device: &RenderDevice, queue: &RenderQueue) {
+        let data = self
+            .points
+            .iter()
+            .flat_map(|point| point.to_array())
+            .flat_map(|value| value.to_le_bytes())
+            .collect::<Vec<_>>();
+        if !self.texture.set(device, queue, &data) {
+            warn!("Too many metaball points are in the scene");
+        }
+    }
+}
+
+// The field is 1 at the points, so the surface needs a threshold between 0 and 1
+pub(super) fn clamp_threshold(threshold: f32) -> f32 {
+    threshold.clamp(0.001, 0.999)
+}
+
+// The distance from a point at which its field falls to the threshold is its radius
+fn influence_radius(radius: f32, threshold: f32) -> f32 {
+    radius / (1.0 - threshold.cbrt()).sqrt()
+}
+
+// Same as sdf_metaballs in the shader
+pub(super) fn metaballs_distance(points: &[MetaballPoint], threshold: f32, pnt: Vec3) -> f32 {
+    let threshold = clamp_threshold(threshold);
+    let mut field = 0.0;
+    let mut gradient = 0.0;
+    let mut outside = FAR;
+    for point in points {
+        let influence = influence_radius(point.radius, threshold);
+        let distance = pnt.distance(point.position);
+        if distance < influence {
+            let h = 1.0 - distance * distance / (influence * influence);
+            field += h * h * h;
+            gradient += KERNEL_GRADIENT / influence;
+        } else {
+            outside = outside.min(distance - influence);
+        }
+    }
+    ((threshold - field) / gradient.max(0.0001)).min(outside)
+}
+
+// The surface is within the radius of influence of the points
+pub(super) fn metaballs_half_size(points: &[MetaballPoint], threshold: f32) -> Vec3 {
+    let threshold = clamp_threshold(threshold);
+    points.iter().fold(Vec3::ZERO, |half_size, point| {
+        half_size.max(point.position.abs() + influence_radius(point.radius, threshold))
+    })
+}
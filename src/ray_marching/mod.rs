@@ -1,20 +1,39 @@
+mod baking;
+mod data_texture;
 mod environment;
+mod heightfield;
+mod metaballs;
 mod shape_loader;
 mod node;
+mod redistance;
+mod sculpting;
 mod shape;
+mod shape_export;
+mod shape_image;
+mod shape_octree;
 mod stages;
 mod tracing;
 mod upsampling;
 mod view;
 
 pub use self::{
+    baking::{BakeShape, BakedShape, RestoreShape, ShapeAssets, ShapeTree},
     environment::Environment,
-    shape::{Material, Operation, Primitive, Shape, ShapeImage, ShapeType},
+    metaballs::{MetaballPoint, MetaballPoints},
+    redistance::GradientDeviation,
+    sculpting::{Brush, BrushMode, Sculpt, ShapeImageEdits, TexelRegion},
+    shape::{
+        Blend, Domain, Material, Modifiers, Operation, Primitive, Profile, Shape, ShapeImage,
+        ShapeType, SmoothKernel,
+    },
+    shape_export::Ktx2Format,
+    shape_image::ImageOperation,
+    shape_octree::ShapeOctree,
 };
 use self::{
-    environment::EnvironmentPlugin, shape_loader::ShapeLoaderPlugin, node::RayMarchingNode,
-    shape::ShapePlugin, stages::StagesPlugin, tracing::TracingPlugin, upsampling::UpsamplingPlugin,
-    view::ViewPlugin,
+    baking::BakingPlugin, environment::EnvironmentPlugin, shape_loader::ShapeLoaderPlugin,
+    node::RayMarchingNode, shape::ShapePlugin, stages::StagesPlugin, tracing::TracingPlugin,
+    upsampling::UpsamplingPlugin, view::ViewPlugin,
 };
 use bevy::{
     core_pipeline::core_3d,
@@ -36,6 +55,7 @@ impl Plugin for RayMarchingPlugin {
             .add_plugin(ViewPlugin)
             .add_plugin(ShapePlugin)
             .add_plugin(ShapeLoaderPlugin)
+            .add_plugin(BakingPlugin)
             .add_plugin(EnvironmentPlugin)
             .add_plugin(StagesPlugin)
             .add_plugin(TracingPlugin)
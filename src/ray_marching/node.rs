@@ -16,17 +16,19 @@ use bevy::{
     },
 };
 
+type ViewQuery = (
+    &'static ExtractedCamera,
+    &'static ViewTarget,
+    &'static ViewUniformIndex,
+    &'static EnvironmentUniformIndex,
+    &'static StageTextures,
+    &'static StageUniformIndices,
+    &'static StageBindGroups,
+    &'static TracingPipelines,
+);
+
 pub(super) struct RayMarchingNode {
-    view_query: QueryState<(
-        &'static ExtractedCamera,
-        &'static ViewTarget,
-        &'static ViewUniformIndex,
-        &'static EnvironmentUniformIndex,
-        &'static StageTextures,
-        &'static StageUniformIndices,
-        &'static StageBindGroups,
-        &'static TracingPipelines,
-    )>,
+    view_query: QueryState<ViewQuery>,
 }
 
 impl RayMarchingNode {
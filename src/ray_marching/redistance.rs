@@ -0,0 +1,312 @@
+use super::ShapeImage;
+use bevy::prelude::Vec3;
+
+const SWEEP_ROUNDS: usize = 2;
+const MIN_GRADIENT: f32 = 0.1;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GradientDeviation {
+    pub min_gradient: f32,
+    pub max_gradient: f32,
+    pub mean_deviation: f32,
+    pub rms_deviation: f32,
+}
+
+impl ShapeImage {
+    // Recomputes the distances with fast sweeping while keeping the zero level set in place
+    pub fn redistance(&mut self) {
+        let width = self.resolution.width as usize;
+        let height = self.resolution.height as usize;
+        let depth = self.resolution.depth_or_array_layers as usize;
+        let texel_size = self.texel_size();
+
+        let distances = self.interface_distances(texel_size);
+        if distances.iter().all(|distance| distance.is_none()) {
+            return;
+        }
+
+        let frozen = distances
+            .iter()
+            .map(|distance| distance.is_some())
+            .collect::<Vec<_>>();
+        let mut distances = distances
+            .into_iter()
+            .map(|distance| distance.unwrap_or(f32::INFINITY))
+            .collect::<Vec<_>>();
+
+        let index = |x: usize, y: usize, z: usize| (z * height + y) * width + x;
+        let neighbor_min = |distances: &[f32], x: usize, y: usize, z: usize, axis: usize| {
+            let (pnt, len) = match axis {
+                0 => (x, width),
+                1 => (y, height),
+                _ => (z, depth),
+            };
+            let offset = match axis {
+                0 => 1,
+                1 => width,
+                _ => width * height,
+            };
+            let i = index(x, y, z);
+            let prev = if pnt > 0 {
+                distances[i - offset]
+            } else {
+                f32::INFINITY
+            };
+            let next = if pnt + 1 < len {
+                distances[i + offset]
+            } else {
+                f32::INFINITY
+            };
+            prev.min(next)
+        };
+
+        for _ in 0..SWEEP_ROUNDS {
+            for sweep in 0..8 {
+                for z in sweep_range(depth, sweep & 4 != 0) {
+                    for y in sweep_range(height, sweep & 2 != 0) {
+                        for x in sweep_range(width, sweep & 1 != 0) {
+                            let i = index(x, y, z);
+                            if frozen[i] {
+                                continue;
+                            }
+                            let distance = solve_eikonal([
+                                (neighbor_min(&distances, x, y, z, 0), texel_size.x),
+                                (neighbor_min(&distances, x, y, z, 1), texel_size.y),
+                                (neighbor_min(&distances, x, y, z, 2), texel_size.z),
+                            ]);
+                            distances[i] = distances[i].min(distance);
+                        }
+                    }
+                }
+            }
+        }
+
+        for (value, distance) in self.data.iter_mut().zip(distances) {
+            *value = if *value < 0.0 { -distance } else { distance };
+        }
+    }
+
+    // The unsigned distance of the texels next to a sign change, estimated from the crossings
+    fn interface_distances(&self, texel_size: Vec3) -> Vec<Option<f32>> {
+        let width = self.resolution.width;
+        let height = self.resolution.height;
+        let depth = self.resolution.depth_or_array_layers;
+
+        let mut distances = Vec::with_capacity(self.data.len());
+        for z in 0..depth {
+            for y in 0..height {
+                for x in 0..width {
+                    let value = self.texel(x, y, z);
+                    let crossing = |neighbor: Option<(u32, u32, u32)>, size: f32| {
+                        let (x, y, z) = neighbor?;
+                        let neighbor_value = self.texel(x, y, z);
+                        if (value < 0.0) == (neighbor_value < 0.0) {
+                            return None;
+                        }
+                        Some(value / (value - neighbor_value) * size)
+                    };
+                    let axis = |prev, next, size: f32| match (
+                        crossing(prev, size),
+                        crossing(next, size),
+                    ) {
+                        (Some(prev), Some(next)) => Some(prev.min(next)),
+                        (prev, next) => prev.or(next),
+                    };
+
+                    let axes = [
+                        axis(
+                            x.checked_sub(1).map(|x| (x, y, z)),
+                            (x + 1 < width).then_some((x + 1, y, z)),
+                            texel_size.x,
+                        ),
+                        axis(
+                            y.checked_sub(1).map(|y| (x, y, z)),
+                            (y + 1 < height).then_some((x, y + 1, z)),
+                            texel_size.y,
+                        ),
+                        axis(
+                            z.checked_sub(1).map(|z| (x, y, z)),
+                            (z + 1 < depth).then_some((x, y, z + 1)),
+                            texel_size.z,
+                        ),
+                    ];
+
+                    // Distance to the plane going through the crossings of the axes
+                    let inv_squared_sum = axes
+                        .iter()
+                        .flatten()
+                        .map(|distance| 1.0 / (distance * distance).max(f32::EPSILON))
+                        .sum::<f32>();
+                    if inv_squared_sum == 0.0 {
+                        distances.push(None);
+                        continue;
+                    }
+                    let plane_distance = 1.0 / inv_squared_sum.sqrt();
+
+                    // The value normalized by the gradient is more accurate when the field is smooth
+                    let gradient = self.texel_gradient(x, y, z, texel_size).length();
+                    distances.push(Some(if gradient > MIN_GRADIENT {
+                        (value.abs() / gradient).min(plane_distance * 2.0)
+                    } else {
+                        plane_distance
+                    }));
+                }
+            }
+        }
+        distances
+    }
+
+    // Central differences inside the image, one sided differences on its borders
+    fn texel_gradient(&self, x: u32, y: u32, z: u32, texel_size: Vec3) -> Vec3 {
+        let difference = |pnt: u32, len: u32, texel: &dyn Fn(u32) -> f32| {
+            let prev = pnt.saturating_sub(1);
+            let next = (pnt + 1).min(len - 1);
+            if next == prev {
+                0.0
+            } else {
+                (texel(next) - texel(prev)) / (next - prev) as f32
+            }
+        };
+        Vec3::new(
+            difference(x, self.resolution.width, &|x| self.texel(x, y, z)),
+            difference(y, self.resolution.height, &|y| self.texel(x, y, z)),
+            difference(z, self.resolution.depth_or_array_layers, &|z| {
+                self.texel(x, y, z)
+            }),
+        ) / texel_size
+    }
+
+    // How far the length of the gradient drifts from 1, measured with central differences
+    pub fn gradient_deviation(&self) -> GradientDeviation {
+        let width = self.resolution.width;
+        let height = self.resolution.height;
+        let depth = self.resolution.depth_or_array_layers;
+        let texel_size = self.texel_size();
+
+        let mut count = 0;
+        let mut deviation = GradientDeviation {
+            min_gradient: f32::INFINITY,
+            ..Default::default()
+        };
+        for z in 1..depth.saturating_sub(1) {
+            for y in 1..height.saturating_sub(1) {
+                for x in 1..width.saturating_sub(1) {
+                    let gradient = self.texel_gradient(x, y, z, texel_size).length();
+                    let gradient_deviation = (gradient - 1.0).abs();
+
+                    count += 1;
+                    deviation.min_gradient = deviation.min_gradient.min(gradient);
+                    deviation.max_gradient = deviation.max_gradient.max(gradient);
+                    deviation.mean_deviation += gradient_deviation;
+                    deviation.rms_deviation += gradient_deviation * gradient_deviation;
+                }
+            }
+        }
+
+        if count == 0 {
+            return GradientDeviation::default();
+        }
+        deviation.mean_deviation /= count as f32;
+        deviation.rms_deviation = (deviation.rms_deviation / count as f32).sqrt();
+        deviation
+    }
+}
+
+fn sweep_range(len: usize, reverse: bool) -> Box<dyn Iterator<Item = usize>> {
+    if reverse {
+        Box::new((0..len).rev())
+    } else {
+        Box::new(0..len)
+    }
+}
+
+// Godunov upwind solution of |grad u| = 1 from the smallest neighbor of each axis
+fn solve_eikonal(mut neighbors: [(f32, f32); 3]) -> f32 {
+    neighbors.sort_by(|(left, _), (right, _)| left.total_cmp(right));
+
+    let mut weight_sum = 0.0;
+    let mut weighted_sum = 0.0;
+    let mut weighted_squared_sum = 0.0;
+    let mut distance = f32::INFINITY;
+    for (index, (neighbor, size)) in neighbors.iter().enumerate() {
+        if !neighbor.is_finite() {
+            break;
+        }
+        let weight = 1.0 / (size * size);
+        weight_sum += weight;
+        weighted_sum += weight * neighbor;
+        weighted_squared_sum += weight * neighbor * neighbor;
+
+        let discriminant = weighted_sum * weighted_sum - weight_sum * (weighted_squared_sum - 1.0);
+        distance = (weighted_sum + discriminant.max(0.0).sqrt()) / weight_sum;
+
+        if index + 1 == neighbors.len() || distance <= neighbors[index + 1].0 {
+            break;
+        }
+    }
+    distance
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::render::render_resource::Extent3d;
+
+    const RESOLUTION: Extent3d = Extent3d {
+        width: 24,
+        height: 24,
+        depth_or_array_layers: 24,
+    };
+
+    fn sphere(scale: f32) -> ShapeImage {
+        ShapeImage::from_fn(Vec3::splat(3.0), RESOLUTION, |pnt| {
+            (pnt.length() - 1.0) * scale
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn measures_the_gradient_deviation() {
+        let deviation = sphere(1.0).gradient_deviation();
+        assert!(deviation.mean_deviation < 0.01);
+        assert!(deviation.max_gradient <= 1.0);
+
+        let deviation = sphere(3.0).gradient_deviation();
+        assert!((deviation.mean_deviation - 2.0).abs() < 0.05);
+        assert!(deviation.rms_deviation >= deviation.mean_deviation);
+    }
+
+    #[test]
+    fn restores_distances_and_keeps_the_surface() {
+        let exact = sphere(1.0);
+        for scale in [0.25, 3.0] {
+            let mut shape_image = sphere(scale);
+            shape_image.redistance();
+
+            let texel_size = shape_image.texel_size().x;
+            for (distance, exact) in shape_image.data.iter().zip(&exact.data) {
+                assert_eq!(distance.signum(), exact.signum());
+                assert!((distance - exact).abs() < texel_size);
+            }
+            assert!(shape_image.gradient_deviation().mean_deviation < 0.05);
+        }
+    }
+
+    #[test]
+    fn ignores_images_without_a_surface() {
+        let mut shape_image =
+            ShapeImage::from_fn(Vec3::ONE, RESOLUTION, |pnt| pnt.length() + 2.0).unwrap();
+        let data = shape_image.data.clone();
+        shape_image.redistance();
+        assert_eq!(shape_image.data, data);
+    }
+
+    #[test]
+    fn solves_the_eikonal_equation() {
+        // A single neighbor is one step away, three equal ones meet at 1 / sqrt(3)
+        let solution = solve_eikonal([(0.0, 1.0), (f32::INFINITY, 1.0), (f32::INFINITY, 1.0)]);
+        assert!((solution - 1.0).abs() < 1e-6);
+        let solution = solve_eikonal([(0.0, 1.0); 3]);
+        assert!((solution - 1.0 / 3.0f32.sqrt()).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,249 @@
+use super::{
+    shape::get_inverse_transform, shape_image::smooth_min, Primitive, Shape, ShapeImage, ShapeType,
+};
+use bevy::{
+    ecs::system::Command,
+    prelude::{warn, Assets, Entity, GlobalTransform, Handle, Resource, Vec3, World},
+    render::render_resource::Extent3d,
+    utils::HashMap,
+};
+
+const RAYCAST_ITERATIONS: u32 = 256;
+const RAYCAST_EPSILON: f32 = 0.001;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BrushMode {
+    Add,
+    Subtract,
+    Smooth,
+}
+
+// A spherical brush, the smoothness blends the sphere into the surface when adding or
+// subtracting and is the amount the texels move towards their neighbours when smoothing
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Brush {
+    pub mode: BrushMode,
+    pub radius: f32,
+    pub smoothness: f32,
+}
+
+impl Default for Brush {
+    fn default() -> Self {
+        Self {
+            mode: BrushMode::Add,
+            radius: 0.2,
+            smoothness: 0.5,
+        }
+    }
+}
+
+// Applies a brush at a world position to the image of a shape
+pub struct Sculpt {
+    pub entity: Entity,
+    pub position: Vec3,
+    pub brush: Brush,
+}
+
+// Texels from min up to but excluding max
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TexelRegion {
+    pub min: [u32; 3],
+    pub max: [u32; 3],
+}
+
+// The texels of the images changed since the last frame, only these are uploaded again
+#[derive(Resource, Default)]
+pub struct ShapeImageEdits(HashMap<Handle<ShapeImage>, TexelRegion>);
+
+impl Command for Sculpt {
+    fn write(self, world: &mut World) {
+        let Some(shape) = world.get::<Shape>(self.entity) else {
+            warn!("Only shapes can be sculpted");
+            return;
+        };
+        let ShapeType::Primitive(Primitive::Image(handle), _) = &shape.shape_type else {
+            warn!("Only image shapes can be sculpted");
+            return;
+        };
+        let handle = handle.clone_weak();
+        let transform = world
+            .get::<GlobalTransform>(self.entity)
+            .copied()
+            .unwrap_or_default();
+        let (inv_transform, scale) = get_inverse_transform(&transform, false);
+
+        let mut images = world.resource_mut::<Assets<ShapeImage>>();
+        let Some(image) = images.get_mut(&handle) else {
+            warn!("The image of the shape isn't loaded");
+            return;
+        };
+        let region = image.sculpt(
+            &self.brush,
+            inv_transform.transform_point3(self.position),
+            scale,
+        );
+        if let Some(region) = region {
+            world.resource_mut::<ShapeImageEdits>().add(&handle, region);
+        }
+    }
+}
+
+impl TexelRegion {
+    pub fn union(self, other: Self) -> Self {
+        Self {
+            min: [0, 1, 2].map(|axis| self.min[axis].min(other.min[axis])),
+            max: [0, 1, 2].map(|axis| self.max[axis].max(other.max[axis])),
+        }
+    }
+
+    pub fn extent(&self) -> Extent3d {
+        Extent3d {
+            width: self.max[0] - self.min[0],
+            height: self.max[1] - self.min[1],
+            depth_or_array_layers: self.max[2] - self.min[2],
+        }
+    }
+}
+
+impl ShapeImageEdits {
+    pub fn add(&mut self, handle: &Handle<ShapeImage>, region: TexelRegion) {
+        let region = match self.0.get(handle) {
+            Some(edited) => edited.union(region),
+            None => region,
+        };
+        self.0.insert(handle.clone_weak(), region);
+    }
+
+    pub fn get(&self, handle: &Handle<ShapeImage>) -> Option<TexelRegion> {
+        self.0.get(handle).copied()
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+impl ShapeImage {
+    // Applies a brush at a point in the space of the image, scaled by the scale of the shape,
+    // and returns the changed texels
+    pub fn sculpt(&mut self, brush: &Brush, center: Vec3, scale: f32) -> Option<TexelRegion> {
+        let radius = brush.radius / scale;
+        let k = (brush.smoothness * radius).max(f32::EPSILON);
+        let brush_distance = |pnt: Vec3| (pnt - center).length() - radius;
+
+        // Adding or subtracting only moves the surface within the radius and the smoothness. The
+        // distances are updated up to another radius away, further out subtracting leaves them
+        // too small and adding, with the brush on the surface, too large by at most the radius
+        let candidates = match brush.mode {
+            BrushMode::Add | BrushMode::Subtract => self.texels_around(center, 2.0 * radius + k)?,
+            BrushMode::Smooth => self.texels_around(center, radius)?,
+        };
+
+        let mut changes = Vec::new();
+        for z in candidates.min[2]..candidates.max[2] {
+            for y in candidates.min[1]..candidates.max[1] {
+                for x in candidates.min[0]..candidates.max[0] {
+                    let pnt = self.texel_position(x, y, z);
+                    let old = self.texel(x, y, z);
+                    let new = match brush.mode {
+                        BrushMode::Add => smooth_min(old, brush_distance(pnt), k),
+                        BrushMode::Subtract => -smooth_min(-old, brush_distance(pnt), k),
+                        BrushMode::Smooth => {
+                            let falloff = (1.0 - (pnt - center).length() / radius).max(0.0);
+                            let weight = brush.smoothness.clamp(0.0, 1.0) * falloff * falloff;
+                            old + (self.neighbour_average(x, y, z) - old) * weight
+                        }
+                    };
+                    if new != old {
+                        changes.push(([x, y, z], new));
+                    }
+                }
+            }
+        }
+
+        let mut region: Option<TexelRegion> = None;
+        for ([x, y, z], distance) in changes {
+            let index = self.texel_index(x, y, z);
+            self.data[index] = distance;
+            let texel = TexelRegion {
+                min: [x, y, z],
+                max: [x + 1, y + 1, z + 1],
+            };
+            region = Some(region.map_or(texel, |region| region.union(texel)));
+        }
+        region
+    }
+
+    // The texels of the region, row by row
+    pub fn region_data(&self, region: TexelRegion) -> Vec<f32> {
+        let extent = region.extent();
+        let mut data = Vec::with_capacity(
+            (extent.width * extent.height * extent.depth_or_array_layers) as usize,
+        );
+        for z in region.min[2]..region.max[2] {
+            for y in region.min[1]..region.max[1] {
+                let start = self.texel_index(region.min[0], y, z);
+                data.extend_from_slice(&self.data[start..start + extent.width as usize]);
+            }
+        }
+        data
+    }
+
+    // Sphere traces a ray in the space of the image and returns the point it hits
+    pub fn raycast(&self, origin: Vec3, direction: Vec3) -> Option<Vec3> {
+        let direction = direction.normalize();
+        let far = self.size.length() + origin.length();
+        let mut distance = 0.0;
+        for _ in 0..RAYCAST_ITERATIONS {
+            let pnt = origin + direction * distance;
+            let step = self.distance(pnt);
+            if step < RAYCAST_EPSILON {
+                return Some(pnt);
+            }
+            distance += step;
+            if distance > far {
+                break;
+            }
+        }
+        None
+    }
+
+    fn texels_around(&self, center: Vec3, radius: f32) -> Option<TexelRegion> {
+        let resolution = [
+            self.resolution.width,
+            self.resolution.height,
+            self.resolution.depth_or_array_layers,
+        ];
+        let texel_size = self.texel_size();
+        let texel = |pnt: Vec3| (pnt + self.size / 2.0) / texel_size - 0.5;
+        let (min, max) = (
+            texel(center - radius).ceil(),
+            texel(center + radius).floor(),
+        );
+
+        let region = TexelRegion {
+            min: [0, 1, 2].map(|axis| min[axis].max(0.0) as u32),
+            max: [0, 1, 2].map(|axis| (max[axis] + 1.0).clamp(0.0, resolution[axis] as f32) as u32),
+        };
+        (0..3)
+            .all(|axis| region.min[axis] < region.max[axis])
+            .then_some(region)
+    }
+
+    fn neighbour_average(&self, x: u32, y: u32, z: u32) -> f32 {
+        let max = [
+            self.resolution.width - 1,
+            self.resolution.height - 1,
+            self.resolution.depth_or_array_layers - 1,
+        ];
+        let neighbours = [
+            self.texel(x.saturating_sub(1), y, z),
+            self.texel((x + 1).min(max[0]), y, z),
+            self.texel(x, y.saturating_sub(1), z),
+            self.texel(x, (y + 1).min(max[1]), z),
+            self.texel(x, y, z.saturating_sub(1)),
+            self.texel(x, y, (z + 1).min(max[2])),
+        ];
+        neighbours.iter().sum::<f32>() / 6.0
+    }
+}
@@ -1,27 +1,41 @@
 use bevy::{
     ecs::{
         query::QueryItem,
-        system::{lifetimeless::SRes, SystemParamItem},
+        system::{lifetimeless::SRes, SystemParam, SystemParamItem},
     },
-    math::Vec3A,
     prelude::{
-        default, warn, AddAsset, Children, Commands, Component, Deref, Entity, FromWorld,
-        GlobalTransform, Handle, IntoSystemConfig, Mat4, Parent, Plugin, Query, Res, ResMut,
-        Resource, Vec3, With, Without,
+        default, warn, AddAsset, AssetEvent, Assets, BVec3, Children, Commands, Component, CoreSet,
+        Deref, Entity, EventReader, FromWorld, GlobalTransform, Handle, IVec3, IntoSystemAppConfig,
+        IntoSystemConfig, Mat4, Parent, Plugin, Query, Res, ResMut, Resource, UVec3, Vec2, Vec3,
+        Vec4, With, Without,
     },
     reflect::{FromReflect, Reflect, TypeUuid},
     render::{
         extract_component::{ExtractComponent, ExtractComponentPlugin},
-        render_asset::{PrepareAssetError, RenderAsset, RenderAssetPlugin, RenderAssets},
+        render_asset::{
+            PrepareAssetError, PrepareAssetSet, RenderAsset, RenderAssetPlugin, RenderAssets,
+        },
         render_resource::*,
         renderer::{RenderDevice, RenderQueue},
-        RenderApp, RenderSet,
+        Extract, ExtractSchedule, RenderApp, RenderSet,
     },
-    utils::HashMap,
+    utils::{HashMap, HashSet},
 };
 use nalgebra::SMatrix;
+
+use super::{
+    data_texture::data_texture_layout_entry,
+    heightfield::{
+        convert_heightfield_images, extract_heightfields, height_scale, ShapeHeightfields,
+    },
+    metaballs::{clamp_threshold, MetaballPoint, MetaballPoints, ShapeMetaballs},
+    sculpting::{ShapeImageEdits, TexelRegion},
+    shape_octree::{ShapeOctree, ShapeOctrees},
+};
 use std::{
     borrow::Borrow,
+    f32::consts::{FRAC_1_SQRT_2, SQRT_2, TAU},
+    num::NonZeroU32,
     ops::{Deref, Range},
 };
 
@@ -30,15 +44,33 @@ pub struct ShapePlugin;
 impl Plugin for ShapePlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
         app.add_asset::<ShapeImage>()
+            .add_asset::<ShapeOctree>()
+            .init_resource::<ShapeImageEdits>()
+            .add_system(clear_shape_image_edits.in_base_set(CoreSet::First))
+            .add_system(convert_heightfield_images.in_base_set(CoreSet::PostUpdate))
             .add_plugin(ExtractComponentPlugin::<ExtractedShape>::default())
             .add_plugin(ExtractComponentPlugin::<RootShape>::default())
-            .add_plugin(RenderAssetPlugin::<ShapeImage>::default());
+            .add_plugin(RenderAssetPlugin::<ShapeOctree>::default());
+        // The images are extracted like with RenderAssetPlugin, but the edited ones only
+        // upload the edited texels
         app.sub_app_mut(RenderApp)
+            .init_resource::<RenderAssets<ShapeImage>>()
+            .init_resource::<ExtractedShapeImages>()
+            .add_system(extract_shape_images.in_schedule(ExtractSchedule))
+            .add_system(prepare_shape_images.in_set(PrepareAssetSet::AssetPrepare))
             .init_resource::<ShapesUniformBuffer>()
             .init_resource::<ShapesBindGroupLayout>()
             .init_resource::<ShapeSampler>()
             .init_resource::<ShapeImages>()
-            .add_system(prepare_shapes.in_set(RenderSet::Prepare))
+            .init_resource::<ShapeOctrees>()
+            .init_resource::<ShapeHeightfields>()
+            .init_resource::<ShapeMetaballs>()
+            .add_system(extract_heightfields.in_schedule(ExtractSchedule))
+            .add_system(
+                prepare_shapes
+                    .in_set(RenderSet::Prepare)
+                    .after(PrepareAssetSet::AssetPrepare),
+            )
             .add_system(queue_shapes_bind_group.in_set(RenderSet::Queue));
     }
 }
@@ -47,6 +79,11 @@ impl Plugin for ShapePlugin {
 pub struct Shape {
     pub shape_type: ShapeType,
     pub negative: bool,
+    pub modifiers: Modifiers,
+    // Only used by shapes with children
+    pub domain: Domain,
+    // Only used by shapes with an operation that blends
+    pub blend: Blend,
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -60,7 +97,47 @@ pub enum Primitive {
     Plane,
     Sphere { radius: f32 },
     Cube { size: Vec3 },
+    // Around the z axis
+    Torus { major_radius: f32, minor_radius: f32 },
+    // The following are centered on and aligned with the z axis
+    Cylinder { radius: f32, height: f32 },
+    // The height is the distance between the centers of the caps
+    Capsule { radius: f32, height: f32 },
+    // The apex points up
+    Cone { radius: f32, height: f32 },
+    // The profile is extruded along the z axis
+    Extrusion { profile: Profile, height: f32 },
+    // The profile is revolved around the z axis, with its x axis pointing away from it at the
+    // offset and its y axis along it
+    Revolution { profile: Profile, offset: f32 },
+    // A chain of quadratic Bezier curves, every other point is a control point between the ends
+    // of a curve. The radius changes linearly from the start to the end
+    Tube { points: Vec<Vec3>, start_radius: f32, end_radius: f32 },
+    // The heights are the first channel of the image, from 0 to 1 up the z axis, over a unit
+    // square around the origin. The transform scales it to the size of the terrain
+    Heightfield(Handle<bevy::render::texture::Image>),
     Image(Handle<ShapeImage>),
+    Octree(Handle<ShapeOctree>),
+    // The source is the body of a WGSL function that returns the distance from the point in the
+    // space of the shape, with the arguments pnt: vec3<f32> and parameters: array<vec4<f32>, 4>.
+    // The parameters are passed through the uniform, but changing the source generates a new
+    // shader
+    Custom { source: String, parameters: [Vec4; 4] },
+    // The points come from the MetaballPoints of the entity, their fields add up and the surface
+    // is where the sum reaches the threshold, between 0 and 1. Lower thresholds merge the points
+    // from farther apart
+    Metaballs { threshold: f32 },
+}
+
+// A 2D shape in the xy plane
+#[derive(Clone, PartialEq, Debug)]
+pub enum Profile {
+    Circle { radius: f32 },
+    Rectangle { size: Vec2 },
+    // The corners in order, the last one connects back to the first one
+    Polygon(Vec<Vec2>),
+    // Symmetric around the y axis and open towards the bottom, the angle is the aperture
+    Arc { radius: f32, angle: f32, thickness: f32 },
 }
 
 #[derive(Reflect, FromReflect, Debug, Clone, TypeUuid)]
@@ -77,6 +154,192 @@ pub enum Operation {
     Union,
     Intersection,
     SmoothUnion,
+    // The first child minus the other ones, the cut surface takes the material of the cutter or
+    // keeps the one of the first child
+    Subtraction { cutter_material: bool },
+    SmoothIntersection,
+    SmoothSubtraction { cutter_material: bool },
+    // The corners where the shapes meet are cut off at 45 degrees
+    ChamferUnion,
+    ChamferIntersection,
+    // The corners are filled with steps or columns
+    StairsUnion,
+    StairsIntersection,
+    ColumnsUnion,
+    ColumnsIntersection,
+    // The first child gets a V shaped cut, a groove or a tongue where the other ones cross it,
+    // which keep the material of the first child
+    Engrave,
+    Groove,
+    Tongue,
+    // The distance and the material go from the first child at a t of 0 to the second one at 1
+    Morph,
+}
+
+impl Operation {
+    pub fn subtracts(&self) -> bool {
+        matches!(
+            self,
+            Self::Subtraction { .. } | Self::SmoothSubtraction { .. }
+        )
+    }
+
+    // The first child is the base the other ones are combined with
+    pub fn has_base(&self) -> bool {
+        self.subtracts()
+            || matches!(
+                self,
+                Self::Engrave | Self::Groove | Self::Tongue | Self::Morph
+            )
+    }
+
+    pub fn blends(&self) -> bool {
+        !matches!(
+            self,
+            Self::Union | Self::Intersection | Self::Subtraction { .. }
+        )
+    }
+}
+
+// The parameters of the operations that blend the shapes. The smooth operations blend the
+// distances where they differ by less than k with the kernel, the chamfers, stairs and columns
+// are k wide and the stairs and columns have a count. The engravings, grooves and tongues are
+// deep by the depth and the grooves and tongues are k wide on each side. The morphs are at t
+// between their children. It's passed through the uniform, so changing it doesn't generate a new
+// shader, which also allows animating it
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Blend {
+    pub k: f32,
+    pub kernel: SmoothKernel,
+    pub count: u32,
+    pub depth: f32,
+    pub t: f32,
+}
+
+impl Default for Blend {
+    fn default() -> Self {
+        Self {
+            k: 0.4,
+            kernel: SmoothKernel::Polynomial,
+            count: 4,
+            depth: 0.1,
+            t: 0.5,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum SmoothKernel {
+    Polynomial,
+    Exponential,
+    Circular,
+}
+
+// The following are the same as the functions with the same names in the shader
+impl Blend {
+    // Same as smooth_blend in the shader, without the weight
+    pub(super) fn lowering(&self, difference: f32) -> f32 {
+        let k = self.k.max(0.0001);
+        match self.kernel {
+            SmoothKernel::Polynomial => {
+                let h = (k - difference).max(0.0) / k;
+                h * h * h * k * (1.0 / 2.0 / 3.0)
+            }
+            SmoothKernel::Exponential => {
+                let k = k * (1.0 / 6.0);
+                k * (1.0 + (-difference / k).exp2()).log2()
+            }
+            SmoothKernel::Circular => {
+                let h = (k - difference).max(0.0) / k;
+                k * 0.5 * (1.0 + h - (1.0 - h * (h - 2.0)).sqrt())
+            }
+        }
+    }
+
+    pub(super) fn min(&self, left: f32, right: f32) -> f32 {
+        left.min(right) - self.lowering((left - right).abs())
+    }
+
+    pub(super) fn max(&self, left: f32, right: f32) -> f32 {
+        -self.min(-left, -right)
+    }
+
+    pub(super) fn chamfer_union(&self, left: f32, right: f32) -> f32 {
+        left.min(right).min((left - self.k + right) * FRAC_1_SQRT_2)
+    }
+
+    pub(super) fn chamfer_intersection(&self, left: f32, right: f32) -> f32 {
+        left.max(right).max((left + self.k + right) * FRAC_1_SQRT_2)
+    }
+
+    pub(super) fn stairs_union(&self, left: f32, right: f32) -> f32 {
+        let step = self.k.max(0.0001) / self.count.max(1) as f32;
+        let u = right - self.k;
+        let stairs = 0.5 * (u + left + (floor_mod(u - left + step, 2.0 * step) - step).abs());
+        left.min(right).min(stairs)
+    }
+
+    pub(super) fn stairs_intersection(&self, left: f32, right: f32) -> f32 {
+        -self.stairs_union(-left, -right)
+    }
+
+    pub(super) fn columns_union(&self, left: f32, right: f32) -> f32 {
+        let k = self.k.max(0.0001);
+        if left >= k || right >= k {
+            return left.min(right);
+        }
+        let radius = self.column_radius();
+        let mut pnt = Vec2::new(left + right, right - left) * FRAC_1_SQRT_2;
+        pnt.x += radius * SQRT_2 - FRAC_1_SQRT_2 * k;
+        if self.count % 2 == 1 {
+            pnt.y += radius;
+        }
+        pnt.y = floor_mod(pnt.y + radius, radius * 2.0) - radius;
+        (pnt.length() - radius).min(pnt.x).min(left).min(right)
+    }
+
+    pub(super) fn columns_intersection(&self, left: f32, right: f32) -> f32 {
+        let (left, right) = (-left, -right);
+        let k = self.k.max(0.0001);
+        if left >= k || right >= k {
+            return -left.min(right);
+        }
+        let radius = self.column_radius();
+        let mut pnt = Vec2::new(left + right, right - left) * FRAC_1_SQRT_2;
+        pnt.y += radius;
+        pnt.x -= FRAC_1_SQRT_2 * (k + radius);
+        if self.count % 2 == 1 {
+            pnt.y += radius;
+        }
+        pnt.y = floor_mod(pnt.y + radius, radius * 2.0) - radius;
+        -(radius - pnt.length()).max(pnt.x).min(left).min(right)
+    }
+
+    fn column_radius(&self) -> f32 {
+        let count = self.count.max(1) as f32;
+        self.k.max(0.0001) * SQRT_2 / ((count - 1.0) * 2.0 + SQRT_2)
+    }
+
+    pub(super) fn engrave(&self, left: f32, right: f32) -> f32 {
+        left.max((left + self.depth - right.abs()) * FRAC_1_SQRT_2)
+    }
+
+    pub(super) fn groove(&self, left: f32, right: f32) -> f32 {
+        left.max((left + self.depth).min(self.k - right.abs()))
+    }
+
+    pub(super) fn tongue(&self, left: f32, right: f32) -> f32 {
+        left.min((left - self.depth).max(right.abs() - self.k))
+    }
+
+    pub(super) fn morph(&self, left: f32, right: f32) -> f32 {
+        left + (right - left) * self.t
+    }
+}
+
+// Same as the mod of GLSL, the result has the sign of the divisor
+fn floor_mod(value: f32, divisor: f32) -> f32 {
+    value - divisor * (value / divisor).floor()
 }
 
 impl Default for ShapeType {
@@ -85,6 +348,205 @@ impl Default for ShapeType {
     }
 }
 
+// Applied to the distance of a shape, or of a whole group for shapes with children. The
+// elongation stretches the shape along its local axes, the rounding is subtracted from the
+// distance and a positive onion thickness hollows the shape into a shell
+#[derive(ShaderType, PartialEq, Clone, Copy, Default, Debug)]
+pub struct Modifiers {
+    pub elongation: Vec3,
+    pub rounding: f32,
+    pub onion: f32,
+    // The angle the shape turns around the z axis per unit along it
+    pub twist: f32,
+    // The angle the x axis turns towards the z axis per unit along it
+    pub bend: f32,
+    // How much the shape narrows per unit along the z axis
+    pub taper: f32,
+    // The surface is displaced by value noise
+    pub noise_amplitude: f32,
+    pub noise_frequency: f32,
+}
+
+// The steepest slope of the noise, the smoothstep makes it at most 3 along every axis
+const NOISE_GRADIENT: f32 = 5.196152;
+
+impl Modifiers {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub(super) fn deforms(&self) -> bool {
+        self.twist != 0.0 || self.bend != 0.0 || self.taper != 0.0
+    }
+
+    // Same as elongate in the shader
+    pub(super) fn elongate(&self, pnt: Vec3) -> Vec3 {
+        pnt - pnt.clamp(-self.elongation, self.elongation)
+    }
+
+    // Same as deform in the shader
+    pub(super) fn deform(&self, pnt: Vec3) -> Vec3 {
+        let twisted = rotate(pnt.truncate(), -self.twist * pnt.z).extend(pnt.z);
+        let bent = rotate(Vec2::new(twisted.x, twisted.z), -self.bend * twisted.x);
+        let pnt = Vec3::new(bent.x, twisted.y, bent.y);
+        (pnt.truncate() * (1.0 + self.taper * pnt.z)).extend(pnt.z)
+    }
+
+    // Same as modify in the shader, the point is the one before deform
+    pub(super) fn modify(&self, distance: f32, pnt: Vec3) -> f32 {
+        let mut displaced = distance;
+        if self.noise_amplitude != 0.0 {
+            displaced += self.noise_amplitude * noise(pnt * self.noise_frequency);
+        }
+        let rounded = displaced - self.rounding;
+        let shell = if self.onion > 0.0 {
+            rounded.abs() - self.onion / 2.0
+        } else {
+            rounded
+        };
+        self.lipschitz_correct(shell, pnt)
+    }
+
+    // Same as lipschitz_correct in the shader
+    fn lipschitz_correct(&self, distance: f32, pnt: Vec3) -> f32 {
+        let radius = pnt.length() + distance.abs();
+        let stretch = (1.0 + self.twist.abs() * radius)
+            * (1.0 + self.bend.abs() * radius)
+            * (1.0 + 2.0 * self.taper.abs() * radius)
+            + (self.noise_amplitude * self.noise_frequency).abs() * NOISE_GRADIENT;
+        distance / stretch
+    }
+
+    // How far the surface can move outwards before the deformations, apart from the elongation
+    pub(super) fn padding(&self) -> f32 {
+        self.rounding.max(0.0) + self.onion.max(0.0) / 2.0 + self.noise_amplitude.abs()
+    }
+
+    // The bounds of the points deform moves into the given bounds, in the reverse order of deform
+    pub(super) fn deform_bounds(&self, bounds: (Vec3, Vec3)) -> Option<(Vec3, Vec3)> {
+        let (mut min, mut max) = bounds;
+        if self.taper != 0.0 {
+            // The shape gets infinitely wide where the taper scales the point to zero
+            let scale_min = (1.0 + self.taper * min.z).min(1.0 + self.taper * max.z);
+            if scale_min <= 0.0 {
+                return None;
+            }
+            let extent = min.truncate().abs().max(max.truncate().abs()) / scale_min;
+            (min, max) = ((-extent).extend(min.z), extent.extend(max.z));
+        }
+        if self.bend != 0.0 {
+            let radius = Vec2::new(min.x.abs().max(max.x.abs()), min.z.abs().max(max.z.abs()));
+            let radius = radius.length();
+            (min, max) = (
+                Vec3::new(-radius, min.y, -radius),
+                Vec3::new(radius, max.y, radius),
+            );
+        }
+        if self.twist != 0.0 {
+            let radius = min.truncate().abs().max(max.truncate().abs()).length();
+            (min, max) = (
+                Vec3::new(-radius, -radius, min.z),
+                Vec3::new(radius, radius, max.z),
+            );
+        }
+        Some((min, max))
+    }
+}
+
+fn rotate(pnt: Vec2, angle: f32) -> Vec2 {
+    Vec2::from_angle(angle).rotate(pnt)
+}
+
+// Same as noise in the shader
+fn noise(pnt: Vec3) -> f32 {
+    let cell = pnt.floor();
+    let f = pnt - cell;
+    let w = f * f * (3.0 - 2.0 * f);
+    let cell = cell.as_ivec3();
+    let hash = |x, y, z| noise_hash(cell + IVec3::new(x, y, z));
+    let lerp = |from: f32, to: f32, factor: f32| from + (to - from) * factor;
+    let x00 = lerp(hash(0, 0, 0), hash(1, 0, 0), w.x);
+    let x10 = lerp(hash(0, 1, 0), hash(1, 1, 0), w.x);
+    let x01 = lerp(hash(0, 0, 1), hash(1, 0, 1), w.x);
+    let x11 = lerp(hash(0, 1, 1), hash(1, 1, 1), w.x);
+    lerp(lerp(x00, x10, w.y), lerp(x01, x11, w.y), w.z)
+}
+
+fn noise_hash(cell: IVec3) -> f32 {
+    let cell = cell.as_uvec3();
+    let mut hash = cell.x.wrapping_mul(73856093)
+        ^ cell.y.wrapping_mul(19349663)
+        ^ cell.z.wrapping_mul(83492791);
+    hash = (hash ^ (hash >> 16)).wrapping_mul(0x45d9f3b);
+    hash = (hash ^ (hash >> 16)).wrapping_mul(0x45d9f3b);
+    hash ^= hash >> 16;
+    (hash >> 8) as f32 / 8388607.5 - 1.0
+}
+
+// Transforms the point the shapes of a group are evaluated at, in the local space of the group.
+// The point is first mirrored across the local planes, then repeated around the z axis and then
+// repeated along the axes with a spacing. A count of zero repeats infinitely along an axis
+#[derive(PartialEq, Clone, Copy, Default, Debug)]
+pub struct Domain {
+    pub mirror: BVec3,
+    pub spacing: Vec3,
+    pub count: UVec3,
+    pub polar_count: u32,
+}
+
+impl Domain {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    // Same as fold_domain in the shader
+    pub(super) fn fold(&self, pnt: Vec3) -> Vec3 {
+        let mut pnt = Vec3::select(self.mirror, pnt.abs(), pnt);
+
+        if self.polar_count > 0 {
+            let sector = TAU / self.polar_count as f32;
+            let angle = pnt.y.atan2(pnt.x);
+            let angle = angle - sector * (angle / sector).round();
+            let radius = pnt.truncate().length();
+            pnt = Vec3::new(radius * angle.cos(), radius * angle.sin(), pnt.z);
+        }
+
+        let repeated = self.spacing.cmpgt(Vec3::ZERO);
+        let finite = self.count.cmpgt(UVec3::ZERO);
+        let spacing = Vec3::select(repeated, self.spacing, Vec3::ONE);
+        let half_count = Vec3::select(finite, (self.count.as_vec3() - 1.0) / 2.0, Vec3::ZERO);
+        let cell = (pnt / spacing + half_count).round() - half_count;
+        let cell = Vec3::select(finite, cell.clamp(-half_count, half_count), cell);
+        Vec3::select(repeated, pnt - spacing * cell, pnt)
+    }
+}
+
+impl Profile {
+    // Half the size of a box around the origin containing the profile
+    pub fn half_size(&self) -> Vec2 {
+        match self {
+            Profile::Circle { radius } => Vec2::splat(*radius),
+            Profile::Rectangle { size } => *size / 2.0,
+            Profile::Polygon(points) => points
+                .iter()
+                .fold(Vec2::ZERO, |half_size, point| half_size.max(point.abs())),
+            Profile::Arc {
+                radius, thickness, ..
+            } => Vec2::splat(radius + thickness / 2.0),
+        }
+    }
+
+    // Same order as in sdf_profile
+    fn kind(&self) -> u32 {
+        match self {
+            Profile::Circle { .. } => 0,
+            Profile::Rectangle { .. } => 1,
+            Profile::Polygon(..) => 2,
+            Profile::Arc { .. } => 3,
+        }
+    }
+}
+
 #[derive(ShaderType, PartialEq, Clone, Debug)]
 pub struct Material {
     pub color: Vec3,
@@ -101,7 +563,11 @@ struct ExtractedShape {
     shape_type: ShapeType,
     children: Option<Vec<Entity>>,
     negative: bool,
+    modifiers: Modifiers,
+    domain: Domain,
+    blend: Blend,
     transform: GlobalTransform,
+    metaball_points: Vec<MetaballPoint>,
 }
 
 impl ExtractComponent for ExtractedShape {
@@ -109,16 +575,23 @@ impl ExtractComponent for ExtractedShape {
         &'static Shape,
         &'static GlobalTransform,
         Option<&'static Children>,
+        Option<&'static MetaballPoints>,
     );
     type Filter = ();
     type Out = Self;
 
-    fn extract_component((shape, transform, children): QueryItem<'_, Self::Query>) -> Option<Self> {
+    fn extract_component(
+        (shape, transform, children, metaball_points): QueryItem<'_, Self::Query>,
+    ) -> Option<Self> {
         Some(Self {
             shape_type: shape.shape_type.clone(),
-            children: children.map(|children| children.iter().map(|entity| *entity).collect()),
+            children: children.map(|children| children.iter().copied().collect()),
             negative: shape.negative,
-            transform: transform.clone(),
+            modifiers: shape.modifiers,
+            domain: shape.domain,
+            blend: shape.blend,
+            transform: *transform,
+            metaball_points: metaball_points.map_or_else(Vec::new, |points| points.0.clone()),
         })
     }
 }
@@ -161,12 +634,12 @@ impl RenderAsset for ShapeImage {
             queue,
             &TextureDescriptor {
                 label: "shape_texture".into(),
-                size: image.resolution.clone(),
+                size: image.resolution,
                 mip_level_count: 1,
                 sample_count: 1,
                 dimension: TextureDimension::D3,
                 format: TextureFormat::R32Float,
-                usage: TextureUsages::TEXTURE_BINDING,
+                usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
                 view_formats: &[],
             },
             unsafe {
@@ -185,27 +658,201 @@ impl RenderAsset for ShapeImage {
     }
 }
 
+impl ShapeTexture {
+    fn write_region(&self, queue: &RenderQueue, region: TexelRegion, data: &[f32]) {
+        let extent = region.extent();
+        queue.write_texture(
+            ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: Origin3d {
+                    x: region.min[0],
+                    y: region.min[1],
+                    z: region.min[2],
+                },
+                aspect: TextureAspect::All,
+            },
+            unsafe { std::slice::from_raw_parts(data.as_ptr() as *const u8, data.len() * 4) },
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(extent.width * 4),
+                rows_per_image: NonZeroU32::new(extent.height),
+            },
+            extent,
+        );
+    }
+}
+
+enum ExtractedShapeImage {
+    Image(ShapeImage),
+    Region(TexelRegion, Vec<f32>),
+}
+
+#[derive(Resource, Default)]
+struct ExtractedShapeImages {
+    extracted: Vec<(Handle<ShapeImage>, ExtractedShapeImage)>,
+    removed: Vec<Handle<ShapeImage>>,
+}
+
+fn clear_shape_image_edits(mut edits: ResMut<ShapeImageEdits>) {
+    edits.clear();
+}
+
+fn extract_shape_images(
+    mut commands: Commands,
+    mut events: Extract<EventReader<AssetEvent<ShapeImage>>>,
+    images: Extract<Res<Assets<ShapeImage>>>,
+    edits: Extract<Res<ShapeImageEdits>>,
+    textures: Res<RenderAssets<ShapeImage>>,
+) {
+    let mut changed = HashSet::new();
+    let mut removed = Vec::new();
+    for event in events.iter() {
+        match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => {
+                changed.insert(handle.clone_weak());
+            }
+            AssetEvent::Removed { handle } => {
+                changed.remove(handle);
+                removed.push(handle.clone_weak());
+            }
+        }
+    }
+
+    let mut extracted = Vec::new();
+    for handle in changed.drain() {
+        let Some(image) = images.get(&handle) else {
+            continue;
+        };
+        let texture = textures
+            .get(&handle)
+            .filter(|texture| texture.resolution == image.resolution);
+        let extracted_image = match (edits.get(&handle), texture) {
+            (Some(region), Some(_)) => {
+                ExtractedShapeImage::Region(region, image.region_data(region))
+            }
+            _ => ExtractedShapeImage::Image(image.extract_asset()),
+        };
+        extracted.push((handle, extracted_image));
+    }
+
+    commands.insert_resource(ExtractedShapeImages { extracted, removed });
+}
+
+fn prepare_shape_images(
+    mut extracted_images: ResMut<ExtractedShapeImages>,
+    mut textures: ResMut<RenderAssets<ShapeImage>>,
+    device: Res<RenderDevice>,
+    queue: Res<RenderQueue>,
+) {
+    for removed in std::mem::take(&mut extracted_images.removed) {
+        textures.remove(&removed);
+    }
+
+    let mut param = (device, queue);
+    for (handle, extracted_image) in std::mem::take(&mut extracted_images.extracted) {
+        match extracted_image {
+            ExtractedShapeImage::Image(image) => {
+                if let Ok(texture) = ShapeImage::prepare_asset(image, &mut param) {
+                    textures.insert(handle, texture);
+                }
+            }
+            ExtractedShapeImage::Region(region, data) => {
+                if let Some(texture) = textures.get(&handle) {
+                    texture.write_region(&param.1, region, &data);
+                }
+            }
+        }
+    }
+}
+
+// The limits keep the uniform below the 16 KiB WebGL2 guarantees, see the assertion below
 pub const MAX_PLANES: u8 = 4;
 pub const MAX_SPHERES: u8 = 24;
 pub const MAX_CUBES: u8 = 24;
+pub const MAX_TORI: u8 = 2;
+pub const MAX_CYLINDERS: u8 = 2;
+pub const MAX_CAPSULES: u8 = 2;
+pub const MAX_CONES: u8 = 2;
 pub const MAX_IMAGES: u8 = 4;
+pub const MAX_OCTREES: u8 = 2;
+pub const MAX_EXTRUSIONS: u8 = 2;
+pub const MAX_REVOLUTIONS: u8 = 2;
+pub const MAX_TUBES: u8 = 2;
+pub const MAX_HEIGHTFIELDS: u8 = 2;
+pub const MAX_CUSTOMS: u8 = 2;
+pub const MAX_METABALLS: u8 = 2;
+pub const MAX_MODIFIED_GROUPS: u8 = 4;
+pub const MAX_DOMAIN_GROUPS: u8 = 4;
+pub const MAX_BLEND_GROUPS: u8 = 8;
+// The corners of all the polygon profiles and the points of all the tubes
+pub const MAX_POINTS: u8 = 96;
+// The smallest scale of the distance of tapered tubes
+const MIN_TUBE_TAPER_SCALE: f32 = 0.1;
 
 pub const MAX_TEXTURES: u8 = 2;
+pub const MAX_HEIGHTFIELD_TEXTURES: u8 = 2;
 
-#[derive(ShaderType, Clone, Default)]
+#[derive(ShaderType, Clone)]
 struct ShapesUniform {
     planes: [Plane; MAX_PLANES as usize],
     spheres: [Sphere; MAX_SPHERES as usize],
     cubes: [Cube; MAX_CUBES as usize],
+    tori: [Torus; MAX_TORI as usize],
+    cylinders: [Cylinder; MAX_CYLINDERS as usize],
+    capsules: [Capsule; MAX_CAPSULES as usize],
+    cones: [Cone; MAX_CONES as usize],
     images: [Image; MAX_IMAGES as usize],
+    octrees: [Octree; MAX_OCTREES as usize],
+    extrusions: [Extrusion; MAX_EXTRUSIONS as usize],
+    revolutions: [Revolution; MAX_REVOLUTIONS as usize],
+    tubes: [Tube; MAX_TUBES as usize],
+    heightfields: [Heightfield; MAX_HEIGHTFIELDS as usize],
+    customs: [Custom; MAX_CUSTOMS as usize],
+    metaballs: [Metaballs; MAX_METABALLS as usize],
+    points: [Vec4; MAX_POINTS as usize],
+    modified_groups: [ModifiedGroup; MAX_MODIFIED_GROUPS as usize],
+    domain_groups: [DomainGroup; MAX_DOMAIN_GROUPS as usize],
+    blend_groups: [BlendGroup; MAX_BLEND_GROUPS as usize],
     texture_properties: [TextureProperties; MAX_TEXTURES as usize],
 }
 
+const _: () = assert!(ShapesUniform::SHADER_SIZE.get() <= 16384);
+
+// Arrays this long don't implement Default
+impl Default for ShapesUniform {
+    fn default() -> Self {
+        Self {
+            planes: default(),
+            spheres: default(),
+            cubes: default(),
+            tori: default(),
+            cylinders: default(),
+            capsules: default(),
+            cones: default(),
+            images: default(),
+            octrees: default(),
+            extrusions: default(),
+            revolutions: default(),
+            tubes: default(),
+            heightfields: default(),
+            customs: default(),
+            metaballs: default(),
+            points: [Vec4::ZERO; MAX_POINTS as usize],
+            modified_groups: default(),
+            domain_groups: default(),
+            blend_groups: default(),
+            texture_properties: default(),
+        }
+    }
+}
+
 #[derive(ShaderType, Clone, Default)]
 struct Plane {
     pub inv_transform: Mat4,
     pub scale: f32,
     pub material: Material,
+    pub modifiers: Modifiers,
 }
 
 #[derive(ShaderType, Clone, Default)]
@@ -214,6 +861,7 @@ struct Sphere {
     pub inv_transform: Mat4,
     pub scale: f32,
     pub material: Material,
+    pub modifiers: Modifiers,
 }
 
 #[derive(ShaderType, Clone, Default)]
@@ -222,6 +870,47 @@ struct Cube {
     pub inv_transform: Mat4,
     pub scale: f32,
     pub material: Material,
+    pub modifiers: Modifiers,
+}
+
+#[derive(ShaderType, Clone, Default)]
+struct Torus {
+    pub major_radius: f32,
+    pub minor_radius: f32,
+    pub inv_transform: Mat4,
+    pub scale: f32,
+    pub material: Material,
+    pub modifiers: Modifiers,
+}
+
+#[derive(ShaderType, Clone, Default)]
+struct Cylinder {
+    pub radius: f32,
+    pub half_height: f32,
+    pub inv_transform: Mat4,
+    pub scale: f32,
+    pub material: Material,
+    pub modifiers: Modifiers,
+}
+
+#[derive(ShaderType, Clone, Default)]
+struct Capsule {
+    pub radius: f32,
+    pub half_height: f32,
+    pub inv_transform: Mat4,
+    pub scale: f32,
+    pub material: Material,
+    pub modifiers: Modifiers,
+}
+
+#[derive(ShaderType, Clone, Default)]
+struct Cone {
+    pub radius: f32,
+    pub half_height: f32,
+    pub inv_transform: Mat4,
+    pub scale: f32,
+    pub material: Material,
+    pub modifiers: Modifiers,
 }
 
 #[derive(ShaderType, Clone, Default)]
@@ -229,6 +918,132 @@ struct Image {
     inv_transform: Mat4,
     scale: f32,
     material: Material,
+    modifiers: Modifiers,
+}
+
+#[derive(ShaderType, Clone, Default)]
+struct Octree {
+    inv_transform: Mat4,
+    scale: f32,
+    material: Material,
+    size: f32,
+    node_offset: u32,
+    sample_offset: u32,
+    modifiers: Modifiers,
+}
+
+#[derive(ShaderType, Clone, Default)]
+struct Extrusion {
+    profile: ProfileUniform,
+    half_height: f32,
+    inv_transform: Mat4,
+    scale: f32,
+    material: Material,
+    modifiers: Modifiers,
+}
+
+#[derive(ShaderType, Clone, Default)]
+struct Revolution {
+    profile: ProfileUniform,
+    offset: f32,
+    inv_transform: Mat4,
+    scale: f32,
+    material: Material,
+    modifiers: Modifiers,
+}
+
+#[derive(ShaderType, Clone, Default)]
+struct Tube {
+    start_radius: f32,
+    end_radius: f32,
+    taper_scale: f32,
+    point_offset: u32,
+    segment_count: u32,
+    inv_transform: Mat4,
+    scale: f32,
+    material: Material,
+    modifiers: Modifiers,
+}
+
+#[derive(ShaderType, Clone, Default)]
+struct Heightfield {
+    texture_index: u32,
+    height_scale: f32,
+    inv_transform: Mat4,
+    scale: f32,
+    material: Material,
+    modifiers: Modifiers,
+}
+
+#[derive(ShaderType, Clone, Default)]
+struct Custom {
+    parameters: [Vec4; 4],
+    inv_transform: Mat4,
+    scale: f32,
+    material: Material,
+    modifiers: Modifiers,
+}
+
+#[derive(ShaderType, Clone, Default)]
+struct Metaballs {
+    threshold: f32,
+    point_offset: u32,
+    point_count: u32,
+    inv_transform: Mat4,
+    scale: f32,
+    material: Material,
+    modifiers: Modifiers,
+}
+
+// The parameters are the radius of a circle, half the size of a rectangle, or the radius, half
+// the angle and half the thickness of an arc
+#[derive(ShaderType, Clone, Default)]
+struct ProfileUniform {
+    kind: u32,
+    parameters: Vec3,
+    point_offset: u32,
+    point_count: u32,
+}
+
+#[derive(ShaderType, Clone, Default)]
+struct ModifiedGroup {
+    transform: Mat4,
+    inv_transform: Mat4,
+    scale: f32,
+    modifiers: Modifiers,
+}
+
+#[derive(ShaderType, Clone, Default)]
+struct DomainGroup {
+    transform: Mat4,
+    inv_transform: Mat4,
+    mirror: UVec3,
+    spacing: Vec3,
+    count: UVec3,
+    polar_count: u32,
+}
+
+#[derive(ShaderType, Clone, Default)]
+struct BlendGroup {
+    k: f32,
+    kernel: u32,
+    count: u32,
+    depth: f32,
+    t: f32,
+    _padding: Vec3,
+}
+
+impl BlendGroup {
+    fn new(blend: &Blend) -> Self {
+        Self {
+            k: blend.k,
+            kernel: blend.kernel as u32,
+            count: blend.count,
+            depth: blend.depth,
+            t: blend.t,
+            _padding: Vec3::ZERO,
+        }
+    }
 }
 
 #[derive(ShaderType, Clone, Default)]
@@ -245,10 +1060,29 @@ pub struct ShapeGroup {
     pub plane_index_range: Range<u8>,
     pub sphere_index_range: Range<u8>,
     pub cube_index_range: Range<u8>,
+    pub torus_index_range: Range<u8>,
+    pub cylinder_index_range: Range<u8>,
+    pub capsule_index_range: Range<u8>,
+    pub cone_index_range: Range<u8>,
     pub image_index_range: Range<u8>,
+    pub octree_index_range: Range<u8>,
+    pub extrusion_index_range: Range<u8>,
+    pub revolution_index_range: Range<u8>,
+    pub tube_index_range: Range<u8>,
+    pub heightfield_index_range: Range<u8>,
+    pub custom_index_range: Range<u8>,
+    // The sources of the custom shapes in the index range
+    pub custom_sources: Vec<String>,
+    pub metaballs_index_range: Range<u8>,
     pub children: Vec<Self>,
     pub operation: Operation,
     pub negative: bool,
+    // The index of the modifiers of the group
+    pub modifiers: Option<u8>,
+    // The index of the domain of the group
+    pub domain: Option<u8>,
+    // The index of the blend of the group, if its operation blends
+    pub blend: Option<u8>,
 }
 
 #[derive(Default)]
@@ -256,7 +1090,43 @@ struct ShapeIndices {
     plane: u8,
     sphere: u8,
     cube: u8,
+    torus: u8,
+    cylinder: u8,
+    capsule: u8,
+    cone: u8,
     image: u8,
+    octree: u8,
+    extrusion: u8,
+    revolution: u8,
+    tube: u8,
+    heightfield: u8,
+    custom: u8,
+    metaballs: u8,
+    point: u8,
+    modified_group: u8,
+    domain_group: u8,
+    blend_group: u8,
+}
+
+// What the shapes get added into while preparing them
+struct ShapeContext<'a> {
+    uniform: ShapesUniform,
+    indices: ShapeIndices,
+    images: &'a mut ShapeImages,
+    octrees: &'a mut ShapeOctrees,
+    heightfields: &'a mut ShapeHeightfields,
+    metaballs: &'a mut ShapeMetaballs,
+    custom_sources: Vec<String>,
+}
+
+// The resources the shapes are written into every frame
+#[derive(SystemParam)]
+struct PreparedShapes<'w> {
+    uniform_buffer: ResMut<'w, ShapesUniformBuffer>,
+    images: ResMut<'w, ShapeImages>,
+    octrees: ResMut<'w, ShapeOctrees>,
+    heightfields: ResMut<'w, ShapeHeightfields>,
+    metaballs: ResMut<'w, ShapeMetaballs>,
 }
 
 fn prepare_shapes(
@@ -264,26 +1134,58 @@ fn prepare_shapes(
     roots: Query<Entity, With<RootShape>>,
     shapes: Query<&ExtractedShape>,
     images: Res<RenderAssets<ShapeImage>>,
-    device: Res<RenderDevice>,
-    queue: Res<RenderQueue>,
-    mut uniform_buffer: ResMut<ShapesUniformBuffer>,
-    mut shape_images: ResMut<ShapeImages>,
+    octrees: Res<RenderAssets<ShapeOctree>>,
+    (device, queue): (Res<RenderDevice>, Res<RenderQueue>),
+    prepared: PreparedShapes,
 ) {
+    let PreparedShapes {
+        mut uniform_buffer,
+        images: mut shape_images,
+        octrees: mut shape_octrees,
+        heightfields: mut shape_heightfields,
+        metaballs: mut shape_metaballs,
+    } = prepared;
     shape_images.handles.clear();
     shape_images.indices.clear();
+    shape_octrees.clear();
+    shape_heightfields.clear();
+    shape_metaballs.clear();
+
+    let mut context = ShapeContext {
+        uniform: ShapesUniform::default(),
+        indices: ShapeIndices::default(),
+        images: &mut shape_images,
+        octrees: &mut shape_octrees,
+        heightfields: &mut shape_heightfields,
+        metaballs: &mut shape_metaballs,
+        custom_sources: Vec::new(),
+    };
 
-    let mut uniform = ShapesUniform::default();
-    let mut indices = ShapeIndices::default();
-
+    // The root is a union, so it always fits
     let root_group = create_group(
         &shapes,
         &ExtractedShape::default(),
         roots.iter(),
-        &mut uniform,
-        &mut shape_images,
-        &mut indices,
+        &mut context,
     );
-    commands.insert_resource(root_group);
+    let ShapeContext {
+        mut uniform,
+        indices,
+        ..
+    } = context;
+    if let Some(root_group) = root_group {
+        commands.insert_resource(root_group);
+    }
+
+    shape_octrees.prepare(&octrees, &device, &queue);
+    shape_metaballs.prepare(&device, &queue);
+    for index in 0..indices.octree {
+        let offsets = shape_octrees.get_offsets(index);
+        let octree = &mut uniform.octrees[index as usize];
+        octree.size = offsets.size;
+        octree.node_offset = offsets.node_offset;
+        octree.sample_offset = offsets.sample_offset;
+    }
 
     for (index, handle) in shape_images.handles.iter().enumerate() {
         let texture = match images.get(handle) {
@@ -305,102 +1207,232 @@ fn prepare_shapes(
     }
 
     uniform_buffer.0.set(uniform);
-    uniform_buffer.0.write_buffer(&*device, &*queue);
+    uniform_buffer.0.write_buffer(&device, &queue);
 }
 
 fn create_group<T>(
     shapes: &Query<&ExtractedShape>,
     shape: &ExtractedShape,
     children: T,
-    uniform: &mut ShapesUniform,
-    images: &mut ShapeImages,
-    indices: &mut ShapeIndices,
-) -> ShapeGroup
+    context: &mut ShapeContext,
+) -> Option<ShapeGroup>
 where
     T: IntoIterator,
     T::Item: Borrow<Entity>,
 {
+    let operation = match shape.shape_type {
+        ShapeType::Primitive(..) => Operation::Union,
+        ShapeType::Compound(operation) => operation,
+    };
+
+    // The groups that don't fit are left out
+    let blend = if !operation.blends() {
+        None
+    } else if context.indices.blend_group == MAX_BLEND_GROUPS {
+        warn!("Too many blended groups are in the scene");
+        return None;
+    } else {
+        context.uniform.blend_groups[context.indices.blend_group as usize] =
+            BlendGroup::new(&shape.blend);
+        context.indices.blend_group += 1;
+        Some(context.indices.blend_group - 1)
+    };
+
     // Saving the starting indices
-    let mut plane_index_range = indices.plane..indices.plane;
-    let mut sphere_index_range = indices.sphere..indices.sphere;
-    let mut cube_index_range = indices.cube..indices.cube;
-    let mut image_index_range = indices.image..indices.image;
+    let mut plane_index_range = context.indices.plane..context.indices.plane;
+    let mut sphere_index_range = context.indices.sphere..context.indices.sphere;
+    let mut cube_index_range = context.indices.cube..context.indices.cube;
+    let mut torus_index_range = context.indices.torus..context.indices.torus;
+    let mut cylinder_index_range = context.indices.cylinder..context.indices.cylinder;
+    let mut capsule_index_range = context.indices.capsule..context.indices.capsule;
+    let mut cone_index_range = context.indices.cone..context.indices.cone;
+    let mut image_index_range = context.indices.image..context.indices.image;
+    let mut octree_index_range = context.indices.octree..context.indices.octree;
+    let mut extrusion_index_range = context.indices.extrusion..context.indices.extrusion;
+    let mut revolution_index_range = context.indices.revolution..context.indices.revolution;
+    let mut tube_index_range = context.indices.tube..context.indices.tube;
+    let mut heightfield_index_range = context.indices.heightfield..context.indices.heightfield;
+    let mut custom_index_range = context.indices.custom..context.indices.custom;
+    let mut metaballs_index_range = context.indices.metaballs..context.indices.metaballs;
+
+    // Adding the shape if it has one
+    if let ShapeType::Primitive(primitive, material) = &shape.shape_type {
+        add_primitive(
+            context,
+            &shape.transform,
+            primitive,
+            &shape.metaball_points,
+            material,
+            &Modifiers::default(),
+            false,
+        );
+    }
 
-    // Calculating the operation and adding the shape if it has one
-    let (operation, negative) = {
-        let ExtractedShape {
-            shape_type,
-            transform,
-            negative,
-            ..
-        } = shape;
+    // The modifiers of a shape with children apply to the whole group
+    let modifiers = if shape.modifiers.is_empty() {
+        None
+    } else if context.indices.modified_group == MAX_MODIFIED_GROUPS {
+        warn!("Too many modified groups are in the scene");
+        None
+    } else {
+        let (inv_transform, scale) = get_inverse_transform(&shape.transform, false);
+        context.uniform.modified_groups[context.indices.modified_group as usize] = ModifiedGroup {
+            transform: shape.transform.compute_matrix(),
+            inv_transform,
+            scale,
+            modifiers: shape.modifiers,
+        };
+        context.indices.modified_group += 1;
+        Some(context.indices.modified_group - 1)
+    };
 
-        match shape_type {
-            ShapeType::Primitive(primitive, material) => {
-                add_primitive(
-                    uniform, images, indices, transform, primitive, material, false,
-                );
-                (Operation::Union, *negative)
-            }
-            ShapeType::Compound(operation) => (*operation, *negative),
-        }
+    let domain = if shape.domain.is_empty() {
+        None
+    } else if context.indices.domain_group == MAX_DOMAIN_GROUPS {
+        warn!("Too many groups with a domain are in the scene");
+        None
+    } else {
+        let domain = &shape.domain;
+        context.uniform.domain_groups[context.indices.domain_group as usize] = DomainGroup {
+            transform: shape.transform.compute_matrix(),
+            inv_transform: shape.transform.compute_matrix().inverse(),
+            mirror: UVec3::select(domain.mirror, UVec3::ONE, UVec3::ZERO),
+            spacing: domain.spacing,
+            count: domain.count,
+            polar_count: domain.polar_count,
+        };
+        context.indices.domain_group += 1;
+        Some(context.indices.domain_group - 1)
     };
 
-    // Adding the shapes that don't have children and saving the ones that do
-    let mut groups = Vec::<(&ExtractedShape, &Vec<Entity>)>::new();
-    for shape in shapes.iter_many(children) {
+    // Adding the shapes that don't have children and saving the ones that do. The first child of
+    // an operation with a base is always saved as a group, so it stays the first child of the
+    // group, and the other children of a subtraction are subtracted by negating them
+    let mut groups = Vec::<(&ExtractedShape, &[Entity], bool)>::new();
+    let mut child_count = 0;
+    for (index, shape) in shapes.iter_many(children).enumerate() {
+        child_count += 1;
+        if operation == Operation::Morph && index >= 2 {
+            continue;
+        }
         let ExtractedShape {
             children,
             shape_type,
             transform,
             negative,
+            modifiers,
+            metaball_points,
+            ..
         } = shape;
+        let negative = *negative != (operation.subtracts() && index > 0);
         match children {
+            None if operation.has_base() && index == 0 => {
+                groups.push((shape, &[], negative));
+            }
             None => {
                 if let ShapeType::Primitive(primitive, material) = shape_type {
                     add_primitive(
-                        uniform, images, indices, transform, primitive, material, *negative,
+                        context,
+                        transform,
+                        primitive,
+                        metaball_points,
+                        material,
+                        modifiers,
+                        negative,
                     )
                 }
             }
             Some(children) => {
-                groups.push((shape, children));
+                groups.push((shape, children, negative));
             }
         }
     }
+    // A morph only goes from its first child to its second one, the other children are ignored,
+    // and without two children it's left out
+    if operation == Operation::Morph && child_count < 2 {
+        warn!("A morph needs two children");
+        return None;
+    }
+    if operation == Operation::Morph && child_count > 2 {
+        warn!("Only the first two children of a morph are morphed");
+    }
 
     // Saving the ending indices
-    plane_index_range.end = indices.plane;
-    sphere_index_range.end = indices.sphere;
-    cube_index_range.end = indices.cube;
-    image_index_range.end = indices.image;
-
-    // Converting the shapes with children into groups
-    let children = groups
-        .iter()
-        .map(|(shape, children)| create_group(shapes, shape, *children, uniform, images, indices))
-        .collect::<Vec<_>>();
-
-    ShapeGroup {
+    plane_index_range.end = context.indices.plane;
+    sphere_index_range.end = context.indices.sphere;
+    cube_index_range.end = context.indices.cube;
+    torus_index_range.end = context.indices.torus;
+    cylinder_index_range.end = context.indices.cylinder;
+    capsule_index_range.end = context.indices.capsule;
+    cone_index_range.end = context.indices.cone;
+    image_index_range.end = context.indices.image;
+    octree_index_range.end = context.indices.octree;
+    extrusion_index_range.end = context.indices.extrusion;
+    revolution_index_range.end = context.indices.revolution;
+    tube_index_range.end = context.indices.tube;
+    heightfield_index_range.end = context.indices.heightfield;
+    custom_index_range.end = context.indices.custom;
+    metaballs_index_range.end = context.indices.metaballs;
+    let custom_sources = std::mem::take(&mut context.custom_sources);
+
+    // Converting the shapes with children into groups. An operation whose base is left out is
+    // left out as well, otherwise its first cutter would become the base
+    let mut child_groups = Vec::with_capacity(groups.len());
+    for (index, (shape, children, negative)) in groups.iter().enumerate() {
+        match create_group(shapes, shape, *children, context) {
+            Some(group) => child_groups.push(ShapeGroup {
+                negative: *negative,
+                ..group
+            }),
+            None if operation.has_base() && index == 0 => return None,
+            None => {}
+        }
+    }
+
+    Some(ShapeGroup {
         plane_index_range,
         sphere_index_range,
         cube_index_range,
+        torus_index_range,
+        cylinder_index_range,
+        capsule_index_range,
+        cone_index_range,
         image_index_range,
-        children,
+        octree_index_range,
+        extrusion_index_range,
+        revolution_index_range,
+        tube_index_range,
+        heightfield_index_range,
+        custom_index_range,
+        custom_sources,
+        metaballs_index_range,
+        children: child_groups,
         operation,
-        negative,
-    }
+        negative: shape.negative,
+        modifiers,
+        domain,
+        blend,
+    })
 }
 
 fn add_primitive(
-    uniform: &mut ShapesUniform,
-    images: &mut ShapeImages,
-    indices: &mut ShapeIndices,
+    context: &mut ShapeContext,
     transform: &GlobalTransform,
     primitive: &Primitive,
+    metaball_points: &[MetaballPoint],
     material: &Material,
+    modifiers: &Modifiers,
     negative: bool,
 ) {
+    let ShapeContext {
+        uniform,
+        indices,
+        images,
+        octrees,
+        heightfields,
+        metaballs,
+        custom_sources,
+    } = context;
     match primitive {
         Primitive::Plane => {
             if indices.plane == MAX_PLANES {
@@ -411,6 +1443,7 @@ fn add_primitive(
                     inv_transform,
                     scale,
                     material: material.clone(),
+                    modifiers: *modifiers,
                 };
                 indices.plane += 1;
             }
@@ -425,6 +1458,7 @@ fn add_primitive(
                     inv_transform,
                     scale,
                     material: material.clone(),
+                    modifiers: *modifiers,
                 };
                 indices.sphere += 1;
             }
@@ -439,10 +1473,78 @@ fn add_primitive(
                     inv_transform,
                     scale,
                     material: material.clone(),
+                    modifiers: *modifiers,
                 };
                 indices.cube += 1;
             }
         }
+        Primitive::Torus {
+            major_radius,
+            minor_radius,
+        } => {
+            if indices.torus == MAX_TORI {
+                warn!("Too many tori are in the scene");
+            } else {
+                let (inv_transform, scale) = get_inverse_transform(transform, negative);
+                uniform.tori[indices.torus as usize] = Torus {
+                    major_radius: *major_radius,
+                    minor_radius: *minor_radius,
+                    inv_transform,
+                    scale,
+                    material: material.clone(),
+                    modifiers: *modifiers,
+                };
+                indices.torus += 1;
+            }
+        }
+        Primitive::Cylinder { radius, height } => {
+            if indices.cylinder == MAX_CYLINDERS {
+                warn!("Too many cylinders are in the scene");
+            } else {
+                let (inv_transform, scale) = get_inverse_transform(transform, negative);
+                uniform.cylinders[indices.cylinder as usize] = Cylinder {
+                    radius: *radius,
+                    half_height: *height / 2.0,
+                    inv_transform,
+                    scale,
+                    material: material.clone(),
+                    modifiers: *modifiers,
+                };
+                indices.cylinder += 1;
+            }
+        }
+        Primitive::Capsule { radius, height } => {
+            if indices.capsule == MAX_CAPSULES {
+                warn!("Too many capsules are in the scene");
+            } else {
+                let (inv_transform, scale) = get_inverse_transform(transform, negative);
+                uniform.capsules[indices.capsule as usize] = Capsule {
+                    radius: *radius,
+                    half_height: *height / 2.0,
+                    inv_transform,
+                    scale,
+                    material: material.clone(),
+                    modifiers: *modifiers,
+                };
+                indices.capsule += 1;
+            }
+        }
+        Primitive::Cone { radius, height } => {
+            if indices.cone == MAX_CONES {
+                warn!("Too many cones are in the scene");
+            } else {
+                let (inv_transform, scale) = get_inverse_transform(transform, negative);
+                uniform.cones[indices.cone as usize] = Cone {
+                    radius: *radius,
+                    half_height: *height / 2.0,
+                    inv_transform,
+                    scale,
+                    material: material.clone(),
+                    modifiers: *modifiers,
+                };
+                indices.cone += 1;
+            }
+        }
         Primitive::Image(image) => {
             if indices.image == MAX_IMAGES {
                 warn!("Too many images are in the scene");
@@ -452,15 +1554,221 @@ fn add_primitive(
                     inv_transform,
                     scale,
                     material: material.clone(),
+                    modifiers: *modifiers,
                 };
                 images.add_image(indices.image, image);
                 indices.image += 1;
             }
         }
+        Primitive::Octree(octree) => {
+            if indices.octree == MAX_OCTREES {
+                warn!("Too many octrees are in the scene");
+            } else {
+                let (inv_transform, scale) = get_inverse_transform(transform, negative);
+                uniform.octrees[indices.octree as usize] = Octree {
+                    inv_transform,
+                    scale,
+                    material: material.clone(),
+                    modifiers: *modifiers,
+                    ..default()
+                };
+                octrees.add_octree(octree);
+                indices.octree += 1;
+            }
+        }
+        Primitive::Extrusion { profile, height } => {
+            if indices.extrusion == MAX_EXTRUSIONS {
+                warn!("Too many extrusions are in the scene");
+            } else if let Some(profile) = add_profile(uniform, indices, profile) {
+                let (inv_transform, scale) = get_inverse_transform(transform, negative);
+                uniform.extrusions[indices.extrusion as usize] = Extrusion {
+                    profile,
+                    half_height: *height / 2.0,
+                    inv_transform,
+                    scale,
+                    material: material.clone(),
+                    modifiers: *modifiers,
+                };
+                indices.extrusion += 1;
+            }
+        }
+        Primitive::Revolution { profile, offset } => {
+            if indices.revolution == MAX_REVOLUTIONS {
+                warn!("Too many revolutions are in the scene");
+            } else if let Some(profile) = add_profile(uniform, indices, profile) {
+                let (inv_transform, scale) = get_inverse_transform(transform, negative);
+                uniform.revolutions[indices.revolution as usize] = Revolution {
+                    profile,
+                    offset: *offset,
+                    inv_transform,
+                    scale,
+                    material: material.clone(),
+                    modifiers: *modifiers,
+                };
+                indices.revolution += 1;
+            }
+        }
+        Primitive::Tube {
+            points,
+            start_radius,
+            end_radius,
+        } => {
+            if indices.tube == MAX_TUBES {
+                warn!("Too many tubes are in the scene");
+            } else if let Some(point_offset) = add_points(
+                uniform,
+                indices,
+                points.iter().map(|point| point.extend(0.0)),
+            ) {
+                let (inv_transform, scale) = get_inverse_transform(transform, negative);
+                uniform.tubes[indices.tube as usize] = Tube {
+                    start_radius: *start_radius,
+                    end_radius: *end_radius,
+                    taper_scale: tube_taper_scale(points, *start_radius, *end_radius),
+                    point_offset,
+                    segment_count: points.len().saturating_sub(1) as u32 / 2,
+                    inv_transform,
+                    scale,
+                    material: material.clone(),
+                    modifiers: *modifiers,
+                };
+                indices.tube += 1;
+            }
+        }
+        Primitive::Heightfield(image) => {
+            if indices.heightfield == MAX_HEIGHTFIELDS {
+                warn!("Too many heightfields are in the scene");
+            } else if let Some((texture_index, slope)) = heightfields.add_heightfield(image) {
+                let (inv_transform, scale) = get_inverse_transform(transform, negative);
+                uniform.heightfields[indices.heightfield as usize] = Heightfield {
+                    texture_index,
+                    height_scale: height_scale(transform, slope, scale),
+                    inv_transform,
+                    scale,
+                    material: material.clone(),
+                    modifiers: *modifiers,
+                };
+                indices.heightfield += 1;
+            }
+        }
+        Primitive::Custom { source, parameters } => {
+            if indices.custom == MAX_CUSTOMS {
+                warn!("Too many custom shapes are in the scene");
+            } else {
+                let (inv_transform, scale) = get_inverse_transform(transform, negative);
+                uniform.customs[indices.custom as usize] = Custom {
+                    parameters: *parameters,
+                    inv_transform,
+                    scale,
+                    material: material.clone(),
+                    modifiers: *modifiers,
+                };
+                custom_sources.push(source.clone());
+                indices.custom += 1;
+            }
+        }
+        Primitive::Metaballs { threshold } => {
+            if indices.metaballs == MAX_METABALLS {
+                warn!("Too many metaballs are in the scene");
+            } else {
+                let (inv_transform, scale) = get_inverse_transform(transform, negative);
+                uniform.metaballs[indices.metaballs as usize] = Metaballs {
+                    threshold: clamp_threshold(*threshold),
+                    point_offset: metaballs.add_points(metaball_points, *threshold, material),
+                    point_count: metaball_points.len() as u32,
+                    inv_transform,
+                    scale,
+                    material: material.clone(),
+                    modifiers: *modifiers,
+                };
+                indices.metaballs += 1;
+            }
+        }
     }
 }
 
-fn get_inverse_transform(transform: &GlobalTransform, negative: bool) -> (Mat4, f32) {
+// The distance of a tube uses the radius at the point of the curves closest to a point, which
+// is exact without a taper. A taper makes it overestimate like it would for a cone, so it gets
+// scaled like the distance of a cone with the steepest slope of the radius. Every curve changes
+// the radius by the same amount evenly over its parameter, so the slope is the steepest where a
+// curve moves the slowest. Points about as close to two parts of a strongly bent tube can still
+// be overestimated by up to the radius change between them, and curves with a cusp have no
+// slope bound, so the scale is clamped to keep the tracer moving
+pub(super) fn tube_taper_scale(points: &[Vec3], start_radius: f32, end_radius: f32) -> f32 {
+    let segment_count = points.len().saturating_sub(1) / 2;
+    if segment_count == 0 || start_radius == end_radius {
+        return 1.0;
+    }
+
+    // The derivative of a quadratic Bezier curve is linear, its smallest length is the distance
+    // from the origin to the segment between the derivatives at the ends
+    let min_speed = points
+        .windows(3)
+        .step_by(2)
+        .map(|curve| {
+            let start_velocity = 2.0 * (curve[1] - curve[0]);
+            let change = 2.0 * (curve[2] - curve[1]) - start_velocity;
+            let t = if change.length_squared() > 0.0 {
+                (-start_velocity.dot(change) / change.length_squared()).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            (start_velocity + change * t).length()
+        })
+        .fold(f32::INFINITY, f32::min);
+
+    let slope = (end_radius - start_radius).abs() / segment_count as f32 / min_speed;
+    (1.0 / (1.0 + slope * slope).sqrt()).max(MIN_TUBE_TAPER_SCALE)
+}
+
+fn add_points<T>(uniform: &mut ShapesUniform, indices: &mut ShapeIndices, points: T) -> Option<u32>
+where
+    T: ExactSizeIterator<Item = Vec4>,
+{
+    if indices.point as usize + points.len() > MAX_POINTS as usize {
+        warn!("Too many points are in the scene");
+        return None;
+    }
+    let point_offset = indices.point;
+    for point in points {
+        uniform.points[indices.point as usize] = point;
+        indices.point += 1;
+    }
+    Some(point_offset as u32)
+}
+
+fn add_profile(
+    uniform: &mut ShapesUniform,
+    indices: &mut ShapeIndices,
+    profile: &Profile,
+) -> Option<ProfileUniform> {
+    let (parameters, point_offset, point_count) = match profile {
+        Profile::Circle { radius } => (Vec3::new(*radius, 0.0, 0.0), 0, 0),
+        Profile::Rectangle { size } => ((*size / 2.0).extend(0.0), 0, 0),
+        Profile::Polygon(points) => {
+            let point_offset = add_points(
+                uniform,
+                indices,
+                points.iter().map(|point| point.extend(0.0).extend(0.0)),
+            )?;
+            (Vec3::ZERO, point_offset, points.len() as u32)
+        }
+        Profile::Arc {
+            radius,
+            angle,
+            thickness,
+        } => (Vec3::new(*radius, angle / 2.0, thickness / 2.0), 0, 0),
+    };
+
+    Some(ProfileUniform {
+        kind: profile.kind(),
+        parameters,
+        point_offset,
+        point_count,
+    })
+}
+
+pub(super) fn get_inverse_transform(transform: &GlobalTransform, negative: bool) -> (Mat4, f32) {
     let matrix = transform.affine().matrix3;
     let matrix = SMatrix::<f32, 3, 3>::new(
         matrix.x_axis.x,
@@ -480,10 +1788,6 @@ fn get_inverse_transform(transform: &GlobalTransform, negative: bool) -> (Mat4,
     )
 }
 
-fn point_plane_distance(point: Vec3A, plane1: Vec3A, plane2: Vec3A) -> f32 {
-    (point - plane1 * point.dot(plane1) - plane2 * point.dot(plane2)).length()
-}
-
 #[derive(Resource)]
 pub struct ShapesBindGroupLayout(BindGroupLayout);
 
@@ -531,6 +1835,35 @@ impl FromWorld for ShapesBindGroupLayout {
             });
         }
 
+        // The nodes and samples of the octrees
+        entries.push(data_texture_layout_entry(
+            2 + MAX_TEXTURES as u32,
+            TextureSampleType::Uint,
+        ));
+        entries.push(data_texture_layout_entry(
+            3 + MAX_TEXTURES as u32,
+            TextureSampleType::Float { filterable: false },
+        ));
+
+        for index in 0..MAX_HEIGHTFIELD_TEXTURES {
+            entries.push(BindGroupLayoutEntry {
+                binding: 4 + MAX_TEXTURES as u32 + index as u32,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            });
+        }
+
+        // The points of the metaballs
+        entries.push(data_texture_layout_entry(
+            4 + (MAX_TEXTURES + MAX_HEIGHTFIELD_TEXTURES) as u32,
+            TextureSampleType::Float { filterable: false },
+        ));
+
         Self(device.create_bind_group_layout(&BindGroupLayoutDescriptor {
             label: "shapes_bind_group_layout".into(),
             entries: &entries,
@@ -627,15 +1960,34 @@ impl ShapeImages {
 #[derive(Resource, Deref)]
 pub struct ShapesBindGroup(BindGroup);
 
+// The resources the shapes bind group is made of
+#[derive(SystemParam)]
+struct ShapeBindings<'w> {
+    layout: Res<'w, ShapesBindGroupLayout>,
+    uniform_buffer: Res<'w, ShapesUniformBuffer>,
+    sampler: Res<'w, ShapeSampler>,
+    images: Res<'w, ShapeImages>,
+    octrees: Res<'w, ShapeOctrees>,
+    heightfields: Res<'w, ShapeHeightfields>,
+    metaballs: Res<'w, ShapeMetaballs>,
+}
+
 fn queue_shapes_bind_group(
     mut commands: Commands,
     images: Res<RenderAssets<ShapeImage>>,
-    bind_group_layout: Res<ShapesBindGroupLayout>,
-    uniform_buffer: Res<ShapesUniformBuffer>,
-    sampler: Res<ShapeSampler>,
-    shape_images: Res<ShapeImages>,
+    heightfield_images: Res<RenderAssets<bevy::render::texture::Image>>,
+    bindings: ShapeBindings,
     device: Res<RenderDevice>,
 ) {
+    let ShapeBindings {
+        layout: bind_group_layout,
+        uniform_buffer,
+        sampler,
+        images: shape_images,
+        octrees: shape_octrees,
+        heightfields: shape_heightfields,
+        metaballs: shape_metaballs,
+    } = bindings;
     let mut entries = vec![
         BindGroupEntry {
             binding: 0,
@@ -663,6 +2015,27 @@ fn queue_shapes_bind_group(
         });
     }
 
+    entries.push(BindGroupEntry {
+        binding: 2 + MAX_TEXTURES as u32,
+        resource: BindingResource::TextureView(&shape_octrees.nodes.view),
+    });
+    entries.push(BindGroupEntry {
+        binding: 3 + MAX_TEXTURES as u32,
+        resource: BindingResource::TextureView(&shape_octrees.samples.view),
+    });
+    for index in 0..MAX_HEIGHTFIELD_TEXTURES as usize {
+        entries.push(BindGroupEntry {
+            binding: 4 + (MAX_TEXTURES as usize + index) as u32,
+            resource: BindingResource::TextureView(
+                shape_heightfields.texture_view(&heightfield_images, index),
+            ),
+        });
+    }
+    entries.push(BindGroupEntry {
+        binding: 4 + (MAX_TEXTURES + MAX_HEIGHTFIELD_TEXTURES) as u32,
+        resource: BindingResource::TextureView(&shape_metaballs.texture.view),
+    });
+
     commands.insert_resource(ShapesBindGroup(device.create_bind_group(
         &BindGroupDescriptor {
             label: "shapes_bind_group".into(),
@@ -671,3 +2044,202 @@ fn queue_shapes_bind_group(
         },
     )));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::prelude::Mat3;
+
+    // Points on a skewed grid, so none of them sit on the planes the domain folds at
+    fn points() -> impl Iterator<Item = Vec3> {
+        (0..9 * 9 * 9).map(|index| {
+            let cell = Vec3::new(
+                (index % 9) as f32,
+                (index / 9 % 9) as f32,
+                (index / 81) as f32,
+            );
+            (cell - 4.0) * Vec3::new(0.37, 0.41, 0.29) + Vec3::new(0.013, 0.021, 0.017)
+        })
+    }
+
+    fn assert_near(left: Vec3, right: Vec3) {
+        assert!(left.distance(right) < 1e-4, "{left} != {right}");
+    }
+
+    #[test]
+    fn mirrors_and_repeats_the_domain() {
+        let mirrored = Domain {
+            mirror: BVec3::new(true, false, true),
+            ..default()
+        };
+        let repeated = Domain {
+            spacing: Vec3::new(0.5, 0.0, 0.75),
+            ..default()
+        };
+        for pnt in points() {
+            assert_eq!(Domain::default().fold(pnt), pnt);
+            assert_eq!(
+                mirrored.fold(pnt),
+                mirrored.fold(pnt * Vec3::new(-1.0, 1.0, -1.0))
+            );
+
+            let folded = repeated.fold(pnt);
+            assert!(folded.x.abs() <= 0.25 && folded.z.abs() <= 0.375);
+            assert_eq!(folded.y, pnt.y);
+            assert_near(folded, repeated.fold(pnt + Vec3::new(1.0, 0.0, -0.75)));
+        }
+    }
+
+    #[test]
+    fn repeats_the_domain_a_finite_number_of_times() {
+        let domain = Domain {
+            spacing: Vec3::new(2.0, 2.0, 0.0),
+            count: UVec3::new(3, 2, 0),
+            ..default()
+        };
+        // Three copies at -2, 0 and 2 along x, two at -1 and 1 along y
+        assert_near(
+            domain.fold(Vec3::new(0.5, 0.5, 7.0)),
+            Vec3::new(0.5, -0.5, 7.0),
+        );
+        assert_near(
+            domain.fold(Vec3::new(2.5, -1.5, 0.0)),
+            Vec3::new(0.5, -0.5, 0.0),
+        );
+        assert_near(
+            domain.fold(Vec3::new(10.0, 6.0, 0.0)),
+            Vec3::new(8.0, 5.0, 0.0),
+        );
+        assert_near(
+            domain.fold(Vec3::new(-10.0, -6.0, 0.0)),
+            Vec3::new(-8.0, -5.0, 0.0),
+        );
+    }
+
+    #[test]
+    fn repeats_the_domain_around_the_z_axis() {
+        let domain = Domain {
+            polar_count: 5,
+            ..default()
+        };
+        let sector = Mat3::from_rotation_z(TAU / 5.0);
+        for pnt in points() {
+            let folded = domain.fold(pnt);
+            assert!(folded.y.atan2(folded.x).abs() <= TAU / 10.0 + 1e-5);
+            assert!((folded.truncate().length() - pnt.truncate().length()).abs() < 1e-5);
+            assert_near(folded, domain.fold(sector * pnt));
+        }
+    }
+
+    #[test]
+    fn scales_tapered_tubes_by_the_steepest_slope() {
+        let straight = [
+            Vec3::ZERO,
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, 2.0),
+        ];
+        assert_eq!(tube_taper_scale(&straight, 0.5, 0.5), 1.0);
+        // A cone with a slope of 1 / 2
+        let scale = tube_taper_scale(&straight, 1.0, 0.0);
+        assert!((scale - 1.0 / 1.25f32.sqrt()).abs() < 1e-6);
+
+        // The short curve changes the radius as much as the long one
+        let uneven = [
+            Vec3::ZERO,
+            Vec3::new(0.0, 0.0, 1.5),
+            Vec3::new(0.0, 0.0, 3.0),
+            Vec3::new(0.0, 0.0, 3.25),
+            Vec3::new(0.0, 0.0, 3.5),
+        ];
+        let scale = tube_taper_scale(&uneven, 1.0, 0.0);
+        assert!((scale - 1.0 / 2.0f32.sqrt()).abs() < 1e-6);
+
+        // The control point close to the start makes the curve slow down there
+        let slow = [
+            Vec3::ZERO,
+            Vec3::new(0.0, 0.0, 0.25),
+            Vec3::new(0.0, 0.0, 2.0),
+        ];
+        assert!(tube_taper_scale(&slow, 1.0, 0.0) < tube_taper_scale(&straight, 1.0, 0.0));
+
+        // The curve stops and turns back at its cusp
+        let cusp = [
+            Vec3::ZERO,
+            Vec3::new(0.0, 0.0, 2.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        ];
+        assert_eq!(tube_taper_scale(&cusp, 1.0, 0.0), MIN_TUBE_TAPER_SCALE);
+    }
+
+    #[test]
+    fn deforms_like_the_shader() {
+        for pnt in points() {
+            assert_near(Modifiers::default().deform(pnt), pnt);
+        }
+
+        let twisted = Modifiers {
+            twist: 0.5,
+            ..default()
+        };
+        let pnt = Vec3::new(1.0, 0.0, 2.0);
+        assert_near(twisted.deform(pnt), Mat3::from_rotation_z(-1.0) * pnt);
+
+        let bent = Modifiers {
+            bend: 0.5,
+            ..default()
+        };
+        let pnt = Vec3::new(2.0, 0.3, 0.0);
+        assert_near(bent.deform(pnt), Mat3::from_rotation_y(1.0) * pnt);
+
+        let tapered = Modifiers {
+            taper: 0.25,
+            ..default()
+        };
+        assert_near(
+            tapered.deform(Vec3::new(1.0, -2.0, 2.0)),
+            Vec3::new(1.5, -3.0, 2.0),
+        );
+    }
+
+    #[test]
+    fn bounds_the_deformed_points() {
+        let bounds = (Vec3::new(-0.5, -0.75, -1.0), Vec3::new(1.0, 0.5, 0.75));
+        for modifiers in [
+            Modifiers {
+                twist: 0.8,
+                ..default()
+            },
+            Modifiers {
+                bend: -0.6,
+                ..default()
+            },
+            Modifiers {
+                taper: 0.3,
+                ..default()
+            },
+            Modifiers {
+                twist: 0.4,
+                bend: 0.3,
+                taper: -0.2,
+                ..default()
+            },
+        ] {
+            let (min, max) = modifiers.deform_bounds(bounds).unwrap();
+            for pnt in points() {
+                let deformed = modifiers.deform(pnt);
+                if deformed.cmpge(bounds.0).all() && deformed.cmple(bounds.1).all() {
+                    assert!(pnt.cmpge(min - 1e-5).all() && pnt.cmple(max + 1e-5).all());
+                }
+            }
+        }
+
+        // The taper scales the points at z = -2 down to nothing
+        let tapered = Modifiers {
+            taper: 0.5,
+            ..default()
+        };
+        assert!(tapered
+            .deform_bounds((Vec3::splat(-3.0), Vec3::splat(3.0)))
+            .is_none());
+    }
+}
@@ -0,0 +1,292 @@
+use super::ShapeImage;
+use bevy::{asset::Error, prelude::Vec3};
+use half::f16;
+
+const KTX2_IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+const VK_FORMAT_R16_SFLOAT: u32 = 76;
+const VK_FORMAT_R32_SFLOAT: u32 = 100;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Ktx2Format {
+    R32Float,
+    R16Float,
+}
+
+impl ShapeImage {
+    // A single level 3D texture, the size of the image is stored under the SDFsize key
+    pub fn to_ktx2(&self, format: Ktx2Format) -> Vec<u8> {
+        let (vk_format, type_size) = match format {
+            Ktx2Format::R32Float => (VK_FORMAT_R32_SFLOAT, 4u32),
+            Ktx2Format::R16Float => (VK_FORMAT_R16_SFLOAT, 2u32),
+        };
+
+        let dfd = ktx2_dfd(type_size);
+        let kvd = ktx2_kvd(&[
+            ("KTXwriter", String::from("bevy-ray-marching")),
+            (
+                "SDFsize",
+                format!("{} {} {}", self.size.x, self.size.y, self.size.z),
+            ),
+        ]);
+        let level = match format {
+            Ktx2Format::R32Float => self
+                .data
+                .iter()
+                .flat_map(|value| value.to_le_bytes())
+                .collect::<Vec<_>>(),
+            Ktx2Format::R16Float => self
+                .data
+                .iter()
+                .flat_map(|value| f16::from_f32(*value).to_le_bytes())
+                .collect::<Vec<_>>(),
+        };
+
+        let dfd_offset = 12 + 9 * 4 + 4 * 4 + 2 * 8 + 3 * 8;
+        let kvd_offset = dfd_offset + dfd.len();
+        let level_offset = align(kvd_offset + kvd.len(), 4);
+
+        let mut bytes = Vec::with_capacity(level_offset + level.len());
+        bytes.extend_from_slice(&KTX2_IDENTIFIER);
+        for value in [
+            vk_format,
+            type_size,
+            self.resolution.width,
+            self.resolution.height,
+            self.resolution.depth_or_array_layers,
+            0, // layer count
+            1, // face count
+            1, // level count
+            0, // supercompression scheme
+            dfd_offset as u32,
+            dfd.len() as u32,
+            kvd_offset as u32,
+            kvd.len() as u32,
+        ] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in [
+            0, // supercompression global data offset
+            0, // supercompression global data length
+            level_offset as u64,
+            level.len() as u64,
+            level.len() as u64,
+        ] {
+            bytes.extend_from_slice(&u64::to_le_bytes(value));
+        }
+        bytes.extend_from_slice(&dfd);
+        bytes.extend_from_slice(&kvd);
+        bytes.resize(level_offset, 0);
+        bytes.extend_from_slice(&level);
+        bytes
+    }
+
+    // Slice z of the image as an RGB png, the distances are color mapped relative to the image size
+    pub fn to_png_slice(&self, z: u32) -> Result<Vec<u8>, Error> {
+        let width = self.resolution.width;
+        let height = self.resolution.height;
+        let scale = self.size.max_element();
+
+        let mut pixels = Vec::with_capacity((width * height * 3) as usize);
+        for y in (0..height).rev() {
+            for x in 0..width {
+                let color = distance_color(self.texel(x, y, z) / scale);
+                pixels.extend_from_slice(&[
+                    (color.x * 255.0).round() as u8,
+                    (color.y * 255.0).round() as u8,
+                    (color.z * 255.0).round() as u8,
+                ]);
+            }
+        }
+
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, width, height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&pixels)?;
+        writer.finish()?;
+        Ok(bytes)
+    }
+
+    pub fn to_png_slices(&self) -> Result<Vec<Vec<u8>>, Error> {
+        (0..self.resolution.depth_or_array_layers)
+            .map(|z| self.to_png_slice(z))
+            .collect()
+    }
+}
+
+// Orange outside, blue inside, with rings every 1/32 of the image size and a white surface
+fn distance_color(distance: f32) -> Vec3 {
+    let color = if distance > 0.0 {
+        Vec3::new(0.9, 0.6, 0.3)
+    } else {
+        Vec3::new(0.65, 0.85, 1.0)
+    };
+    let color = color * (1.0 - (-12.0 * distance.abs()).exp());
+    let color = color * (0.8 + 0.2 * (distance * 64.0 * std::f32::consts::PI).cos());
+    let surface = 1.0 - (distance.abs() / 0.005).clamp(0.0, 1.0);
+    color.lerp(Vec3::ONE, surface).clamp(Vec3::ZERO, Vec3::ONE)
+}
+
+// Basic data format descriptor of a single channel signed float format
+fn ktx2_dfd(type_size: u32) -> Vec<u8> {
+    let words: [u32; 11] = [
+        // total size, vendor id and descriptor type, version number and block size
+        44,
+        0,
+        2 | 40 << 16,
+        // RGBSDA color model, BT709 primaries, linear transfer function
+        1 | 1 << 8 | 1 << 16,
+        // texel block dimensions and bytes per plane
+        0,
+        type_size,
+        0,
+        // signed float red channel with a sample range of -1.0 to 1.0
+        (type_size * 8 - 1) << 16 | 0xC0 << 24,
+        0,
+        0xBF800000,
+        0x3F800000,
+    ];
+    words.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
+
+fn ktx2_kvd(entries: &[(&str, String)]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for (key, value) in entries {
+        let length = key.len() + 1 + value.len() + 1;
+        bytes.extend_from_slice(&(length as u32).to_le_bytes());
+        bytes.extend_from_slice(key.as_bytes());
+        bytes.push(0);
+        bytes.extend_from_slice(value.as_bytes());
+        bytes.push(0);
+        bytes.resize(align(bytes.len(), 4), 0);
+    }
+    bytes
+}
+
+fn align(offset: usize, alignment: usize) -> usize {
+    offset.div_ceil(alignment) * alignment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::render::render_resource::Extent3d;
+
+    fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+        u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+    }
+
+    fn shape_image() -> ShapeImage {
+        let resolution = Extent3d {
+            width: 3,
+            height: 2,
+            depth_or_array_layers: 2,
+        };
+        ShapeImage::from_fn(Vec3::new(3.0, 2.0, 2.0), resolution, |pnt| {
+            pnt.length() - 1.0
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn writes_the_ktx2_header_and_level() {
+        let shape_image = shape_image();
+        for (format, vk_format, type_size) in [
+            (Ktx2Format::R32Float, VK_FORMAT_R32_SFLOAT, 4),
+            (Ktx2Format::R16Float, VK_FORMAT_R16_SFLOAT, 2),
+        ] {
+            let bytes = shape_image.to_ktx2(format);
+            assert_eq!(bytes[..12], KTX2_IDENTIFIER);
+            assert_eq!(read_u32(&bytes, 12), vk_format);
+            assert_eq!(read_u32(&bytes, 16), type_size);
+            assert_eq!(
+                [20, 24, 28].map(|offset| read_u32(&bytes, offset)),
+                [3, 2, 2]
+            );
+            assert_eq!(read_u32(&bytes, 40), 1);
+
+            let level_offset = read_u64(&bytes, 80) as usize;
+            let level_len = read_u64(&bytes, 88) as usize;
+            assert_eq!(level_offset % 4, 0);
+            assert_eq!(level_len, 12 * type_size as usize);
+            assert_eq!(read_u64(&bytes, 96) as usize, level_len);
+            assert_eq!(bytes.len(), level_offset + level_len);
+
+            let level = &bytes[level_offset..];
+            for (index, distance) in shape_image.data.iter().enumerate() {
+                let value = match format {
+                    Ktx2Format::R32Float => {
+                        f32::from_le_bytes(level[index * 4..index * 4 + 4].try_into().unwrap())
+                    }
+                    Ktx2Format::R16Float => {
+                        f16::from_le_bytes(level[index * 2..index * 2 + 2].try_into().unwrap())
+                            .to_f32()
+                    }
+                };
+                assert!((value - distance).abs() < 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn writes_the_data_format_descriptor() {
+        let bytes = shape_image().to_ktx2(Ktx2Format::R16Float);
+        let dfd_offset = read_u32(&bytes, 48) as usize;
+        let dfd_len = read_u32(&bytes, 52) as usize;
+        assert_eq!(dfd_offset, 80 + 3 * 8);
+        assert_eq!(dfd_len, 44);
+
+        let dfd = &bytes[dfd_offset..dfd_offset + dfd_len];
+        assert_eq!(read_u32(dfd, 0), 44);
+        // Block size in the upper half of the version word
+        assert_eq!(read_u32(dfd, 8) >> 16, 40);
+        assert_eq!(read_u32(dfd, 20), 2);
+        // Bit length and the signed and float flags of the channel
+        assert_eq!(read_u32(dfd, 28) >> 16 & 0xFF, 15);
+        assert_eq!(read_u32(dfd, 28) >> 24, 0xC0);
+        assert_eq!(f32::from_bits(read_u32(dfd, 36)), -1.0);
+        assert_eq!(f32::from_bits(read_u32(dfd, 40)), 1.0);
+    }
+
+    #[test]
+    fn writes_aligned_key_values() {
+        let bytes = shape_image().to_ktx2(Ktx2Format::R32Float);
+        let kvd_offset = read_u32(&bytes, 56) as usize;
+        let kvd_len = read_u32(&bytes, 60) as usize;
+        let mut kvd = &bytes[kvd_offset..kvd_offset + kvd_len];
+
+        let mut entries = Vec::new();
+        while !kvd.is_empty() {
+            let len = read_u32(kvd, 0) as usize;
+            let entry = std::str::from_utf8(&kvd[4..4 + len]).unwrap();
+            let (key, value) = entry.trim_end_matches('\0').split_once('\0').unwrap();
+            entries.push((String::from(key), String::from(value)));
+            kvd = &kvd[align(4 + len, 4)..];
+        }
+        assert_eq!(
+            entries,
+            [
+                (String::from("KTXwriter"), String::from("bevy-ray-marching")),
+                (String::from("SDFsize"), String::from("3 2 2")),
+            ]
+        );
+    }
+
+    #[test]
+    fn colors_the_distances() {
+        assert_eq!(distance_color(0.0), Vec3::ONE);
+        assert!(distance_color(0.25).x > distance_color(0.25).z);
+        assert!(distance_color(-0.25).z > distance_color(-0.25).x);
+
+        let png = shape_image().to_png_slices().unwrap();
+        assert_eq!(png.len(), 2);
+        assert_eq!(png[0][1..4], *b"PNG");
+    }
+}
@@ -0,0 +1,315 @@
+use super::ShapeImage;
+use bevy::{
+    asset::Error,
+    prelude::{Transform, Vec3},
+    render::render_resource::Extent3d,
+};
+use std::thread;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ImageOperation {
+    Union,
+    Intersection,
+    Subtraction,
+    SmoothUnion { k: f32 },
+}
+
+impl ImageOperation {
+    pub fn apply(&self, left: f32, right: f32) -> f32 {
+        match *self {
+            Self::Union => left.min(right),
+            Self::Intersection => left.max(right),
+            Self::Subtraction => left.max(-right),
+            Self::SmoothUnion { k } => smooth_min(left, right, k),
+        }
+    }
+}
+
+impl ShapeImage {
+    pub fn new(size: Vec3, resolution: Extent3d, data: Vec<f32>) -> Result<Self, Error> {
+        let image = Self {
+            size,
+            resolution,
+            data,
+        };
+        image.validate()?;
+        Ok(image)
+    }
+
+    pub fn from_fn<F>(size: Vec3, resolution: Extent3d, function: F) -> Result<Self, Error>
+    where
+        F: Fn(Vec3) -> f32,
+    {
+        let mut image = Self::empty(size, resolution)?;
+        image.fill(function)?;
+        Ok(image)
+    }
+
+    pub fn from_fn_parallel<F>(size: Vec3, resolution: Extent3d, function: F) -> Result<Self, Error>
+    where
+        F: Fn(Vec3) -> f32 + Sync,
+    {
+        let mut image = Self::empty(size, resolution)?;
+        image.fill_parallel(function)?;
+        Ok(image)
+    }
+
+    fn empty(size: Vec3, resolution: Extent3d) -> Result<Self, Error> {
+        Ok(Self {
+            size,
+            resolution,
+            data: vec![0.0; checked_texel_count(resolution)?],
+        })
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        let Extent3d {
+            width,
+            height,
+            depth_or_array_layers: depth,
+        } = self.resolution;
+
+        if self.data.len() != checked_texel_count(self.resolution)? {
+            return Err(Error::msg(format!(
+                "shape image data length {} doesn't match its resolution {width}x{height}x{depth}",
+                self.data.len()
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn fill<F>(&mut self, function: F) -> Result<(), Error>
+    where
+        F: Fn(Vec3) -> f32,
+    {
+        self.validate()?;
+        let (size, resolution) = (self.size, self.resolution);
+        fill_slices(&mut self.data, 0, size, resolution, &function);
+        Ok(())
+    }
+
+    pub fn fill_parallel<F>(&mut self, function: F) -> Result<(), Error>
+    where
+        F: Fn(Vec3) -> f32 + Sync,
+    {
+        self.validate()?;
+        let (size, resolution) = (self.size, self.resolution);
+        let slice_len = resolution.width as usize * resolution.height as usize;
+        let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+        let slices_per_thread = (resolution.depth_or_array_layers as usize).div_ceil(threads);
+
+        thread::scope(|scope| {
+            for (chunk_index, chunk) in self
+                .data
+                .chunks_mut(slice_len * slices_per_thread.max(1))
+                .enumerate()
+            {
+                let function = &function;
+                scope.spawn(move || {
+                    fill_slices(
+                        chunk,
+                        (chunk_index * slices_per_thread) as u32,
+                        size,
+                        resolution,
+                        function,
+                    )
+                });
+            }
+        });
+        Ok(())
+    }
+
+    pub fn texel_size(&self) -> Vec3 {
+        self.size / resolution_vec(self.resolution)
+    }
+
+    pub fn texel_position(&self, x: u32, y: u32, z: u32) -> Vec3 {
+        texel_position(self.size, self.resolution, x, y, z)
+    }
+
+    pub fn texel_index(&self, x: u32, y: u32, z: u32) -> usize {
+        let width = self.resolution.width as usize;
+        let height = self.resolution.height as usize;
+        (z as usize * height + y as usize) * width + x as usize
+    }
+
+    pub fn texel(&self, x: u32, y: u32, z: u32) -> f32 {
+        self.data[self.texel_index(x, y, z)]
+    }
+
+    // Same as the linear, clamp to edge sampling of the shape texture
+    pub fn sample(&self, pnt: Vec3) -> f32 {
+        let resolution = resolution_vec(self.resolution);
+        let coords =
+            ((pnt / self.size + 0.5) * resolution - 0.5).clamp(Vec3::ZERO, resolution - 1.0);
+        let min = coords.floor();
+        let factor = coords - min;
+
+        let x0 = min.x as u32;
+        let y0 = min.y as u32;
+        let z0 = min.z as u32;
+        let x1 = (x0 + 1).min(self.resolution.width - 1);
+        let y1 = (y0 + 1).min(self.resolution.height - 1);
+        let z1 = (z0 + 1).min(self.resolution.depth_or_array_layers - 1);
+
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+        let sample_row = |y, z| lerp(self.texel(x0, y, z), self.texel(x1, y, z), factor.x);
+        let sample_slice = |z| lerp(sample_row(y0, z), sample_row(y1, z), factor.y);
+        lerp(sample_slice(z0), sample_slice(z1), factor.z)
+    }
+
+    // Same as sdf_image in tracing.wgsl, without the transform and scale
+    pub fn distance(&self, pnt: Vec3) -> f32 {
+        let bounds = (self.size - self.texel_size()) / 2.0;
+        let cube_distance = (pnt.abs() - bounds).max(Vec3::ZERO).length();
+        let image_distance = self.sample(pnt);
+        if cube_distance > 0.0 {
+            (cube_distance * cube_distance + image_distance * image_distance).sqrt()
+        } else {
+            image_distance
+        }
+    }
+
+    // The interpolated field is only a distance field again after redistancing
+    pub fn resample(&self, resolution: Extent3d) -> Result<Self, Error> {
+        let mut image = Self::from_fn_parallel(self.size, resolution, |pnt| self.distance(pnt))?;
+        image.redistance();
+        Ok(image)
+    }
+
+    // Crops or pads the image to the given bounds, the result is centered around (min + max) / 2
+    pub fn with_bounds(&self, min: Vec3, max: Vec3) -> Result<Self, Error> {
+        let (mut image, _) =
+            Self::from_bounds_fn(self.texel_size(), min, max, |pnt| self.distance(pnt))?;
+        image.redistance();
+        Ok(image)
+    }
+
+    pub fn pad(&self, texels: u32) -> Result<Self, Error> {
+        let padding = self.texel_size() * texels as f32;
+        self.with_bounds(-self.size / 2.0 - padding, self.size / 2.0 + padding)
+    }
+
+    // Combines the image with another one placed at the given transform relative to this image,
+    // the result keeps the texel size of this image and is centered around the returned offset
+    pub fn combine(
+        &self,
+        other: &Self,
+        transform: Transform,
+        operation: ImageOperation,
+    ) -> Result<(Self, Vec3), Error> {
+        let inv_transform = transform.compute_matrix().inverse();
+        let scale = transform.scale.abs().min_element();
+
+        let half_size = self.size / 2.0;
+        let (min, max) = match operation {
+            ImageOperation::Union | ImageOperation::SmoothUnion { .. } => {
+                let other_half_size = other.size / 2.0;
+                (0..8).fold((-half_size, half_size), |(min, max), corner| {
+                    let pnt = transform.transform_point(
+                        other_half_size
+                            * Vec3::new(
+                                if corner & 1 == 0 { -1.0 } else { 1.0 },
+                                if corner & 2 == 0 { -1.0 } else { 1.0 },
+                                if corner & 4 == 0 { -1.0 } else { 1.0 },
+                            ),
+                    );
+                    (min.min(pnt), max.max(pnt))
+                })
+            }
+            ImageOperation::Intersection | ImageOperation::Subtraction => (-half_size, half_size),
+        };
+
+        let (mut image, center) = Self::from_bounds_fn(self.texel_size(), min, max, |pnt| {
+            operation.apply(
+                self.distance(pnt),
+                other.distance(inv_transform.transform_point3(pnt)) * scale,
+            )
+        })?;
+        image.redistance();
+        Ok((image, center))
+    }
+
+    fn from_bounds_fn<F>(
+        texel_size: Vec3,
+        min: Vec3,
+        max: Vec3,
+        function: F,
+    ) -> Result<(Self, Vec3), Error>
+    where
+        F: Fn(Vec3) -> f32 + Sync,
+    {
+        let texels = ((max - min) / texel_size).round().max(Vec3::ONE);
+        let resolution = Extent3d {
+            width: texels.x as u32,
+            height: texels.y as u32,
+            depth_or_array_layers: texels.z as u32,
+        };
+        let center = (min + max) / 2.0;
+        Ok((
+            Self::from_fn_parallel(texel_size * texels, resolution, |pnt| {
+                function(pnt + center)
+            })?,
+            center,
+        ))
+    }
+}
+
+// Same as the polynomial kernel of smin in tracing.wgsl, a k of 0 is a union
+pub(super) fn smooth_min(left: f32, right: f32, k: f32) -> f32 {
+    let k = k.max(0.0001);
+    let h = (k - (left - right).abs()).max(0.0) / k;
+    left.min(right) - h * h * h * k * (1.0 / 2.0 / 3.0)
+}
+
+fn fill_slices<F>(
+    data: &mut [f32],
+    first_slice: u32,
+    size: Vec3,
+    resolution: Extent3d,
+    function: &F,
+) where
+    F: Fn(Vec3) -> f32,
+{
+    let width = resolution.width as usize;
+    let slice_len = width * resolution.height as usize;
+    for (slice_index, slice) in data.chunks_mut(slice_len).enumerate() {
+        let z = first_slice + slice_index as u32;
+        for y in 0..resolution.height {
+            for x in 0..resolution.width {
+                slice[y as usize * width + x as usize] =
+                    function(texel_position(size, resolution, x, y, z));
+            }
+        }
+    }
+}
+
+fn texel_position(size: Vec3, resolution: Extent3d, x: u32, y: u32, z: u32) -> Vec3 {
+    ((Vec3::new(x as f32, y as f32, z as f32) + 0.5) / resolution_vec(resolution) - 0.5) * size
+}
+
+fn resolution_vec(resolution: Extent3d) -> Vec3 {
+    Vec3::new(
+        resolution.width as f32,
+        resolution.height as f32,
+        resolution.depth_or_array_layers as f32,
+    )
+}
+
+// The resolution needs at least one texel and a texel count that fits in memory
+fn checked_texel_count(resolution: Extent3d) -> Result<usize, Error> {
+    let Extent3d {
+        width,
+        height,
+        depth_or_array_layers: depth,
+    } = resolution;
+    (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|count| count.checked_mul(depth as usize))
+        .filter(|count| *count > 0)
+        .ok_or_else(|| {
+            Error::msg(format!(
+                "invalid shape image resolution: {width}x{height}x{depth}"
+            ))
+        })
+}
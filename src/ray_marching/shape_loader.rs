@@ -4,16 +4,26 @@ use bevy::{
     render::render_resource::Extent3d,
 };
 
-use crate::model::Model;
+use crate::{
+    model::Model,
+    volume::{NrrdHeader, RawHeader, ScalarVolume},
+};
 
 use super::ShapeImage;
 
+// The tolerance of the octrees of meshes, relative to the longest side of the mesh
+const OCTREE_TOLERANCE: f32 = 0.0025;
+const OCTREE_MAX_DEPTH: u32 = 7;
+
 pub struct ShapeLoaderPlugin;
 
 impl Plugin for ShapeLoaderPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
         app.init_asset_loader::<PLYLoader>();
+        app.init_asset_loader::<PLYOctreeLoader>();
         app.init_asset_loader::<SDFLoader>();
+        app.init_asset_loader::<NRRDLoader>();
+        app.init_asset_loader::<RawVolumeLoader>();
     }
 }
 
@@ -32,14 +42,15 @@ impl AssetLoader for PLYLoader {
     ) -> bevy::utils::BoxedFuture<'a, Result<(), Error>> {
         Box::pin(async move {
             let string = String::from(std::str::from_utf8(bytes)?);
-            let shape_image = Model::from_ply(string)?.to_shape_image(
+            let model = Model::from_ply(string)?;
+            let shape_image = model.to_shape_image(
                 Extent3d {
                     width: 64,
                     height: 64,
                     depth_or_array_layers: 64,
                 },
                 4,
-            );
+            )?;
             let asset = LoadedAsset::new(shape_image);
             load_context.set_default_asset(asset);
             Ok(())
@@ -47,6 +58,31 @@ impl AssetLoader for PLYLoader {
     }
 }
 
+// Meshes named like "bunny.octree.ply" are loaded as octrees instead of images
+#[derive(Default)]
+struct PLYOctreeLoader;
+
+impl AssetLoader for PLYOctreeLoader {
+    fn extensions(&self) -> &[&str] {
+        &["octree.ply"]
+    }
+
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> bevy::utils::BoxedFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let string = String::from(std::str::from_utf8(bytes)?);
+            let model = Model::from_ply(string)?;
+            let tolerance = OCTREE_TOLERANCE * (model.max() - model.min()).max_element();
+            let asset = LoadedAsset::new(model.to_shape_octree(tolerance, OCTREE_MAX_DEPTH));
+            load_context.set_default_asset(asset);
+            Ok(())
+        })
+    }
+}
+
 #[derive(Default)]
 struct SDFLoader;
 
@@ -61,7 +97,71 @@ impl AssetLoader for SDFLoader {
         load_context: &'a mut LoadContext,
     ) -> bevy::utils::BoxedFuture<'a, Result<(), Error>> {
         Box::pin(async move {
-            let asset = LoadedAsset::new(ShapeImage::from(bytes));
+            let shape_image = ShapeImage::try_from(bytes)?;
+            shape_image.validate()?;
+            let asset = LoadedAsset::new(shape_image);
+            load_context.set_default_asset(asset);
+            Ok(())
+        })
+    }
+}
+
+#[derive(Default)]
+struct NRRDLoader;
+
+impl AssetLoader for NRRDLoader {
+    fn extensions(&self) -> &[&str] {
+        &["nrrd", "nhdr"]
+    }
+
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> bevy::utils::BoxedFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let (header, offset) = NrrdHeader::parse(bytes)?;
+            let volume = match header.data_path(load_context.path()) {
+                Some(data_path) => header.load(&load_context.read_asset_bytes(data_path).await?)?,
+                None => header.load(&bytes[offset..])?,
+            };
+
+            let iso_value = header.iso_value.unwrap_or_else(|| {
+                let (min, max) = volume.value_range();
+                (min + max) / 2.0
+            });
+            let asset = LoadedAsset::new(volume.to_shape_image(iso_value)?);
+            load_context.set_default_asset(asset);
+            Ok(())
+        })
+    }
+}
+
+#[derive(Default)]
+struct RawVolumeLoader;
+
+impl AssetLoader for RawVolumeLoader {
+    fn extensions(&self) -> &[&str] {
+        &["raw"]
+    }
+
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> bevy::utils::BoxedFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            // e.g. "bonsai_256x256x256_uint8.raw"
+            let file_name = load_context
+                .path()
+                .file_name()
+                .and_then(|file_name| file_name.to_str())
+                .unwrap_or_default();
+            let header = RawHeader::from_file_name(file_name)?;
+            let volume = ScalarVolume::from_raw(bytes, &header)?;
+
+            let (min, max) = volume.value_range();
+            let asset = LoadedAsset::new(volume.to_shape_image((min + max) / 2.0)?);
             load_context.set_default_asset(asset);
             Ok(())
         })
@@ -86,9 +186,20 @@ impl From<ShapeImage> for Box<[u8]> {
     }
 }
 
-impl From<&[u8]> for ShapeImage {
-    fn from(bytes: &[u8]) -> Self {
-        Self {
+// The header is the size and the resolution, followed by the texels. The number of texels is
+// checked by validate
+impl TryFrom<&[u8]> for ShapeImage {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < 24 {
+            return Err(Error::msg("the sdf file is too short for its header"));
+        }
+        if !(bytes.len() - 24).is_multiple_of(4) {
+            return Err(Error::msg("the sdf file ends within a texel"));
+        }
+
+        Ok(Self {
             size: Vec3 {
                 x: f32::from_le_bytes(bytes[0..4].try_into().unwrap()),
                 y: f32::from_le_bytes(bytes[4..8].try_into().unwrap()),
@@ -100,9 +211,43 @@ impl From<&[u8]> for ShapeImage {
                 depth_or_array_layers: u32::from_le_bytes(bytes[20..24].try_into().unwrap()),
             },
             data: bytes[24..]
-                    .chunks(4)
-                    .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
-                    .collect(),
-        }
+                .chunks_exact(4)
+                .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_sdf_files() {
+        let resolution = Extent3d {
+            width: 4,
+            height: 3,
+            depth_or_array_layers: 2,
+        };
+        let shape_image = ShapeImage::from_fn(Vec3::new(2.0, 1.5, 1.0), resolution, |pnt| {
+            pnt.length() - 0.5
+        })
+        .unwrap();
+
+        let bytes = Box::<[u8]>::from(shape_image.clone());
+        assert_eq!(bytes.len(), 24 + 4 * 24);
+        let loaded = ShapeImage::try_from(&bytes[..]).unwrap();
+        loaded.validate().unwrap();
+        assert_eq!(loaded.size, shape_image.size);
+        assert_eq!(loaded.resolution, shape_image.resolution);
+        assert_eq!(loaded.data, shape_image.data);
+
+        // Missing texels are caught by the loader
+        assert!(ShapeImage::try_from(&bytes[..bytes.len() - 4])
+            .unwrap()
+            .validate()
+            .is_err());
+        assert!(ShapeImage::try_from(&bytes[..bytes.len() - 2]).is_err());
+        assert!(ShapeImage::try_from(&bytes[..20]).is_err());
     }
 }
@@ -0,0 +1,255 @@
+use super::data_texture::DataTexture;
+use bevy::{
+    ecs::system::SystemParamItem,
+    prelude::{warn, FromWorld, Handle, Resource, Vec3},
+    reflect::{FromReflect, Reflect, TypeUuid},
+    render::{
+        render_asset::{PrepareAssetError, RenderAsset, RenderAssets},
+        render_resource::*,
+        renderer::{RenderDevice, RenderQueue},
+    },
+};
+use std::sync::Arc;
+
+const FAR: f32 = 64.0;
+
+// An adaptive distance field, the nodes either point to their 8 children or, with the leaf bit
+// set, to the 8 corner distances of the node in the samples, the corners are ordered as x | y << 1 | z << 2
+#[derive(Reflect, FromReflect, Debug, Clone, TypeUuid)]
+#[uuid = "3c8f5a2e-6b1d-4f7a-9e0c-5d2b7a4f8e13"]
+#[reflect_value]
+pub struct ShapeOctree {
+    // The edge length of the root node, centered around the origin
+    pub size: f32,
+    pub nodes: Vec<u32>,
+    pub samples: Vec<f32>,
+}
+
+impl ShapeOctree {
+    pub const LEAF: u32 = 1 << 31;
+
+    pub fn leaf_count(&self) -> usize {
+        self.samples.len() / 8
+    }
+
+    pub fn memory(&self) -> usize {
+        (self.nodes.len() + self.samples.len()) * 4
+    }
+
+    // Same as sdf_octree in tracing.wgsl, without the transform and scale
+    pub fn distance(&self, pnt: Vec3) -> f32 {
+        let half_size = self.size / 2.0;
+        let cube_distance = (pnt.abs() - half_size).max(Vec3::ZERO).length();
+        let pnt = pnt.clamp(Vec3::splat(-half_size), Vec3::splat(half_size));
+
+        let mut node = self.nodes[0];
+        let mut center = Vec3::ZERO;
+        let mut half_size = half_size;
+        while node & Self::LEAF == 0 {
+            half_size /= 2.0;
+            let child = (pnt.x > center.x) as u32
+                | ((pnt.y > center.y) as u32) << 1
+                | ((pnt.z > center.z) as u32) << 2;
+            center += Vec3::select(pnt.cmpgt(center), Vec3::ONE, Vec3::NEG_ONE) * half_size;
+            node = self.nodes[(node + child) as usize];
+        }
+
+        let corners = &self.samples[((node & !Self::LEAF) * 8) as usize..];
+        let factor = (pnt - center + half_size) / (2.0 * half_size);
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+        let row = |offset: usize| lerp(corners[offset], corners[offset + 1], factor.x);
+        let slice = |offset: usize| lerp(row(offset), row(offset + 2), factor.y);
+        let octree_distance = lerp(slice(0), slice(4), factor.z);
+
+        if cube_distance > 0.0 {
+            (cube_distance * cube_distance + octree_distance * octree_distance).sqrt()
+        } else {
+            octree_distance
+        }
+    }
+}
+
+impl RenderAsset for ShapeOctree {
+    type ExtractedAsset = ShapeOctree;
+    type PreparedAsset = Arc<ShapeOctree>;
+    type Param = ();
+
+    fn extract_asset(&self) -> Self::ExtractedAsset {
+        self.clone()
+    }
+
+    // The octrees are uploaded together by ShapeOctrees
+    fn prepare_asset(
+        octree: Self::ExtractedAsset,
+        _: &mut SystemParamItem<Self::Param>,
+    ) -> Result<Self::PreparedAsset, PrepareAssetError<Self::ExtractedAsset>> {
+        Ok(Arc::new(octree))
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct OctreeOffsets {
+    pub size: f32,
+    pub node_offset: u32,
+    pub sample_offset: u32,
+}
+
+// The empty octree at the start of the buffers, it stands in for the unloaded ones
+impl Default for OctreeOffsets {
+    fn default() -> Self {
+        Self {
+            size: 1.0,
+            node_offset: 0,
+            sample_offset: 0,
+        }
+    }
+}
+
+// The nodes and samples of all the octrees in the scene, concatenated into two data textures
+#[derive(Resource)]
+pub struct ShapeOctrees {
+    handles: Vec<Handle<ShapeOctree>>,
+    indices: Vec<u8>,
+    uploaded: Vec<Option<Arc<ShapeOctree>>>,
+    offsets: Vec<OctreeOffsets>,
+    pub nodes: DataTexture,
+    pub samples: DataTexture,
+}
+
+impl FromWorld for ShapeOctrees {
+    fn from_world(world: &mut bevy::prelude::World) -> Self {
+        let device = world.resource::<RenderDevice>();
+        let queue = world.resource::<RenderQueue>();
+        let (nodes, samples) = octree_data(&[]);
+        Self {
+            handles: Vec::new(),
+            indices: Vec::new(),
+            uploaded: Vec::new(),
+            offsets: Vec::new(),
+            nodes: DataTexture::new(
+                device,
+                queue,
+                "shape_octree_nodes",
+                TextureFormat::R32Uint,
+                &nodes,
+            )
+            .unwrap(),
+            samples: DataTexture::new(
+                device,
+                queue,
+                "shape_octree_samples",
+                TextureFormat::R32Float,
+                &samples,
+            )
+            .unwrap(),
+        }
+    }
+}
+
+impl ShapeOctrees {
+    pub fn clear(&mut self) {
+        self.handles.clear();
+        self.indices.clear();
+    }
+
+    pub fn add_octree(&mut self, octree_handle: &Handle<ShapeOctree>) {
+        let index = match self
+            .handles
+            .iter()
+            .position(|handle| handle == octree_handle)
+        {
+            Some(index) => index,
+            None => {
+                self.handles.push(octree_handle.clone());
+                self.handles.len() - 1
+            }
+        };
+        self.indices.push(index as u8);
+    }
+
+    // Uploads the octrees again if any of them changed, the unloaded ones are empty
+    pub fn prepare(
+        &mut self,
+        octrees: &RenderAssets<ShapeOctree>,
+        device: &RenderDevice,
+        queue: &RenderQueue,
+    ) {
+        let prepared = self
+            .handles
+            .iter()
+            .map(|handle| octrees.get(handle).cloned())
+            .collect::<Vec<_>>();
+        let changed = prepared.len() != self.uploaded.len()
+            || prepared
+                .iter()
+                .zip(self.uploaded.iter())
+                .any(|(prepared, uploaded)| match (prepared, uploaded) {
+                    (Some(prepared), Some(uploaded)) => !Arc::ptr_eq(prepared, uploaded),
+                    (None, None) => false,
+                    _ => true,
+                });
+        if !changed {
+            return;
+        }
+
+        let octrees = prepared
+            .iter()
+            .map(|octree| octree.as_deref())
+            .collect::<Vec<_>>();
+        // If they don't fit only the empty octree is uploaded and every shape uses it
+        let (nodes, samples) = octree_data(&octrees);
+        let fits =
+            self.nodes.set(device, queue, &nodes) && self.samples.set(device, queue, &samples);
+        if !fits {
+            warn!("The octrees in the scene are too large");
+            let (nodes, samples) = octree_data(&[]);
+            self.nodes.set(device, queue, &nodes);
+            self.samples.set(device, queue, &samples);
+        }
+
+        let mut node_offset = 1;
+        let mut sample_offset = 1;
+        self.offsets = octrees
+            .iter()
+            .map(|octree| match octree {
+                Some(octree) if fits => {
+                    let offsets = OctreeOffsets {
+                        size: octree.size,
+                        node_offset,
+                        sample_offset,
+                    };
+                    node_offset += octree.nodes.len() as u32;
+                    sample_offset += octree.leaf_count() as u32;
+                    offsets
+                }
+                _ => OctreeOffsets::default(),
+            })
+            .collect();
+        self.uploaded = prepared;
+    }
+
+    pub fn get_offsets(&self, shape_index: u8) -> OctreeOffsets {
+        self.indices
+            .get(shape_index as usize)
+            .and_then(|index| self.offsets.get(*index as usize))
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+// The bytes of the nodes and samples, after an empty octree at the start
+fn octree_data(octrees: &[Option<&ShapeOctree>]) -> (Vec<u8>, Vec<u8>) {
+    let mut nodes = vec![ShapeOctree::LEAF];
+    let mut samples = vec![FAR; 8];
+    for octree in octrees.iter().flatten() {
+        nodes.extend_from_slice(&octree.nodes);
+        samples.extend_from_slice(&octree.samples);
+    }
+    (
+        nodes.iter().flat_map(|node| node.to_le_bytes()).collect(),
+        samples
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect(),
+    )
+}
@@ -184,7 +184,7 @@ fn prepare_stages(
         .collect::<Vec<_>>();
 
     commands.insert_or_spawn_batch(entities);
-    uniform_buffer.0.write_buffer(&*device, &*queue);
+    uniform_buffer.0.write_buffer(&device, &queue);
 }
 
 #[derive(Resource)]
@@ -378,7 +378,7 @@ fn queue_stage_bind_groups(
                             },
                         ],
                     });
-                    prev_texture = &texture;
+                    prev_texture = texture;
                     mid_bind_group
                 })
                 .collect::<Vec<_>>();
@@ -1,24 +1,30 @@
 use super::{
+    data_texture::DATA_TEXTURE_WIDTH,
     environment::EnvironmentBindGroupLayout,
     shape::{
-        ShapeGroup, ShapesBindGroupLayout, MAX_CUBES, MAX_IMAGES, MAX_PLANES, MAX_SPHERES,
-        MAX_TEXTURES, ShapeImages,
+        ShapeGroup, ShapeImages, ShapesBindGroupLayout, MAX_BLEND_GROUPS, MAX_CAPSULES, MAX_CONES,
+        MAX_CUBES, MAX_CUSTOMS, MAX_CYLINDERS, MAX_DOMAIN_GROUPS, MAX_EXTRUSIONS, MAX_HEIGHTFIELDS,
+        MAX_IMAGES, MAX_METABALLS, MAX_MODIFIED_GROUPS, MAX_OCTREES, MAX_PLANES, MAX_POINTS,
+        MAX_REVOLUTIONS, MAX_SPHERES, MAX_TEXTURES, MAX_TORI, MAX_TUBES,
     },
     stages::StageBindGroupLayouts,
     view::ViewBindGroupLayout,
     RayMarching,
 };
-use crate::ray_marching::shape::Operation::{self, Intersection, Union, SmoothUnion};
+use crate::ray_marching::shape::Operation::{
+    self, ChamferIntersection, ChamferUnion, ColumnsIntersection, ColumnsUnion, Engrave, Groove,
+    Intersection, Morph, SmoothIntersection, SmoothSubtraction, SmoothUnion, StairsIntersection,
+    StairsUnion, Subtraction, Tongue, Union,
+};
 use bevy::{
     core_pipeline::fullscreen_vertex_shader::fullscreen_shader_vertex_state,
     prelude::{
-        default, Assets, Commands, Component, Entity, FromWorld, Handle, IntoSystemAppConfig,
-        IntoSystemConfig, Plugin, Query, Res, ResMut, Resource,
+        debug, default, Assets, Commands, Component, Entity, FromWorld, Handle,
+        IntoSystemAppConfig, IntoSystemConfig, Plugin, Query, Res, ResMut, Resource,
     },
     render::{render_resource::*, ExtractSchedule, MainWorld, RenderApp, RenderSet},
-    utils::HashMap,
 };
-use std::ops::{Deref, DerefMut, Range};
+use std::{collections::VecDeque, ops::Range};
 
 const SHADER_SOURCE: &str = include_str!("tracing.wgsl");
 
@@ -35,20 +41,46 @@ impl Plugin for TracingPlugin {
     }
 }
 
+// The number of shape groups whose shaders are kept, so that switching back and forth between a
+// few of them doesn't compile their shaders again
+const SHADER_CACHE_SIZE: usize = 8;
+
+// The shaders of the most recently used shape groups, the least recently used one is last
 #[derive(Resource, Default)]
-struct ShaderCache(HashMap<ShapeGroup, Handle<Shader>>);
+struct ShaderCache(VecDeque<(ShapeGroup, Handle<Shader>)>);
 
-impl Deref for ShaderCache {
-    type Target = HashMap<ShapeGroup, Handle<Shader>>;
+impl ShaderCache {
+    fn get(&self, shape_group: &ShapeGroup) -> Option<&Handle<Shader>> {
+        self.0
+            .iter()
+            .find(|(group, _)| group == shape_group)
+            .map(|(_, handle)| handle)
+    }
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
+    // Moves the shader of the group to the front, returns false if it isn't cached
+    fn touch(&mut self, shape_group: &ShapeGroup) -> bool {
+        match self.0.iter().position(|(group, _)| group == shape_group) {
+            Some(index) => {
+                let entry = self.0.remove(index).unwrap();
+                self.0.push_front(entry);
+                true
+            }
+            None => false,
+        }
     }
-}
 
-impl DerefMut for ShaderCache {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+    // Returns the shader of the least recently used group once the cache is full
+    fn insert(
+        &mut self,
+        shape_group: ShapeGroup,
+        handle: Handle<Shader>,
+    ) -> Option<Handle<Shader>> {
+        self.0.push_front((shape_group, handle));
+        if self.0.len() > SHADER_CACHE_SIZE {
+            self.0.pop_back().map(|(_, handle)| handle)
+        } else {
+            None
+        }
     }
 }
 
@@ -59,15 +91,20 @@ fn extract_shader(
     shape_images: Res<ShapeImages>,
 ) {
     if let Some(shape_group) = shape_group {
-        if !shader_cache.contains_key(&shape_group) {
+        if !shader_cache.touch(&shape_group) {
+            let customs = generate_customs(&shape_group);
             let sdf = generate_sdf(&shape_group, &shape_images);
             let material = generate_material(&shape_group, &shape_images);
-            println!("{sdf}");
-            println!("{material}");
-            let shader_source = format!("{}\n{}\n{}", SHADER_SOURCE, sdf, material);
+            debug!("{sdf}");
+            debug!("{material}");
+            let shader_source = format!("{}\n{}{}\n{}", SHADER_SOURCE, customs, sdf, material);
             let mut shaders = main_world.resource_mut::<Assets<Shader>>();
             let handle = shaders.add(Shader::from_wgsl(shader_source));
-            shader_cache.insert((*shape_group).clone(), handle);
+            // Only a few shaders are kept, otherwise a shader would pile up for every change of the
+            // shape types and the hierarchy
+            if let Some(evicted) = shader_cache.insert((*shape_group).clone(), handle) {
+                shaders.remove(&evicted);
+            }
         }
     }
 }
@@ -132,8 +169,24 @@ impl SpecializedRenderPipeline for TracingPipeline {
             ShaderDefVal::Int("MAX_PLANES".into(), MAX_PLANES as i32),
             ShaderDefVal::Int("MAX_SPHERES".into(), MAX_SPHERES as i32),
             ShaderDefVal::Int("MAX_CUBES".into(), MAX_CUBES as i32),
+            ShaderDefVal::Int("MAX_TORI".into(), MAX_TORI as i32),
+            ShaderDefVal::Int("MAX_CYLINDERS".into(), MAX_CYLINDERS as i32),
+            ShaderDefVal::Int("MAX_CAPSULES".into(), MAX_CAPSULES as i32),
+            ShaderDefVal::Int("MAX_CONES".into(), MAX_CONES as i32),
             ShaderDefVal::Int("MAX_IMAGES".into(), MAX_IMAGES as i32),
+            ShaderDefVal::Int("MAX_OCTREES".into(), MAX_OCTREES as i32),
+            ShaderDefVal::Int("MAX_EXTRUSIONS".into(), MAX_EXTRUSIONS as i32),
+            ShaderDefVal::Int("MAX_REVOLUTIONS".into(), MAX_REVOLUTIONS as i32),
+            ShaderDefVal::Int("MAX_TUBES".into(), MAX_TUBES as i32),
+            ShaderDefVal::Int("MAX_HEIGHTFIELDS".into(), MAX_HEIGHTFIELDS as i32),
+            ShaderDefVal::Int("MAX_CUSTOMS".into(), MAX_CUSTOMS as i32),
+            ShaderDefVal::Int("MAX_METABALLS".into(), MAX_METABALLS as i32),
+            ShaderDefVal::Int("MAX_POINTS".into(), MAX_POINTS as i32),
+            ShaderDefVal::Int("MAX_MODIFIED_GROUPS".into(), MAX_MODIFIED_GROUPS as i32),
+            ShaderDefVal::Int("MAX_DOMAIN_GROUPS".into(), MAX_DOMAIN_GROUPS as i32),
+            ShaderDefVal::Int("MAX_BLEND_GROUPS".into(), MAX_BLEND_GROUPS as i32),
             ShaderDefVal::Int("MAX_TEXTURES".into(), MAX_TEXTURES as i32),
+            ShaderDefVal::Int("DATA_TEXTURE_WIDTH".into(), DATA_TEXTURE_WIDTH as i32),
             ShaderDefVal::Int("FAR".into(), 64),
         ];
 
@@ -276,7 +329,7 @@ fn generate_sdf(group: &ShapeGroup, images: &ShapeImages) -> String {
     let mut group_index = 0u8;
     format!(
         "fn sdf_generated(pnt: vec3<f32>) -> f32 {{\n{}return dist_0;\n}}",
-        generate_group_sdf(images, group, &mut group_index, false)
+        generate_group_sdf(images, group, &mut group_index, false, "pnt")
     )
 }
 
@@ -284,91 +337,278 @@ fn generate_material(group: &ShapeGroup, images: &ShapeImages) -> String {
     let mut group_index = 0u8;
     format!(
         "fn sdf_material_generated(pnt: vec3<f32>) -> SDFMaterialResult {{\n{}return SDFMaterialResult(dist_0, material_0);\n}}",
-        generate_group_sdf(images, group, &mut group_index, true)
+        generate_group_sdf(images, group, &mut group_index, true, "pnt")
     )
 }
 
-fn generate_group_sdf(images: &ShapeImages, group: &ShapeGroup, index: &mut u8, material: bool) -> String {
+// The functions with the sources of the custom shapes, and the ones that call them with the
+// transforms and modifiers like the functions of the other shapes
+fn generate_customs(group: &ShapeGroup) -> String {
+    let mut source = String::new();
+    for (i, custom_source) in group.custom_index_range.clone().zip(&group.custom_sources) {
+        source += &format!(
+            "fn custom_{i}(pnt: vec3<f32>, parameters: array<vec4<f32>, 4>) -> f32 {{\n{custom_source}\n}}\n"
+        );
+        source += &format!(
+            "fn sdf_custom_{i}(pnt: vec3<f32>) -> f32 {{\nreturn modify_custom({i}u, pnt, custom_{i}(elongate_custom({i}u, pnt), shapes.customs[{i}u].parameters));\n}}\n"
+        );
+    }
+    for child in group.children.iter() {
+        source += &generate_customs(child);
+    }
+    source
+}
+
+fn generate_group_sdf(
+    images: &ShapeImages,
+    group: &ShapeGroup,
+    index: &mut u8,
+    material: bool,
+    pnt: &str,
+) -> String {
     let group_index = *index;
     *index += 1;
 
     let mut source = format!(
         "var dist_{group_index} = {};\n",
         match group.operation {
-            Union => "#{FAR}f",
-            Intersection => "-#{FAR}f",
-            SmoothUnion => "#{FAR}f",
+            Union | SmoothUnion | ChamferUnion | StairsUnion | ColumnsUnion => "#{FAR}f",
+            _ => "-#{FAR}f",
         }
     );
     if material {
         source += &format!("var material_{group_index} = Material(vec3(1.0));\n");
     }
+    // The shapes of a modified group are evaluated at the elongated point, which is then folded
+    // into the domain of the group
+    let mut group_pnt = pnt.to_string();
+    if let Some(modifiers) = group.modifiers {
+        group_pnt = format!("elongate_group({modifiers}u, {group_pnt})");
+    }
+    if let Some(domain) = group.domain {
+        group_pnt = format!("fold_domain({domain}u, {group_pnt})");
+    }
+    let outer_pnt = pnt;
+    let pnt = if group_pnt == pnt {
+        group_pnt
+    } else {
+        source += &format!("let pnt_{group_index} = {group_pnt};\n");
+        format!("pnt_{group_index}")
+    };
+
+    // The base goes first, so that it sets the material of the group
+    let mut children = group.children.iter();
+    if group.operation.has_base() {
+        if let Some(base) = children.next() {
+            source += &generate_child_sdf(
+                images,
+                base,
+                (Intersection, None),
+                group_index,
+                index,
+                material,
+                &pnt,
+            );
+        }
+    }
     source += &generate_shapes_sdf(
         group_index,
-        group.operation,
+        group,
         "plane",
+        "planes",
         &group.plane_index_range,
+        &pnt,
         material,
     );
     source += &generate_shapes_sdf(
         group_index,
-        group.operation,
+        group,
         "sphere",
+        "spheres",
         &group.sphere_index_range,
+        &pnt,
         material,
     );
     source += &generate_shapes_sdf(
         group_index,
-        group.operation,
+        group,
         "cube",
+        "cubes",
         &group.cube_index_range,
+        &pnt,
+        material,
+    );
+    source += &generate_shapes_sdf(
+        group_index,
+        group,
+        "torus",
+        "tori",
+        &group.torus_index_range,
+        &pnt,
+        material,
+    );
+    source += &generate_shapes_sdf(
+        group_index,
+        group,
+        "cylinder",
+        "cylinders",
+        &group.cylinder_index_range,
+        &pnt,
+        material,
+    );
+    source += &generate_shapes_sdf(
+        group_index,
+        group,
+        "capsule",
+        "capsules",
+        &group.capsule_index_range,
+        &pnt,
+        material,
+    );
+    source += &generate_shapes_sdf(
+        group_index,
+        group,
+        "cone",
+        "cones",
+        &group.cone_index_range,
+        &pnt,
         material,
     );
     source += &generate_images_sdf(
         images,
         group_index,
-        group.operation,
+        group,
         &group.image_index_range,
+        &pnt,
+        material,
+    );
+    source += &generate_shapes_sdf(
+        group_index,
+        group,
+        "octree",
+        "octrees",
+        &group.octree_index_range,
+        &pnt,
+        material,
+    );
+    source += &generate_shapes_sdf(
+        group_index,
+        group,
+        "extrusion",
+        "extrusions",
+        &group.extrusion_index_range,
+        &pnt,
+        material,
+    );
+    source += &generate_shapes_sdf(
+        group_index,
+        group,
+        "revolution",
+        "revolutions",
+        &group.revolution_index_range,
+        &pnt,
+        material,
+    );
+    source += &generate_shapes_sdf(
+        group_index,
+        group,
+        "tube",
+        "tubes",
+        &group.tube_index_range,
+        &pnt,
+        material,
+    );
+    source += &generate_shapes_sdf(
+        group_index,
+        group,
+        "heightfield",
+        "heightfields",
+        &group.heightfield_index_range,
+        &pnt,
+        material,
+    );
+    source += &generate_customs_sdf(
+        group_index,
+        group,
+        &group.custom_index_range,
+        &pnt,
+        material,
+    );
+    source += &generate_metaballs_sdf(
+        group_index,
+        group,
+        &group.metaballs_index_range,
+        &pnt,
         material,
     );
 
-    for child in group.children.iter() {
-        let child_index = *index;
-        let child_source = generate_group_sdf(images, child, index, material);
-        source += &child_source;
-        source += &generate_operation(
-            group.operation,
+    for child in children {
+        source += &generate_child_sdf(
+            images,
+            child,
+            (group.operation, group.blend),
             group_index,
-            format!(
-                "{}dist_{child_index}",
-                if child.negative { "-" } else { "" }
-            ),
-            if material {
-                Some(format!("material_{child_index}"))
-            } else {
-                None
-            },
+            index,
+            material,
+            &pnt,
+        );
+    }
+
+    if let Some(modifiers) = group.modifiers {
+        source += &format!(
+            "dist_{group_index} = modify_group({modifiers}u, {outer_pnt}, dist_{group_index});\n"
         );
     }
 
     source
 }
 
+fn generate_child_sdf(
+    images: &ShapeImages,
+    child: &ShapeGroup,
+    (operation, blend): (Operation, Option<u8>),
+    group_index: u8,
+    index: &mut u8,
+    material: bool,
+    pnt: &str,
+) -> String {
+    let child_index = *index;
+    let mut source = generate_group_sdf(images, child, index, material, pnt);
+    source += &generate_operation(
+        operation,
+        blend,
+        group_index,
+        format!(
+            "{}dist_{child_index}",
+            if child.negative { "-" } else { "" }
+        ),
+        if material {
+            Some(format!("material_{child_index}"))
+        } else {
+            None
+        },
+    );
+    source
+}
+
 fn generate_shapes_sdf(
     index: u8,
-    operation: Operation,
+    group: &ShapeGroup,
     shape: &str,
+    array: &str,
     index_range: &Range<u8>,
+    pnt: &str,
     material: bool,
 ) -> String {
     match index_range.len() {
         0 => String::new(),
         1 => generate_operation(
-            operation,
+            group.operation,
+            group.blend,
             index,
-            format!("sdf_{shape}({}u, pnt)", index_range.start),
+            format!("sdf_{shape}({}u, {pnt})", index_range.start),
             if material {
-                Some(format!("shapes.{shape}s[{}u].material", index_range.start))
+                Some(format!("shapes.{array}[{}u].material", index_range.start))
             } else {
                 None
             },
@@ -376,11 +616,12 @@ fn generate_shapes_sdf(
         _ => generate_for_loop(
             index_range,
             generate_operation(
-                operation,
+                group.operation,
+                group.blend,
                 index,
-                format!("sdf_{shape}(i, pnt)"),
+                format!("sdf_{shape}(i, {pnt})"),
                 if material {
-                    Some(format!("shapes.{shape}s[i].material"))
+                    Some(format!("shapes.{array}[i].material"))
                 } else {
                     None
                 },
@@ -392,17 +633,19 @@ fn generate_shapes_sdf(
 fn generate_images_sdf(
     images: &ShapeImages,
     index: u8,
-    operation: Operation,
+    group: &ShapeGroup,
     index_range: &Range<u8>,
+    pnt: &str,
     material: bool,
 ) -> String {
     let mut source = String::new();
     for i in index_range.start..index_range.end {
         let image_index = images.get_image_index(i);
         source += &generate_operation(
-            operation,
+            group.operation,
+            group.blend,
             index,
-            format!("sdf_image({i}u, {image_index}u, shape_texture_{image_index}, pnt)"),
+            format!("sdf_image({i}u, {image_index}u, shape_texture_{image_index}, {pnt})"),
             if material {
                 Some(format!("shapes.images[{}u].material", i))
             } else {
@@ -414,6 +657,69 @@ fn generate_images_sdf(
     source
 }
 
+fn generate_customs_sdf(
+    index: u8,
+    group: &ShapeGroup,
+    index_range: &Range<u8>,
+    pnt: &str,
+    material: bool,
+) -> String {
+    let mut source = String::new();
+    for i in index_range.start..index_range.end {
+        source += &generate_operation(
+            group.operation,
+            group.blend,
+            index,
+            format!("sdf_custom_{i}({pnt})"),
+            if material {
+                Some(format!("shapes.customs[{i}u].material"))
+            } else {
+                None
+            },
+        )
+    }
+
+    source
+}
+
+// The material of metaballs mixes the colors of their points
+fn generate_metaballs_sdf(
+    index: u8,
+    group: &ShapeGroup,
+    index_range: &Range<u8>,
+    pnt: &str,
+    material: bool,
+) -> String {
+    match index_range.len() {
+        0 => String::new(),
+        1 => generate_operation(
+            group.operation,
+            group.blend,
+            index,
+            format!("sdf_metaballs({}u, {pnt})", index_range.start),
+            if material {
+                Some(format!("metaballs_material({}u, {pnt})", index_range.start))
+            } else {
+                None
+            },
+        ),
+        _ => generate_for_loop(
+            index_range,
+            generate_operation(
+                group.operation,
+                group.blend,
+                index,
+                format!("sdf_metaballs(i, {pnt})"),
+                if material {
+                    Some(format!("metaballs_material(i, {pnt})"))
+                } else {
+                    None
+                },
+            ),
+        ),
+    }
+}
+
 fn generate_for_loop(range: &Range<u8>, inner: String) -> String {
     format!(
         "for (var i = {}u; i < {}u; i = i + 1u) {{\n{}}}\n",
@@ -423,15 +729,23 @@ fn generate_for_loop(range: &Range<u8>, inner: String) -> String {
 
 fn generate_operation(
     operation: Operation,
+    blend: Option<u8>,
     index: u8,
     dist: String,
     material: Option<String>,
 ) -> String {
+    // Only the groups with an operation that blends have a blend
+    let blend_index = blend.unwrap_or_default();
     match material {
         None => match operation {
             Union => format!("dist_{index} = min(dist_{index}, {dist});\n"),
-            Intersection => format!("dist_{index} = max(dist_{index}, {dist});\n"),
-            SmoothUnion => format!("dist_{index} = smin(dist_{index}, {dist});\n"),
+            Intersection | Subtraction { .. } => {
+                format!("dist_{index} = max(dist_{index}, {dist});\n")
+            }
+            _ => format!(
+                "dist_{index} = {}({blend_index}u, dist_{index}, {dist});\n",
+                blend_function(operation)
+            ),
         },
         Some(material) => match operation {
             Union => format!(
@@ -441,8 +755,59 @@ fn generate_operation(
                 "if max_select(&dist_{index}, {dist}) {{ material_{index} = {material}; }}\n"
             ),
             SmoothUnion => format!(
-                "material_{index} = mix_material(material_{index}, {material}, smin_mix(&dist_{index}, {dist}));\n"
+                "material_{index} = mix_material(material_{index}, {material}, smin_mix({blend_index}u, &dist_{index}, {dist}));\n"
+            ),
+            // The other shapes don't change the material of the base
+            Subtraction {
+                cutter_material: false,
+            }
+            | SmoothSubtraction {
+                cutter_material: false,
+            }
+            | Engrave
+            | Groove
+            | Tongue => generate_operation(operation, blend, index, dist, None),
+            Morph => format!(
+                "material_{index} = mix_material(material_{index}, {material}, morph_mix({blend_index}u, &dist_{index}, {dist}));\n"
+            ),
+            Subtraction {
+                cutter_material: true,
+            } => generate_operation(Intersection, None, index, dist, Some(material)),
+            SmoothIntersection
+            | SmoothSubtraction {
+                cutter_material: true,
+            } => format!(
+                "material_{index} = mix_material(material_{index}, {material}, smax_mix({blend_index}u, &dist_{index}, {dist}));\n"
+            ),
+            // The material of the nearer shape for the unions and of the farther one for the
+            // intersections, the distance is only evaluated once
+            ChamferUnion | StairsUnion | ColumnsUnion => format!(
+                "{{ let d = {dist}; if union_select(&dist_{index}, d, {}({blend_index}u, dist_{index}, d)) {{ material_{index} = {material}; }} }}\n",
+                blend_function(operation)
+            ),
+            ChamferIntersection | StairsIntersection | ColumnsIntersection => format!(
+                "{{ let d = {dist}; if intersection_select(&dist_{index}, d, {}({blend_index}u, dist_{index}, d)) {{ material_{index} = {material}; }} }}\n",
+                blend_function(operation)
             ),
         },
     }
 }
+
+// The shader function of an operation that blends
+fn blend_function(operation: Operation) -> &'static str {
+    match operation {
+        SmoothUnion => "smin",
+        SmoothIntersection | SmoothSubtraction { .. } => "smax",
+        ChamferUnion => "chamfer_union",
+        ChamferIntersection => "chamfer_intersection",
+        StairsUnion => "stairs_union",
+        StairsIntersection => "stairs_intersection",
+        ColumnsUnion => "columns_union",
+        ColumnsIntersection => "columns_intersection",
+        Engrave => "engrave",
+        Groove => "groove",
+        Tongue => "tongue",
+        Morph => "morph",
+        Union | Intersection | Subtraction { .. } => unreachable!("{operation:?} doesn't blend"),
+    }
+}
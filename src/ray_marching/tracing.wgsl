@@ -17,7 +17,22 @@ struct Shapes {
     planes: array<Plane, #{MAX_PLANES}>,
     spheres: array<Sphere, #{MAX_SPHERES}>,
     cubes: array<Cube, #{MAX_CUBES}>,
+    tori: array<Torus, #{MAX_TORI}>,
+    cylinders: array<Cylinder, #{MAX_CYLINDERS}>,
+    capsules: array<Capsule, #{MAX_CAPSULES}>,
+    cones: array<Cone, #{MAX_CONES}>,
     images: array<Image, #{MAX_IMAGES}>,
+    octrees: array<Octree, #{MAX_OCTREES}>,
+    extrusions: array<Extrusion, #{MAX_EXTRUSIONS}>,
+    revolutions: array<Revolution, #{MAX_REVOLUTIONS}>,
+    tubes: array<Tube, #{MAX_TUBES}>,
+    heightfields: array<Heightfield, #{MAX_HEIGHTFIELDS}>,
+    customs: array<Custom, #{MAX_CUSTOMS}>,
+    metaballs: array<Metaballs, #{MAX_METABALLS}>,
+    points: array<vec4<f32>, #{MAX_POINTS}>,
+    modified_groups: array<ModifiedGroup, #{MAX_MODIFIED_GROUPS}>,
+    domain_groups: array<DomainGroup, #{MAX_DOMAIN_GROUPS}>,
+    blend_groups: array<BlendGroup, #{MAX_BLEND_GROUPS}>,
     texture_properties: array<TextureProperties, #{MAX_TEXTURES}>,
 };
 
@@ -25,6 +40,7 @@ struct Plane {
     inv_transform: mat4x4<f32>,
     scale: f32,
     material: Material,
+    modifiers: Modifiers,
 };
 
 struct Sphere {
@@ -32,6 +48,7 @@ struct Sphere {
     inv_transform: mat4x4<f32>,
     scale: f32,
     material: Material,
+    modifiers: Modifiers,
 };
 
 struct Cube {
@@ -39,18 +56,172 @@ struct Cube {
     inv_transform: mat4x4<f32>,
     scale: f32,
     material: Material,
+    modifiers: Modifiers,
+};
+
+struct Torus {
+    major_radius: f32,
+    minor_radius: f32,
+    inv_transform: mat4x4<f32>,
+    scale: f32,
+    material: Material,
+    modifiers: Modifiers,
+};
+
+struct Cylinder {
+    radius: f32,
+    half_height: f32,
+    inv_transform: mat4x4<f32>,
+    scale: f32,
+    material: Material,
+    modifiers: Modifiers,
+};
+
+struct Capsule {
+    radius: f32,
+    half_height: f32,
+    inv_transform: mat4x4<f32>,
+    scale: f32,
+    material: Material,
+    modifiers: Modifiers,
+};
+
+struct Cone {
+    radius: f32,
+    half_height: f32,
+    inv_transform: mat4x4<f32>,
+    scale: f32,
+    material: Material,
+    modifiers: Modifiers,
 };
 
 struct Image {
     inv_transform: mat4x4<f32>,
     scale: f32,
     material: Material,
+    modifiers: Modifiers,
+};
+
+struct Octree {
+    inv_transform: mat4x4<f32>,
+    scale: f32,
+    material: Material,
+    size: f32,
+    node_offset: u32,
+    sample_offset: u32,
+    modifiers: Modifiers,
+};
+
+struct Extrusion {
+    profile: Profile,
+    half_height: f32,
+    inv_transform: mat4x4<f32>,
+    scale: f32,
+    material: Material,
+    modifiers: Modifiers,
+};
+
+struct Revolution {
+    profile: Profile,
+    offset: f32,
+    inv_transform: mat4x4<f32>,
+    scale: f32,
+    material: Material,
+    modifiers: Modifiers,
+};
+
+struct Tube {
+    start_radius: f32,
+    end_radius: f32,
+    taper_scale: f32,
+    point_offset: u32,
+    segment_count: u32,
+    inv_transform: mat4x4<f32>,
+    scale: f32,
+    material: Material,
+    modifiers: Modifiers,
+};
+
+struct Heightfield {
+    texture_index: u32,
+    height_scale: f32,
+    inv_transform: mat4x4<f32>,
+    scale: f32,
+    material: Material,
+    modifiers: Modifiers,
+};
+
+struct Custom {
+    parameters: array<vec4<f32>, 4>,
+    inv_transform: mat4x4<f32>,
+    scale: f32,
+    material: Material,
+    modifiers: Modifiers,
+};
+
+struct Metaballs {
+    threshold: f32,
+    point_offset: u32,
+    point_count: u32,
+    inv_transform: mat4x4<f32>,
+    scale: f32,
+    material: Material,
+    modifiers: Modifiers,
+};
+
+struct MetaballPoint {
+    position: vec3<f32>,
+    influence: f32,
+    color: vec3<f32>,
+};
+
+struct Profile {
+    kind: u32,
+    parameters: vec3<f32>,
+    point_offset: u32,
+    point_count: u32,
 };
 
 struct Material {
     color: vec3<f32>,
 };
 
+struct Modifiers {
+    elongation: vec3<f32>,
+    rounding: f32,
+    onion: f32,
+    twist: f32,
+    bend: f32,
+    taper: f32,
+    noise_amplitude: f32,
+    noise_frequency: f32,
+};
+
+struct ModifiedGroup {
+    transform: mat4x4<f32>,
+    inv_transform: mat4x4<f32>,
+    scale: f32,
+    modifiers: Modifiers,
+};
+
+struct DomainGroup {
+    transform: mat4x4<f32>,
+    inv_transform: mat4x4<f32>,
+    mirror: vec3<u32>,
+    spacing: vec3<f32>,
+    count: vec3<u32>,
+    polar_count: u32,
+};
+
+struct BlendGroup {
+    k: f32,
+    kernel: u32,
+    count: u32,
+    depth: f32,
+    t: f32,
+    _padding: vec3<f32>,
+};
+
 struct TextureProperties {
     bounds: vec3<f32>,
     texture_bounds: vec3<f32>,
@@ -71,6 +242,16 @@ var shape_sampler: sampler;
 var shape_texture_0: texture_3d<f32>;
 @group(1) @binding(3)
 var shape_texture_1: texture_3d<f32>;
+@group(1) @binding(4)
+var octree_nodes: texture_2d<u32>;
+@group(1) @binding(5)
+var octree_samples: texture_2d<f32>;
+@group(1) @binding(6)
+var heightfield_texture_0: texture_2d<f32>;
+@group(1) @binding(7)
+var heightfield_texture_1: texture_2d<f32>;
+@group(1) @binding(8)
+var metaball_points: texture_2d<f32>;
 
 #ifdef FIRST_STAGE
     @group(2) @binding(0)
@@ -95,6 +276,11 @@ var shape_texture_1: texture_3d<f32>;
     #endif
 #endif
 
+// The texel of a data texture that holds the element at the index
+fn data_texel(index: u32) -> vec2<i32> {
+    return vec2(i32(index % #{DATA_TEXTURE_WIDTH}u), i32(index / #{DATA_TEXTURE_WIDTH}u));
+}
+
 struct SDFMaterialResult {
     distance: f32,
     material: Material,
@@ -291,37 +477,467 @@ fn sdf_material(pnt: vec3<f32>) -> SDFMaterialResult {
     return sdf_material_generated(pnt);
 }
 
+// Stretches a shape by moving the point towards the origin within the elongation
+fn elongate(pnt: vec3<f32>, modifiers: Modifiers) -> vec3<f32> {
+    return pnt - clamp(pnt, -modifiers.elongation, modifiers.elongation);
+}
+
+// Twists the shape around the z axis, bends the x axis towards z and narrows the shape along z
+fn deform(pnt: vec3<f32>, modifiers: Modifiers) -> vec3<f32> {
+    let twisted = vec3(rotate(pnt.xy, -modifiers.twist * pnt.z), pnt.z);
+    let bent = rotate(twisted.xz, -modifiers.bend * twisted.x);
+    let p = vec3(bent.x, twisted.y, bent.y);
+    return vec3(p.xy * (1.0 + modifiers.taper * p.z), p.z);
+}
+
+// Displaces the surface by the noise, rounds the shape and turns it into a shell if it has an
+// onion thickness. The point is the one before deform
+fn modify(distance: f32, pnt: vec3<f32>, modifiers: Modifiers) -> f32 {
+    var displaced = distance;
+    if modifiers.noise_amplitude != 0.0 {
+        displaced += modifiers.noise_amplitude * noise(pnt * modifiers.noise_frequency);
+    }
+    let rounded = displaced - modifiers.rounding;
+    let shell = select(rounded, abs(rounded) - modifiers.onion * 0.5, modifiers.onion > 0.0);
+    return lipschitz_correct(shell, pnt, modifiers);
+}
+
+// Divides the distance by how much the deformations and the noise can stretch it. The twist and
+// the bend keep the distance to the origin, so the stretching between the point and any point
+// within the distance is bounded by the stretching at the distance to the origin plus the distance
+fn lipschitz_correct(distance: f32, pnt: vec3<f32>, modifiers: Modifiers) -> f32 {
+    let radius = length(pnt) + abs(distance);
+    let stretch = (1.0 + abs(modifiers.twist) * radius) * (1.0 + abs(modifiers.bend) * radius)
+        * (1.0 + 2.0 * abs(modifiers.taper) * radius)
+        + abs(modifiers.noise_amplitude * modifiers.noise_frequency) * NOISE_GRADIENT;
+    return distance / stretch;
+}
+
+fn rotate(pnt: vec2<f32>, angle: f32) -> vec2<f32> {
+    let c = cos(angle);
+    let s = sin(angle);
+    return vec2(c * pnt.x - s * pnt.y, s * pnt.x + c * pnt.y);
+}
+
+// The steepest slope of the noise, the smoothstep makes it at most 3 along every axis
+const NOISE_GRADIENT = 5.196152;
+
+// Value noise from -1 to 1
+fn noise(pnt: vec3<f32>) -> f32 {
+    let cell = vec3<i32>(floor(pnt));
+    let f = pnt - floor(pnt);
+    let w = f * f * (3.0 - 2.0 * f);
+    let x00 = mix(noise_hash(cell), noise_hash(cell + vec3(1, 0, 0)), w.x);
+    let x10 = mix(noise_hash(cell + vec3(0, 1, 0)), noise_hash(cell + vec3(1, 1, 0)), w.x);
+    let x01 = mix(noise_hash(cell + vec3(0, 0, 1)), noise_hash(cell + vec3(1, 0, 1)), w.x);
+    let x11 = mix(noise_hash(cell + vec3(0, 1, 1)), noise_hash(cell + vec3(1, 1, 1)), w.x);
+    return mix(mix(x00, x10, w.y), mix(x01, x11, w.y), w.z);
+}
+
+fn noise_hash(cell: vec3<i32>) -> f32 {
+    let c = bitcast<vec3<u32>>(cell);
+    var h = (c.x * 73856093u) ^ (c.y * 19349663u) ^ (c.z * 83492791u);
+    h = (h ^ (h >> 16u)) * 0x45d9f3bu;
+    h = (h ^ (h >> 16u)) * 0x45d9f3bu;
+    h = h ^ (h >> 16u);
+    return f32(h >> 8u) / 8388607.5 - 1.0;
+}
+
+fn elongate_group(index: u32, pnt: vec3<f32>) -> vec3<f32> {
+    let group = &shapes.modified_groups[index];
+    let local = pos_transform(pnt, (*group).inv_transform);
+    let p = elongate(deform(local, (*group).modifiers), (*group).modifiers);
+    return pos_transform(p, (*group).transform);
+}
+
+fn modify_group(index: u32, pnt: vec3<f32>, distance: f32) -> f32 {
+    let group = &shapes.modified_groups[index];
+    let local = pos_transform(pnt, (*group).inv_transform);
+    return modify(distance / (*group).scale, local, (*group).modifiers) * (*group).scale;
+}
+
+// Mirrors the point across the local planes of the group, repeats it around the z axis and then
+// along the axes with a spacing, a count of zero repeats infinitely
+fn fold_domain(index: u32, pnt: vec3<f32>) -> vec3<f32> {
+    let group = &shapes.domain_groups[index];
+    var p = pos_transform(pnt, (*group).inv_transform);
+    p = select(p, abs(p), (*group).mirror != vec3(0u));
+
+    if (*group).polar_count > 0u {
+        let sector = 6.28318530718 / f32((*group).polar_count);
+        let angle = atan2(p.y, p.x);
+        let folded = angle - sector * round(angle / sector);
+        p = vec3(length(p.xy) * vec2(cos(folded), sin(folded)), p.z);
+    }
+
+    let repeated = (*group).spacing > vec3(0.0);
+    let finite = (*group).count > vec3(0u);
+    let spacing = select(vec3(1.0), (*group).spacing, repeated);
+    let half_count = select(vec3(0.0), (vec3<f32>((*group).count) - 1.0) * 0.5, finite);
+    var cell = round(p / spacing + half_count) - half_count;
+    cell = select(cell, clamp(cell, -half_count, half_count), finite);
+    return pos_transform(select(p, p - spacing * cell, repeated), (*group).transform);
+}
+
 fn sdf_plane(index: u32, pnt: vec3<f32>) -> f32 {
     let plane = &shapes.planes[index];
-    return pos_transform(pnt, (*plane).inv_transform).z * (*plane).scale;
+    let local = pos_transform(pnt, (*plane).inv_transform);
+    let p = elongate(deform(local, (*plane).modifiers), (*plane).modifiers);
+    return modify(p.z, local, (*plane).modifiers) * (*plane).scale;
 }
 
 fn sdf_sphere(index: u32, pnt: vec3<f32>) -> f32 {
     let sphere = &shapes.spheres[index];
-    return (length(pos_transform(pnt, (*sphere).inv_transform)) - (*sphere).radius) * (*sphere).scale;
+    let local = pos_transform(pnt, (*sphere).inv_transform);
+    let p = elongate(deform(local, (*sphere).modifiers), (*sphere).modifiers);
+    return modify(length(p) - (*sphere).radius, local, (*sphere).modifiers) * (*sphere).scale;
 }
 
 fn sdf_cube(index: u32, pnt: vec3<f32>) -> f32 {
     let cube = &shapes.cubes[index];
-    let q = abs(pos_transform(pnt, (*cube).inv_transform)) - (*cube).bounds;
-    return (length(max(q, vec3(0.0))) + min(max(q.x, max(q.y, q.z)), 0.0)) * (*cube).scale;
+    let local = pos_transform(pnt, (*cube).inv_transform);
+    let p = elongate(deform(local, (*cube).modifiers), (*cube).modifiers);
+    let q = abs(p) - (*cube).bounds;
+    let distance = length(max(q, vec3(0.0))) + min(max(q.x, max(q.y, q.z)), 0.0);
+    return modify(distance, local, (*cube).modifiers) * (*cube).scale;
+}
+
+fn sdf_torus(index: u32, pnt: vec3<f32>) -> f32 {
+    let torus = &shapes.tori[index];
+    let local = pos_transform(pnt, (*torus).inv_transform);
+    let p = elongate(deform(local, (*torus).modifiers), (*torus).modifiers);
+    let q = vec2(length(p.xy) - (*torus).major_radius, p.z);
+    return modify(length(q) - (*torus).minor_radius, local, (*torus).modifiers) * (*torus).scale;
+}
+
+fn sdf_cylinder(index: u32, pnt: vec3<f32>) -> f32 {
+    let cylinder = &shapes.cylinders[index];
+    let local = pos_transform(pnt, (*cylinder).inv_transform);
+    let p = elongate(deform(local, (*cylinder).modifiers), (*cylinder).modifiers);
+    let q = abs(vec2(length(p.xy), p.z)) - vec2((*cylinder).radius, (*cylinder).half_height);
+    let distance = length(max(q, vec2(0.0))) + min(max(q.x, q.y), 0.0);
+    return modify(distance, local, (*cylinder).modifiers) * (*cylinder).scale;
+}
+
+fn sdf_capsule(index: u32, pnt: vec3<f32>) -> f32 {
+    let capsule = &shapes.capsules[index];
+    let local = pos_transform(pnt, (*capsule).inv_transform);
+    var p = elongate(deform(local, (*capsule).modifiers), (*capsule).modifiers);
+    p.z -= clamp(p.z, -(*capsule).half_height, (*capsule).half_height);
+    return modify(length(p) - (*capsule).radius, local, (*capsule).modifiers) * (*capsule).scale;
+}
+
+fn sdf_cone(index: u32, pnt: vec3<f32>) -> f32 {
+    let cone = &shapes.cones[index];
+    let local = pos_transform(pnt, (*cone).inv_transform);
+    let p = elongate(deform(local, (*cone).modifiers), (*cone).modifiers);
+    let radius = (*cone).radius;
+    let half_height = (*cone).half_height;
+    let q = vec2(length(p.xy), p.z);
+    // Distances to the base and to the slanted side
+    let base = vec2(q.x - min(q.x, select(0.0, radius, q.y < 0.0)), abs(q.y) - half_height);
+    let side = vec2(-radius, 2.0 * half_height);
+    let apex = q - vec2(0.0, half_height);
+    let slant = apex + side * clamp(dot(-apex, side) / dot(side, side), 0.0, 1.0);
+    let distance = sqrt(min(dot(base, base), dot(slant, slant)));
+    let signed_distance = select(distance, -distance, slant.x < 0.0 && base.y < 0.0);
+    return modify(signed_distance, local, (*cone).modifiers) * (*cone).scale;
 }
 
 fn sdf_image(index: u32, texture_index: u32, texture_image: texture_3d<f32>, pnt: vec3<f32>) -> f32 {
     let image = &shapes.images[index];
     let properties = &shapes.texture_properties[texture_index];
-    let transformed_pnt = pos_transform(pnt, (*image).inv_transform);
+    let local = pos_transform(pnt, (*image).inv_transform);
+    let transformed_pnt = elongate(deform(local, (*image).modifiers), (*image).modifiers);
     let q = abs(transformed_pnt) - (*properties).bounds;
     let cube_distance = length(max(q, vec3(0.0)));
     let image_distance = textureSample(
         texture_image, shape_sampler,
         (transformed_pnt / (*properties).texture_bounds + vec3(1.0)) * 0.5
     ).r;
-    return select(
+    let distance = select(
         image_distance,
         length(vec2(cube_distance, image_distance)),
         cube_distance > 0.0
-    ) * (*image).scale;
+    );
+    return modify(distance, local, (*image).modifiers) * (*image).scale;
+}
+
+fn sdf_extrusion(index: u32, pnt: vec3<f32>) -> f32 {
+    let extrusion = &shapes.extrusions[index];
+    let local = pos_transform(pnt, (*extrusion).inv_transform);
+    let p = elongate(deform(local, (*extrusion).modifiers), (*extrusion).modifiers);
+    let w = vec2(sdf_profile((*extrusion).profile, p.xy), abs(p.z) - (*extrusion).half_height);
+    let distance = min(max(w.x, w.y), 0.0) + length(max(w, vec2(0.0)));
+    return modify(distance, local, (*extrusion).modifiers) * (*extrusion).scale;
+}
+
+fn sdf_revolution(index: u32, pnt: vec3<f32>) -> f32 {
+    let revolution = &shapes.revolutions[index];
+    let local = pos_transform(pnt, (*revolution).inv_transform);
+    let p = elongate(deform(local, (*revolution).modifiers), (*revolution).modifiers);
+    let q = vec2(length(p.xy) - (*revolution).offset, p.z);
+    return modify(sdf_profile((*revolution).profile, q), local, (*revolution).modifiers) * (*revolution).scale;
+}
+
+fn sdf_profile(profile: Profile, pnt: vec2<f32>) -> f32 {
+    var distance: f32;
+    switch profile.kind {
+        // Circle
+        case 0u: {
+            distance = length(pnt) - profile.parameters.x;
+        }
+        // Rectangle
+        case 1u: {
+            let q = abs(pnt) - profile.parameters.xy;
+            distance = length(max(q, vec2(0.0))) + min(max(q.x, q.y), 0.0);
+        }
+        // Polygon
+        case 2u: {
+            distance = sdf_polygon(profile.point_offset, profile.point_count, pnt);
+        }
+        // Arc
+        default: {
+            let radius = profile.parameters.x;
+            let aperture = vec2(sin(profile.parameters.y), cos(profile.parameters.y));
+            let p = vec2(abs(pnt.x), pnt.y);
+            distance = select(
+                abs(length(p) - radius),
+                length(p - aperture * radius),
+                aperture.y * p.x > aperture.x * p.y
+            ) - profile.parameters.z;
+        }
+    }
+    return distance;
+}
+
+// from: https://iquilezles.org/articles/distfunctions2d/
+fn sdf_polygon(offset: u32, count: u32, pnt: vec2<f32>) -> f32 {
+    if count == 0u {
+        return #{FAR}f;
+    }
+    var distance = #{FAR}f * #{FAR}f;
+    var inside = false;
+    var j = count - 1u;
+    for (var i = 0u; i < count; i = i + 1u) {
+        let a = shapes.points[offset + i].xy;
+        let b = shapes.points[offset + j].xy;
+        let edge = b - a;
+        let w = pnt - a;
+        let q = w - edge * clamp(dot(w, edge) / max(dot(edge, edge), 1e-12), 0.0, 1.0);
+        distance = min(distance, dot(q, q));
+        let crossing = vec3(pnt.y >= a.y, pnt.y < b.y, edge.x * w.y > edge.y * w.x);
+        if all(crossing) || !any(crossing) {
+            inside = !inside;
+        }
+        j = i;
+    }
+    return select(sqrt(distance), -sqrt(distance), inside);
+}
+
+fn sdf_tube(index: u32, pnt: vec3<f32>) -> f32 {
+    let tube = &shapes.tubes[index];
+    let local = pos_transform(pnt, (*tube).inv_transform);
+    let p = elongate(deform(local, (*tube).modifiers), (*tube).modifiers);
+    let segment_count = (*tube).segment_count;
+    var distance = #{FAR}f;
+    for (var i = 0u; i < segment_count; i = i + 1u) {
+        let offset = (*tube).point_offset + 2u * i;
+        let curve = sdf_bezier(
+            p,
+            shapes.points[offset].xyz,
+            shapes.points[offset + 1u].xyz,
+            shapes.points[offset + 2u].xyz
+        );
+        // The radius at the closest point of the curve
+        let progress = (f32(i) + curve.y) / f32(segment_count);
+        let radius = mix((*tube).start_radius, (*tube).end_radius, progress);
+        distance = min(distance, curve.x - radius);
+    }
+    return modify(distance * (*tube).taper_scale, local, (*tube).modifiers) * (*tube).scale;
+}
+
+// The distance to a quadratic Bezier curve and the parameter of the closest point
+// from: https://www.shadertoy.com/view/ldj3Wh
+fn sdf_bezier(pnt: vec3<f32>, start: vec3<f32>, control: vec3<f32>, end: vec3<f32>) -> vec2<f32> {
+    let a = control - start;
+    let b = start - 2.0 * control + end;
+    let c = a * 2.0;
+    let d = start - pnt;
+
+    // A straight line when the control point is halfway between the ends
+    if dot(b, b) < 1e-8 {
+        let line = end - start;
+        let t = clamp(dot(-d, line) / max(dot(line, line), 1e-12), 0.0, 1.0);
+        return vec2(length(d + line * t), t);
+    }
+
+    let kk = 1.0 / dot(b, b);
+    let kx = kk * dot(a, b);
+    let ky = kk * (2.0 * dot(a, a) + dot(d, b)) / 3.0;
+    let kz = kk * dot(d, a);
+    let p = ky - kx * kx;
+    let q = kx * (2.0 * kx * kx - 3.0 * ky) + kz;
+    let h = q * q + 4.0 * p * p * p;
+
+    if h >= 0.0 {
+        let x = (vec2(sqrt(h), -sqrt(h)) - q) / 2.0;
+        let uv = sign(x) * pow(abs(x), vec2(1.0 / 3.0));
+        let t = clamp(uv.x + uv.y - kx, 0.0, 1.0);
+        return vec2(length(d + (c + b * t) * t), t);
+    }
+
+    let z = sqrt(-p);
+    let v = acos(q / (p * z * 2.0)) / 3.0;
+    let m = cos(v);
+    let n = sin(v) * 1.732050808;
+    let t = clamp(vec2(m + m, -n - m) * z - kx, vec2(0.0), vec2(1.0));
+    let first = length(d + (c + b * t.x) * t.x);
+    let second = length(d + (c + b * t.y) * t.y);
+    return select(vec2(first, t.x), vec2(second, t.y), second < first);
+}
+
+// The footprint is a unit square around the origin and the heights go from 0 to 1 up the z axis
+fn sdf_heightfield(index: u32, pnt: vec3<f32>) -> f32 {
+    let heightfield = &shapes.heightfields[index];
+    let local = pos_transform(pnt, (*heightfield).inv_transform);
+    let p = elongate(deform(local, (*heightfield).modifiers), (*heightfield).modifiers);
+    let height = sample_height((*heightfield).texture_index, vec2(p.x + 0.5, 0.5 - p.y));
+    let q = abs(p - vec3(0.0, 0.0, 0.5)) - vec3(0.5);
+    let box_distance = length(max(q, vec3(0.0))) + min(max(q.x, max(q.y, q.z)), 0.0);
+    // The height above the surface is scaled by the steepest slope to keep it a bound
+    let distance = max(box_distance, (p.z - height) * (*heightfield).height_scale);
+    return modify(distance, local, (*heightfield).modifiers) * (*heightfield).scale;
+}
+
+fn sample_height(texture_index: u32, uv: vec2<f32>) -> f32 {
+    if texture_index == 0u {
+        return textureSampleLevel(heightfield_texture_0, shape_sampler, uv, 0.0).r;
+    }
+    return textureSampleLevel(heightfield_texture_1, shape_sampler, uv, 0.0).r;
+}
+
+// The custom shapes call the functions generated from their sources with the elongated point
+fn elongate_custom(index: u32, pnt: vec3<f32>) -> vec3<f32> {
+    let custom = &shapes.customs[index];
+    let local = pos_transform(pnt, (*custom).inv_transform);
+    return elongate(deform(local, (*custom).modifiers), (*custom).modifiers);
+}
+
+fn modify_custom(index: u32, pnt: vec3<f32>, distance: f32) -> f32 {
+    let custom = &shapes.customs[index];
+    let local = pos_transform(pnt, (*custom).inv_transform);
+    return modify(distance, local, (*custom).modifiers) * (*custom).scale;
+}
+
+// The steepest slope of the kernel (1 - x^2)^3, at x = 1 / sqrt(5)
+const METABALL_GRADIENT = 1.7173002;
+
+fn metaball_point(index: u32) -> MetaballPoint {
+    let position = textureLoad(metaball_points, data_texel(2u * index), 0);
+    let color = textureLoad(metaball_points, data_texel(2u * index + 1u), 0);
+    return MetaballPoint(position.xyz, position.w, color.xyz);
+}
+
+// The fields of the points fall from 1 at their centers to 0 at their radius of influence, and
+// the surface is where their sum reaches the threshold. The distance is estimated with the
+// steepest slopes of the points in range, and stays below the distance to the influence of the
+// other points, so that none of them can add to the field within it
+fn sdf_metaballs(index: u32, pnt: vec3<f32>) -> f32 {
+    let metaballs = &shapes.metaballs[index];
+    let local = pos_transform(pnt, (*metaballs).inv_transform);
+    let p = elongate(deform(local, (*metaballs).modifiers), (*metaballs).modifiers);
+    var field = 0.0;
+    var gradient = 0.0;
+    var outside = #{FAR}f;
+    let end = (*metaballs).point_offset + (*metaballs).point_count;
+    for (var i = (*metaballs).point_offset; i < end; i = i + 1u) {
+        let point = metaball_point(i);
+        let distance = length(p - point.position);
+        if distance < point.influence {
+            let h = 1.0 - distance * distance / (point.influence * point.influence);
+            field += h * h * h;
+            gradient += METABALL_GRADIENT / point.influence;
+        } else {
+            outside = min(outside, distance - point.influence);
+        }
+    }
+    let distance = min(((*metaballs).threshold - field) / max(gradient, 0.0001), outside);
+    return modify(distance, local, (*metaballs).modifiers) * (*metaballs).scale;
+}
+
+// The colors of the points weighted by their fields
+fn metaballs_material(index: u32, pnt: vec3<f32>) -> Material {
+    let metaballs = &shapes.metaballs[index];
+    let local = pos_transform(pnt, (*metaballs).inv_transform);
+    let p = elongate(deform(local, (*metaballs).modifiers), (*metaballs).modifiers);
+    var color = vec3(0.0);
+    var weight = 0.0;
+    let end = (*metaballs).point_offset + (*metaballs).point_count;
+    for (var i = (*metaballs).point_offset; i < end; i = i + 1u) {
+        let point = metaball_point(i);
+        let offset = p - point.position;
+        let h = max(1.0 - dot(offset, offset) / (point.influence * point.influence), 0.0);
+        color += h * h * h * point.color;
+        weight += h * h * h;
+    }
+    if weight == 0.0 {
+        return (*metaballs).material;
+    }
+    return Material(color / weight);
+}
+
+const OCTREE_LEAF = 0x80000000u;
+
+fn octree_node(index: u32) -> u32 {
+    return textureLoad(octree_nodes, data_texel(index), 0).x;
+}
+
+fn octree_sample(index: u32) -> f32 {
+    return textureLoad(octree_samples, data_texel(index), 0).x;
+}
+
+fn sdf_octree(index: u32, pnt: vec3<f32>) -> f32 {
+    let octree = &shapes.octrees[index];
+    let local = pos_transform(pnt, (*octree).inv_transform);
+    let transformed_pnt = elongate(deform(local, (*octree).modifiers), (*octree).modifiers);
+    var half_size = (*octree).size * 0.5;
+    let q = abs(transformed_pnt) - vec3(half_size);
+    let cube_distance = length(max(q, vec3(0.0)));
+    let octree_pnt = clamp(transformed_pnt, vec3(-half_size), vec3(half_size));
+
+    var node = octree_node((*octree).node_offset);
+    var center = vec3(0.0);
+    while (node & OCTREE_LEAF) == 0u {
+        half_size *= 0.5;
+        let child = select(0u, 1u, octree_pnt.x > center.x) |
+            select(0u, 2u, octree_pnt.y > center.y) |
+            select(0u, 4u, octree_pnt.z > center.z);
+        center += select(vec3(-half_size), vec3(half_size), octree_pnt > center);
+        node = octree_node((*octree).node_offset + node + child);
+    }
+
+    let samples = ((*octree).sample_offset + (node & ~OCTREE_LEAF)) * 8u;
+    let factor = (octree_pnt - center + half_size) / (2.0 * half_size);
+    let octree_distance = mix(
+        mix(
+            mix(octree_sample(samples), octree_sample(samples + 1u), factor.x),
+            mix(octree_sample(samples + 2u), octree_sample(samples + 3u), factor.x),
+            factor.y
+        ),
+        mix(
+            mix(octree_sample(samples + 4u), octree_sample(samples + 5u), factor.x),
+            mix(octree_sample(samples + 6u), octree_sample(samples + 7u), factor.x),
+            factor.y
+        ),
+        factor.z
+    );
+    let distance = select(
+        octree_distance,
+        length(vec2(cube_distance, octree_distance)),
+        cube_distance > 0.0
+    );
+    return modify(distance, local, (*octree).modifiers) * (*octree).scale;
 }
 
 
@@ -336,23 +952,163 @@ fn max_select(left: ptr<function, f32>, right: f32) -> bool {
     return *left == right;
 }
 
-const K = 0.4;
-fn smin(left: f32, right: f32) -> f32 {
-    let h = max(K - abs(left - right), 0.0) / K;
-    return min(left, right) - h * h * h * K * (1.0 / 2.0 / 3.0);
+// How much the smaller distance is lowered and the weight of the larger one, from the
+// difference between the distances
+fn smooth_blend(index: u32, difference: f32) -> vec2<f32> {
+    let group = &shapes.blend_groups[index];
+    let k = max((*group).k, 0.0001);
+    switch (*group).kernel {
+        // Exponential, lowered as much as the polynomial one where the distances are equal
+        case 1u: {
+            let k = k * (1.0 / 6.0);
+            let e = exp2(-difference / k);
+            return vec2(k * log2(1.0 + e), e / (1.0 + e));
+        }
+        // Circular
+        case 2u: {
+            let h = max(k - difference, 0.0) / k;
+            let root = sqrt(1.0 - h * (h - 2.0));
+            return vec2(k * 0.5 * (1.0 + h - root), 0.5 * (1.0 - (1.0 - h) / root));
+        }
+        // Polynomial
+        default: {
+            let h = max(k - difference, 0.0) / k;
+            let m = h * h * h * 0.5;
+            return vec2(m * k * (1.0 / 3.0), m);
+        }
+    }
 }
 
-fn smin_mix(left: ptr<function, f32>, right: f32) -> f32 {
-    let h = max(K - abs(*left - right), 0.0) / K;
-    let m = h * h * h * 0.5;
-    let s = m * K * (1.0 / 3.0);
+fn smin(index: u32, left: f32, right: f32) -> f32 {
+    return min(left, right) - smooth_blend(index, abs(left - right)).x;
+}
+
+fn smin_mix(index: u32, left: ptr<function, f32>, right: f32) -> f32 {
+    let blend = smooth_blend(index, abs(*left - right));
     if *left < right {
-        *left = *left - s;
-        return m;
+        *left = *left - blend.x;
+        return blend.y;
     } else {
-        *left = right - s;
-        return 1.0 - m;
+        *left = right - blend.x;
+        return 1.0 - blend.y;
+    }
+}
+
+fn smax(index: u32, left: f32, right: f32) -> f32 {
+    return -smin(index, -left, -right);
+}
+
+fn smax_mix(index: u32, left: ptr<function, f32>, right: f32) -> f32 {
+    var negated = -*left;
+    let factor = smin_mix(index, &negated, -right);
+    *left = -negated;
+    return factor;
+}
+
+// Replaces the left distance with the blended one and returns if the right one is nearer or
+// farther
+fn union_select(left: ptr<function, f32>, right: f32, blended: f32) -> bool {
+    let nearer = right < *left;
+    *left = blended;
+    return nearer;
+}
+
+fn intersection_select(left: ptr<function, f32>, right: f32, blended: f32) -> bool {
+    let farther = right > *left;
+    *left = blended;
+    return farther;
+}
+
+// The result has the sign of the divisor, unlike %
+fn floor_mod(value: f32, divisor: f32) -> f32 {
+    return value - divisor * floor(value / divisor);
+}
+
+fn chamfer_union(index: u32, left: f32, right: f32) -> f32 {
+    let k = shapes.blend_groups[index].k;
+    return min(min(left, right), (left - k + right) * sqrt(0.5));
+}
+
+fn chamfer_intersection(index: u32, left: f32, right: f32) -> f32 {
+    let k = shapes.blend_groups[index].k;
+    return max(max(left, right), (left + k + right) * sqrt(0.5));
+}
+
+fn stairs_union(index: u32, left: f32, right: f32) -> f32 {
+    let group = &shapes.blend_groups[index];
+    let step = max((*group).k, 0.0001) / f32(max((*group).count, 1u));
+    let u = right - (*group).k;
+    let stairs = 0.5 * (u + left + abs(floor_mod(u - left + step, 2.0 * step) - step));
+    return min(min(left, right), stairs);
+}
+
+fn stairs_intersection(index: u32, left: f32, right: f32) -> f32 {
+    return -stairs_union(index, -left, -right);
+}
+
+fn column_radius(index: u32) -> f32 {
+    let group = &shapes.blend_groups[index];
+    let count = f32(max((*group).count, 1u));
+    return max((*group).k, 0.0001) * sqrt(2.0) / ((count - 1.0) * 2.0 + sqrt(2.0));
+}
+
+fn columns_union(index: u32, left: f32, right: f32) -> f32 {
+    let group = &shapes.blend_groups[index];
+    let k = max((*group).k, 0.0001);
+    if left >= k || right >= k {
+        return min(left, right);
+    }
+    let radius = column_radius(index);
+    var pnt = vec2(left + right, right - left) * sqrt(0.5);
+    pnt.x += radius * sqrt(2.0) - sqrt(0.5) * k;
+    if (*group).count % 2u == 1u {
+        pnt.y += radius;
     }
+    pnt.y = floor_mod(pnt.y + radius, radius * 2.0) - radius;
+    return min(min(length(pnt) - radius, pnt.x), min(left, right));
+}
+
+fn columns_intersection(index: u32, left: f32, right: f32) -> f32 {
+    let group = &shapes.blend_groups[index];
+    let k = max((*group).k, 0.0001);
+    if -left >= k || -right >= k {
+        return max(left, right);
+    }
+    let radius = column_radius(index);
+    var pnt = vec2(-left - right, left - right) * sqrt(0.5);
+    pnt.y += radius;
+    pnt.x -= sqrt(0.5) * (k + radius);
+    if (*group).count % 2u == 1u {
+        pnt.y += radius;
+    }
+    pnt.y = floor_mod(pnt.y + radius, radius * 2.0) - radius;
+    return -min(min(max(radius - length(pnt), pnt.x), -left), -right);
+}
+
+fn engrave(index: u32, left: f32, right: f32) -> f32 {
+    let depth = shapes.blend_groups[index].depth;
+    return max(left, (left + depth - abs(right)) * sqrt(0.5));
+}
+
+fn groove(index: u32, left: f32, right: f32) -> f32 {
+    let group = &shapes.blend_groups[index];
+    return max(left, min(left + (*group).depth, (*group).k - abs(right)));
+}
+
+fn tongue(index: u32, left: f32, right: f32) -> f32 {
+    let group = &shapes.blend_groups[index];
+    return min(left, max(left - (*group).depth, abs(right) - (*group).k));
+}
+
+fn morph(index: u32, left: f32, right: f32) -> f32 {
+    return mix(left, right, shapes.blend_groups[index].t);
+}
+
+// Replaces the left distance with the morphed one and returns the factor of the right one
+fn morph_mix(index: u32, left: ptr<function, f32>, right: f32) -> f32 {
+    let t = shapes.blend_groups[index].t;
+    *left = mix(*left, right, t);
+    return t;
 }
 
 fn mix_material(left: Material, right: Material, factor: f32) -> Material {
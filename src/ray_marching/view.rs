@@ -98,7 +98,7 @@ fn prepare_views(
         .collect::<Vec<_>>();
 
     commands.insert_or_spawn_batch(entities);
-    uniform_buffer.0.write_buffer(&*device, &*queue);
+    uniform_buffer.0.write_buffer(&device, &queue);
 }
 
 #[derive(Resource)]
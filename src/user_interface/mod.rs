@@ -1,4 +1,5 @@
 mod diagnostics;
+mod sculpting;
 mod shape;
 mod shapes;
 mod view;
@@ -9,12 +10,16 @@ pub struct UIPlugin;
 
 impl Plugin for UIPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.init_resource::<SelectedShape>().add_systems((
-            diagnostics::ui,
-            view::ui.after(diagnostics::ui),
-            shapes::ui.after(view::ui),
-            shape::ui.after(shapes::ui),
-        ));
+        app.init_resource::<SelectedShape>()
+            .init_resource::<sculpting::SculptSettings>()
+            .add_systems((
+                diagnostics::ui,
+                view::ui.after(diagnostics::ui),
+                shapes::ui.after(view::ui),
+                shape::ui.after(shapes::ui),
+                sculpting::ui.after(shape::ui),
+                sculpting::sculpt.after(sculpting::ui),
+            ));
     }
 }
 
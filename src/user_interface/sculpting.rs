@@ -0,0 +1,157 @@
+use super::SelectedShape;
+use crate::ray_marching::{
+    Brush, BrushMode, Primitive::Image, RayMarching, Sculpt, Shape, ShapeImage,
+    ShapeType::Primitive,
+};
+use bevy::{
+    prelude::{
+        warn, Assets, Camera, Commands, GlobalTransform, Input, MouseButton, Query, Res, ResMut,
+        Resource, Window, With,
+    },
+    window::PrimaryWindow,
+};
+use bevy_egui::{
+    egui::{self, DragValue, Grid},
+    EguiContexts,
+};
+
+#[derive(Resource)]
+pub struct SculptSettings {
+    enabled: bool,
+    brush: Brush,
+    path: String,
+}
+
+impl Default for SculptSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            brush: Brush::default(),
+            path: "assets/sculpted.sdf".into(),
+        }
+    }
+}
+
+pub fn ui(
+    mut egui_contexts: EguiContexts,
+    mut settings: ResMut<SculptSettings>,
+    selected_shape: Res<SelectedShape>,
+    shapes: Query<&Shape>,
+    images: Res<Assets<ShapeImage>>,
+) {
+    let Some(Ok(Shape {
+        shape_type: Primitive(Image(handle), _),
+        ..
+    })) = selected_shape.0.map(|entity| shapes.get(entity))
+    else {
+        return;
+    };
+
+    egui::Window::new("Sculpting")
+        .collapsible(false)
+        .show(egui_contexts.ctx_mut(), |ui| {
+            Grid::new("brush").num_columns(2).show(ui, |ui| {
+                ui.label("Enabled:");
+                ui.checkbox(&mut settings.enabled, "");
+                ui.end_row();
+                ui.label("Mode:");
+                ui.horizontal(|ui| {
+                    ui.selectable_value(&mut settings.brush.mode, BrushMode::Add, "Add");
+                    ui.selectable_value(&mut settings.brush.mode, BrushMode::Subtract, "Subtract");
+                    ui.selectable_value(&mut settings.brush.mode, BrushMode::Smooth, "Smooth");
+                });
+                ui.end_row();
+                ui.label("Radius:");
+                ui.add(
+                    DragValue::new(&mut settings.brush.radius)
+                        .clamp_range(0.001..=10.0)
+                        .speed(0.01),
+                );
+                ui.end_row();
+                ui.label("Smoothness:");
+                ui.add(
+                    DragValue::new(&mut settings.brush.smoothness)
+                        .clamp_range(0.0..=1.0)
+                        .speed(0.01),
+                );
+                ui.end_row();
+            });
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut settings.path);
+                if ui.button("Save").clicked() {
+                    match images.get(handle) {
+                        Some(image) => {
+                            let bytes = Box::<[u8]>::from(image.clone());
+                            if let Err(error) = std::fs::write(&settings.path, bytes) {
+                                warn!("Failed to save the image: {error}");
+                            }
+                        }
+                        None => warn!("The image of the shape isn't loaded"),
+                    }
+                }
+            });
+        });
+}
+
+// Sculpts the selected image shape where the cursor points at it while the left button is held
+pub fn sculpt(
+    mut commands: Commands,
+    mut egui_contexts: EguiContexts,
+    (settings, selected_shape): (Res<SculptSettings>, Res<SelectedShape>),
+    mouse_buttons: Res<Input<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<RayMarching>>,
+    (shapes, images): (Query<(&Shape, &GlobalTransform)>, Res<Assets<ShapeImage>>),
+) {
+    if !settings.enabled
+        || !mouse_buttons.pressed(MouseButton::Left)
+        || egui_contexts.ctx_mut().is_pointer_over_area()
+    {
+        return;
+    }
+
+    let Some(entity) = selected_shape.0 else {
+        return;
+    };
+    let Ok((
+        Shape {
+            shape_type: Primitive(Image(handle), _),
+            ..
+        },
+        transform,
+    )) = shapes.get(entity)
+    else {
+        return;
+    };
+    let Some(image) = images.get(handle) else {
+        return;
+    };
+    let Some(cursor) = windows
+        .get_single()
+        .ok()
+        .and_then(|window| window.cursor_position())
+    else {
+        return;
+    };
+    let Some(ray) = cameras
+        .get_single()
+        .ok()
+        .and_then(|(camera, camera_transform)| camera.viewport_to_world(camera_transform, cursor))
+    else {
+        return;
+    };
+
+    let inv_transform = transform.compute_matrix().inverse();
+    let hit = image.raycast(
+        inv_transform.transform_point3(ray.origin),
+        inv_transform.transform_vector3(ray.direction),
+    );
+    if let Some(hit) = hit {
+        commands.add(Sculpt {
+            entity,
+            position: transform.transform_point(hit),
+            brush: settings.brush,
+        });
+    }
+}
@@ -1,28 +1,65 @@
 use super::SelectedShape;
 use crate::{
     ray_marching::{
-        Material,
-        Operation::{Intersection, Union, SmoothUnion},
-        Primitive::{Cube, Image, Plane, Sphere},
-        Shape, ShapeImage,
+        BakeShape, BakedShape, Blend, Material,
+        Operation::{
+            self, ChamferIntersection, ChamferUnion, ColumnsIntersection, ColumnsUnion, Engrave,
+            Groove, Intersection, Morph, SmoothIntersection, SmoothSubtraction, SmoothUnion,
+            StairsIntersection, StairsUnion, Subtraction, Tongue, Union,
+        },
+        Primitive::{
+            Capsule, Cone, Cube, Custom, Cylinder, Extrusion, Heightfield, Image, Metaballs,
+            Octree, Plane, Revolution, Sphere, Torus, Tube,
+        },
+        Profile, RestoreShape, Shape,
         ShapeType::{self, Compound, Primitive},
+        SmoothKernel,
     },
-    Images,
+    Heightfields, Images, Octrees,
 };
 use bevy::prelude::{
-    BuildChildren, Commands, DespawnRecursiveExt, Entity, EulerRot, Handle, Mut, Name, Parent,
-    Quat, Query, Res, Transform, Vec3,
+    BuildChildren, Children, Commands, DespawnRecursiveExt, Entity, EulerRot, Local, Mut, Name,
+    Parent, Quat, Query, Res, Transform, Vec2, Vec3, Vec4,
 };
 use bevy_egui::{
     egui::{Align, ComboBox, DragValue, Grid, Layout, Ui, Window},
     EguiContexts,
 };
+use std::f32::consts::PI;
 
+pub struct BakeSettings {
+    resolution: u32,
+    padding: u32,
+}
+
+impl Default for BakeSettings {
+    fn default() -> Self {
+        Self {
+            resolution: 64,
+            padding: 4,
+        }
+    }
+}
+
+// The custom sources are edited in a copy and only applied when compiling, so the shader isn't
+// generated again for every edit
+#[derive(Default)]
+pub struct CustomSourceEdit {
+    entity: Option<Entity>,
+    applied: String,
+    source: String,
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn ui(
     mut commands: Commands,
     mut egui_contexts: EguiContexts,
+    mut bake_settings: Local<BakeSettings>,
+    mut custom_source_edit: Local<CustomSourceEdit>,
     selected_shape: Res<SelectedShape>,
     images: Res<Images>,
+    octrees: Res<Octrees>,
+    heightfields: Res<Heightfields>,
     mut shapes: Query<(
         Entity,
         &mut Name,
@@ -30,6 +67,7 @@ pub fn ui(
         &mut Transform,
         &mut Shape,
     )>,
+    hierarchy: Query<(Option<&Children>, Option<&BakedShape>)>,
 ) {
     let Some(selected_entity) = selected_shape.0 else {
         return
@@ -49,6 +87,13 @@ pub fn ui(
     let Ok((entity, name, parent, transform, shape)) = shapes.get_mut(selected_entity) else {
         return
     };
+    let (children, baked_shape) = hierarchy.get(entity).unwrap_or_default();
+    if custom_source_edit.entity != Some(entity) {
+        *custom_source_edit = CustomSourceEdit {
+            entity: Some(entity),
+            ..Default::default()
+        };
+    }
 
     Window::new("Shape")
         .collapsible(false)
@@ -57,8 +102,25 @@ pub fn ui(
             ui.separator();
             transform_ui(ui, transform);
             ui.separator();
-            shape_ui(ui, &images, shape);
+            shape_ui(
+                ui,
+                &images,
+                &octrees,
+                &heightfields,
+                &mut custom_source_edit,
+                children.is_some(),
+                shape,
+            );
             ui.separator();
+            if baked_shape.is_some() {
+                if ui.button("Restore").clicked() {
+                    commands.add(RestoreShape { entity });
+                }
+                ui.separator();
+            } else if children.is_some() {
+                bake_ui(ui, &mut commands, &mut bake_settings, entity);
+                ui.separator();
+            }
             if ui.button("Delete").clicked() {
                 commands.entity(entity).despawn_recursive();
             }
@@ -87,13 +149,12 @@ fn name_parent_ui(
         ComboBox::new("parent", "")
             .selected_text(
                 parent
-                    .map(|parent| {
+                    .and_then(|parent| {
                         parents
                             .iter()
                             .find(|(entity, _)| *entity == parent)
                             .map(|(_, name)| name.as_str())
                     })
-                    .flatten()
                     .unwrap_or("none"),
             )
             .show_ui(ui, |ui| {
@@ -128,10 +189,18 @@ fn transform_ui(ui: &mut Ui, mut transform: Mut<Transform>) {
     });
 }
 
-fn shape_ui(ui: &mut Ui, images: &Images, mut shape: Mut<Shape>) {
+fn shape_ui(
+    ui: &mut Ui,
+    images: &Images,
+    octrees: &Octrees,
+    heightfields: &Heightfields,
+    custom_source_edit: &mut CustomSourceEdit,
+    group: bool,
+    mut shape: Mut<Shape>,
+) {
     Grid::new("shape").num_columns(2).show(ui, |ui| {
         ui.label("Type:");
-        shape_type_ui(ui, images, &mut shape.shape_type);
+        shape_type_ui(ui, images, octrees, heightfields, &mut shape.shape_type);
         ui.end_row();
 
         if let Primitive(ref mut primitive, ref mut material) = &mut shape.shape_type {
@@ -146,38 +215,429 @@ fn shape_ui(ui: &mut Ui, images: &Images, mut shape: Mut<Shape>) {
                     vec_ui(ui, size);
                     ui.end_row();
                 }
+                Torus {
+                    ref mut major_radius,
+                    ref mut minor_radius,
+                } => {
+                    ui.label("Major radius:");
+                    scalar_ui(ui, major_radius);
+                    ui.end_row();
+                    ui.label("Minor radius:");
+                    scalar_ui(ui, minor_radius);
+                    ui.end_row();
+                }
+                Cylinder {
+                    ref mut radius,
+                    ref mut height,
+                }
+                | Capsule {
+                    ref mut radius,
+                    ref mut height,
+                }
+                | Cone {
+                    ref mut radius,
+                    ref mut height,
+                } => {
+                    ui.label("Radius:");
+                    scalar_ui(ui, radius);
+                    ui.end_row();
+                    ui.label("Height:");
+                    scalar_ui(ui, height);
+                    ui.end_row();
+                }
+                Extrusion {
+                    ref mut profile,
+                    ref mut height,
+                } => {
+                    ui.label("Height:");
+                    scalar_ui(ui, height);
+                    ui.end_row();
+                    profile_ui(ui, profile);
+                }
+                Revolution {
+                    ref mut profile,
+                    ref mut offset,
+                } => {
+                    ui.label("Offset:");
+                    scalar_ui(ui, offset);
+                    ui.end_row();
+                    profile_ui(ui, profile);
+                }
+                Tube {
+                    ref mut points,
+                    ref mut start_radius,
+                    ref mut end_radius,
+                } => {
+                    ui.label("Start radius:");
+                    scalar_ui(ui, start_radius);
+                    ui.end_row();
+                    ui.label("End radius:");
+                    scalar_ui(ui, end_radius);
+                    ui.end_row();
+                    tube_points_ui(ui, points);
+                }
+                Custom {
+                    ref mut source,
+                    ref mut parameters,
+                } => {
+                    for (i, parameter) in parameters.iter_mut().enumerate() {
+                        ui.label(format!("Parameter {i}:"));
+                        vec4_ui(ui, parameter);
+                        ui.end_row();
+                    }
+                    // Starting over when the source was changed somewhere else
+                    if custom_source_edit.applied != *source {
+                        custom_source_edit.applied = source.clone();
+                        custom_source_edit.source = source.clone();
+                    }
+                    ui.label("Source:");
+                    ui.code_editor(&mut custom_source_edit.source);
+                    ui.end_row();
+                    ui.label("");
+                    if ui.button("Compile").clicked() {
+                        *source = custom_source_edit.source.clone();
+                        custom_source_edit.applied = source.clone();
+                    }
+                    ui.end_row();
+                }
+                Metaballs { ref mut threshold } => {
+                    ui.label("Threshold:");
+                    scalar_ui(ui, threshold);
+                    *threshold = threshold.clamp(0.0, 1.0);
+                    ui.end_row();
+                }
                 _ => {}
             }
             ui.label("Color:");
             color_ui(ui, &mut material.color);
             ui.end_row();
         }
+        if let Compound(
+            Subtraction {
+                ref mut cutter_material,
+            }
+            | SmoothSubtraction {
+                ref mut cutter_material,
+            },
+        ) = &mut shape.shape_type
+        {
+            ui.label("Cutter material:");
+            ui.checkbox(cutter_material, "");
+            ui.end_row();
+        }
+        if let Compound(operation) = shape.shape_type {
+            blend_ui(ui, operation, &mut shape.blend);
+        }
+
+        let modifiers = &mut shape.modifiers;
+        ui.label("Elongation:");
+        vec_ui(ui, &mut modifiers.elongation);
+        modifiers.elongation = modifiers.elongation.max(Vec3::ZERO);
+        ui.end_row();
+        ui.label("Rounding:");
+        scalar_ui(ui, &mut modifiers.rounding);
+        ui.end_row();
+        ui.label("Onion:");
+        scalar_ui(ui, &mut modifiers.onion);
+        ui.end_row();
+        ui.label("Twist:");
+        scalar_ui(ui, &mut modifiers.twist);
+        ui.end_row();
+        ui.label("Bend:");
+        scalar_ui(ui, &mut modifiers.bend);
+        ui.end_row();
+        ui.label("Taper:");
+        scalar_ui(ui, &mut modifiers.taper);
+        ui.end_row();
+        ui.label("Noise:");
+        ui.with_layout(Layout::left_to_right(Align::Center), |ui| {
+            ui.label("Amplitude:");
+            scalar_ui(ui, &mut modifiers.noise_amplitude);
+            ui.label("Frequency:");
+            scalar_ui(ui, &mut modifiers.noise_frequency);
+        });
+        ui.end_row();
 
         ui.label("Negative:");
         ui.checkbox(&mut shape.negative, "");
         ui.end_row();
+
+        // The domain only applies to the children
+        if group {
+            let domain = &mut shape.domain;
+            ui.label("Mirror:");
+            ui.with_layout(Layout::left_to_right(Align::Center), |ui| {
+                ui.checkbox(&mut domain.mirror.x, "X");
+                ui.checkbox(&mut domain.mirror.y, "Y");
+                ui.checkbox(&mut domain.mirror.z, "Z");
+            });
+            ui.end_row();
+            ui.label("Polar count:");
+            ui.add(DragValue::new(&mut domain.polar_count));
+            ui.end_row();
+            ui.label("Spacing:");
+            vec_ui(ui, &mut domain.spacing);
+            domain.spacing = domain.spacing.max(Vec3::ZERO);
+            ui.end_row();
+            ui.label("Count:");
+            ui.with_layout(Layout::left_to_right(Align::Center), |ui| {
+                ui.label("X:");
+                ui.add(DragValue::new(&mut domain.count.x));
+                ui.label("Y:");
+                ui.add(DragValue::new(&mut domain.count.y));
+                ui.label("Z:");
+                ui.add(DragValue::new(&mut domain.count.z));
+            })
+            .response
+            .on_hover_text("Zero repeats infinitely");
+            ui.end_row();
+        }
+    });
+}
+
+fn profile_ui(ui: &mut Ui, profile: &mut Profile) {
+    let half_size = profile.half_size();
+    let polygon = match profile {
+        Profile::Polygon(points) => points.clone(),
+        _ => rectangle_corners(half_size),
+    };
+    let arc = match profile {
+        Profile::Arc { .. } => profile.clone(),
+        _ => Profile::Arc {
+            radius: half_size.x,
+            angle: PI,
+            thickness: 0.1,
+        },
+    };
+
+    ui.label("Profile:");
+    ComboBox::new("profile", "")
+        .selected_text(match profile {
+            Profile::Circle { .. } => "Circle",
+            Profile::Rectangle { .. } => "Rectangle",
+            Profile::Polygon(..) => "Polygon",
+            Profile::Arc { .. } => "Arc",
+        })
+        .show_ui(ui, |ui| {
+            ui.selectable_value(
+                profile,
+                Profile::Circle {
+                    radius: half_size.x,
+                },
+                "Circle",
+            );
+            ui.selectable_value(
+                profile,
+                Profile::Rectangle {
+                    size: half_size * 2.0,
+                },
+                "Rectangle",
+            );
+            ui.selectable_value(profile, Profile::Polygon(polygon), "Polygon");
+            ui.selectable_value(profile, arc, "Arc");
+        });
+    ui.end_row();
+
+    match profile {
+        Profile::Circle { radius } => {
+            ui.label("Radius:");
+            scalar_ui(ui, radius);
+            ui.end_row();
+        }
+        Profile::Rectangle { size } => {
+            ui.label("Size:");
+            vec2_ui(ui, size);
+            ui.end_row();
+        }
+        Profile::Polygon(points) => {
+            let mut removed = None;
+            for (i, point) in points.iter_mut().enumerate() {
+                ui.label(format!("Point {i}:"));
+                ui.with_layout(Layout::left_to_right(Align::Center), |ui| {
+                    vec2_ui(ui, point);
+                    if ui.button("Remove").clicked() {
+                        removed = Some(i);
+                    }
+                });
+                ui.end_row();
+            }
+            if let Some(i) = removed {
+                points.remove(i);
+            }
+            ui.label("");
+            if ui.button("Add point").clicked() {
+                // Halfway along the edge closing the polygon
+                let point = match (points.first(), points.last()) {
+                    (Some(first), Some(last)) => (*first + *last) / 2.0,
+                    _ => Vec2::ZERO,
+                };
+                points.push(point);
+            }
+            ui.end_row();
+        }
+        Profile::Arc {
+            radius,
+            angle,
+            thickness,
+        } => {
+            ui.label("Radius:");
+            scalar_ui(ui, radius);
+            ui.end_row();
+            ui.label("Angle:");
+            ui.drag_angle(angle);
+            ui.end_row();
+            ui.label("Thickness:");
+            scalar_ui(ui, thickness);
+            ui.end_row();
+        }
+    }
+}
+
+fn tube_points_ui(ui: &mut Ui, points: &mut Vec<Vec3>) {
+    for (i, point) in points.iter_mut().enumerate() {
+        ui.label(if i % 2 == 0 { "Point:" } else { "Control:" });
+        vec_ui(ui, point);
+        ui.end_row();
+    }
+
+    ui.label("");
+    ui.with_layout(Layout::left_to_right(Align::Center), |ui| {
+        if ui.button("Add curve").clicked() {
+            // Continuing smoothly by mirroring the last control point
+            let (control, end) = match points.as_slice() {
+                [.., control, end] => (*end * 2.0 - *control, *end * 3.0 - *control * 2.0),
+                [end] => (*end + Vec3::X * 0.5, *end + Vec3::X),
+                [] => (Vec3::X * 0.5, Vec3::X),
+            };
+            points.extend([control, end]);
+        }
+        if points.len() > 3 && ui.button("Remove curve").clicked() {
+            points.truncate(points.len() - 2);
+        }
     });
+    ui.end_row();
+}
+
+fn rectangle_corners(half_size: Vec2) -> Vec<Vec2> {
+    vec![
+        Vec2::new(-half_size.x, -half_size.y),
+        Vec2::new(half_size.x, -half_size.y),
+        Vec2::new(half_size.x, half_size.y),
+        Vec2::new(-half_size.x, half_size.y),
+    ]
 }
 
-fn shape_type_ui(ui: &mut Ui, images: &Images, shape_type: &mut ShapeType) {
-    let (name, radius, size, handle, material) = match shape_type {
-        Primitive(Plane, material) => {
-            ("Plane", 1.0, Vec3::ONE, Handle::default(), material.clone())
+fn bake_ui(ui: &mut Ui, commands: &mut Commands, bake_settings: &mut BakeSettings, entity: Entity) {
+    Grid::new("bake").num_columns(2).show(ui, |ui| {
+        ui.label("Resolution:");
+        ui.add(
+            DragValue::new(&mut bake_settings.resolution)
+                .clamp_range(8..=256)
+                .speed(1),
+        );
+        ui.end_row();
+        ui.label("Padding:");
+        ui.add(
+            DragValue::new(&mut bake_settings.padding)
+                .clamp_range(0..=8)
+                .speed(1),
+        );
+        ui.end_row();
+    });
+    if ui.button("Bake").clicked() {
+        commands.add(BakeShape {
+            entity,
+            resolution: bake_settings.resolution,
+            padding: bake_settings.padding,
+        });
+    }
+}
+
+fn shape_type_ui(
+    ui: &mut Ui,
+    images: &Images,
+    octrees: &Octrees,
+    heightfields: &Heightfields,
+    shape_type: &mut ShapeType,
+) {
+    let (name, radius, size, material) = match shape_type {
+        Primitive(Plane, material) => ("Plane", 1.0, Vec3::ONE, material.clone()),
+        Primitive(Sphere { radius }, material) => {
+            ("Sphere", *radius, Vec3::splat(*radius), material.clone())
         }
-        Primitive(Sphere { radius }, material) => (
-            "Sphere",
-            *radius,
-            Vec3::splat(*radius),
-            Handle::default(),
-            material.clone(),
-        ),
         Primitive(Cube { size }, material) => (
             "Cube",
             (size.x + size.y + size.z) / 3.0,
             *size,
-            Handle::default(),
             material.clone(),
         ),
+        Primitive(
+            Torus {
+                major_radius,
+                minor_radius,
+            },
+            material,
+        ) => (
+            "Torus",
+            *major_radius,
+            Vec3::new(
+                2.0 * (*major_radius + *minor_radius),
+                2.0 * (*major_radius + *minor_radius),
+                2.0 * *minor_radius,
+            ),
+            material.clone(),
+        ),
+        Primitive(Cylinder { radius, height }, material) => (
+            "Cylinder",
+            *radius,
+            Vec3::new(2.0 * *radius, 2.0 * *radius, *height),
+            material.clone(),
+        ),
+        Primitive(Capsule { radius, height }, material) => (
+            "Capsule",
+            *radius,
+            Vec3::new(2.0 * *radius, 2.0 * *radius, *height),
+            material.clone(),
+        ),
+        Primitive(Cone { radius, height }, material) => (
+            "Cone",
+            *radius,
+            Vec3::new(2.0 * *radius, 2.0 * *radius, *height),
+            material.clone(),
+        ),
+        Primitive(Extrusion { profile, height }, material) => (
+            "Extrusion",
+            profile.half_size().x,
+            (profile.half_size() * 2.0).extend(*height),
+            material.clone(),
+        ),
+        Primitive(Revolution { profile, offset }, material) => (
+            "Revolution",
+            *offset,
+            Vec3::new(
+                2.0 * (*offset + profile.half_size().x),
+                2.0 * (*offset + profile.half_size().x),
+                2.0 * profile.half_size().y,
+            ),
+            material.clone(),
+        ),
+        Primitive(
+            Tube {
+                points,
+                start_radius,
+                end_radius,
+            },
+            material,
+        ) => (
+            "Tube",
+            start_radius.max(*end_radius),
+            points
+                .iter()
+                .fold(Vec3::ZERO, |size, point| size.max(point.abs() * 2.0)),
+            material.clone(),
+        ),
+        Primitive(Custom { .. }, material) => ("Custom", 1.0, Vec3::ONE, material.clone()),
+        Primitive(Metaballs { .. }, material) => ("Metaballs", 1.0, Vec3::ONE, material.clone()),
         Primitive(Image(image_handle), material) => (
             images
                 .iter()
@@ -185,32 +645,104 @@ fn shape_type_ui(ui: &mut Ui, images: &Images, shape_type: &mut ShapeType) {
                 .map_or("Image", |(name, _)| name),
             1.0,
             Vec3::ONE,
-            image_handle.clone(),
             material.clone(),
         ),
-        Compound(Union) => (
-            "Union",
+        Primitive(Octree(octree_handle), material) => (
+            octrees
+                .iter()
+                .find(|(_, handle)| handle == octree_handle)
+                .map_or("Octree", |(name, _)| name),
             1.0,
             Vec3::ONE,
-            Handle::default(),
-            Material::default(),
+            material.clone(),
         ),
-        Compound(Intersection) => (
-            "Intersection",
+        Primitive(Heightfield(heightfield_handle), material) => (
+            heightfields
+                .iter()
+                .find(|(_, handle)| handle == heightfield_handle)
+                .map_or("Heightfield", |(name, _)| name),
             1.0,
             Vec3::ONE,
-            Handle::default(),
-            Material::default(),
+            material.clone(),
         ),
-        Compound(SmoothUnion) => (
-            "SmoothUnion",
+        Compound(operation) => (
+            match operation {
+                Union => "Union",
+                Intersection => "Intersection",
+                SmoothUnion => "SmoothUnion",
+                Subtraction { .. } => "Subtraction",
+                SmoothIntersection => "SmoothIntersection",
+                SmoothSubtraction { .. } => "SmoothSubtraction",
+                ChamferUnion => "ChamferUnion",
+                ChamferIntersection => "ChamferIntersection",
+                StairsUnion => "StairsUnion",
+                StairsIntersection => "StairsIntersection",
+                ColumnsUnion => "ColumnsUnion",
+                ColumnsIntersection => "ColumnsIntersection",
+                Engrave => "Engrave",
+                Groove => "Groove",
+                Tongue => "Tongue",
+                Morph => "Morph",
+            },
             1.0,
             Vec3::ONE,
-            Handle::default(),
             Material::default(),
         ),
     };
 
+    // Keeping the profile or points when the same type is selected again
+    let extrusion = match shape_type {
+        Primitive(extrusion @ Extrusion { .. }, _) => extrusion.clone(),
+        _ => Extrusion {
+            profile: Profile::Polygon(rectangle_corners(size.truncate() / 2.0)),
+            height: size.z,
+        },
+    };
+    let revolution = match shape_type {
+        Primitive(revolution @ Revolution { .. }, _) => revolution.clone(),
+        _ => Revolution {
+            profile: Profile::Circle {
+                radius: size.z / 2.0,
+            },
+            offset: radius,
+        },
+    };
+    let cutter_material = match shape_type {
+        Compound(Subtraction { cutter_material } | SmoothSubtraction { cutter_material }) => {
+            *cutter_material
+        }
+        _ => true,
+    };
+    let tube = match shape_type {
+        Primitive(tube @ Tube { .. }, _) => tube.clone(),
+        _ => Tube {
+            points: vec![
+                Vec3::new(-size.x / 2.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, size.z / 2.0),
+                Vec3::new(size.x / 2.0, 0.0, 0.0),
+            ],
+            start_radius: radius / 4.0,
+            end_radius: radius / 4.0,
+        },
+    };
+
+    let custom = match shape_type {
+        Primitive(custom @ Custom { .. }, _) => custom.clone(),
+        _ => Custom {
+            source: "return length(pnt) - parameters[0].x;".to_string(),
+            parameters: [
+                Vec4::new(radius, 0.0, 0.0, 0.0),
+                Vec4::ZERO,
+                Vec4::ZERO,
+                Vec4::ZERO,
+            ],
+        },
+    };
+    let threshold = match shape_type {
+        Primitive(Metaballs { threshold }, _) => *threshold,
+        _ => 0.5,
+    };
+
     ComboBox::new("shape_type", "")
         .selected_text(name)
         .show_ui(ui, |ui| {
@@ -225,6 +757,67 @@ fn shape_type_ui(ui: &mut Ui, images: &Images, shape_type: &mut ShapeType) {
                 Primitive(Cube { size }, material.clone()),
                 "Cube",
             );
+            ui.selectable_value(
+                shape_type,
+                Primitive(
+                    Torus {
+                        major_radius: radius,
+                        minor_radius: size.z / 2.0,
+                    },
+                    material.clone(),
+                ),
+                "Torus",
+            );
+            ui.selectable_value(
+                shape_type,
+                Primitive(
+                    Cylinder {
+                        radius,
+                        height: size.z,
+                    },
+                    material.clone(),
+                ),
+                "Cylinder",
+            );
+            ui.selectable_value(
+                shape_type,
+                Primitive(
+                    Capsule {
+                        radius,
+                        height: size.z,
+                    },
+                    material.clone(),
+                ),
+                "Capsule",
+            );
+            ui.selectable_value(
+                shape_type,
+                Primitive(
+                    Cone {
+                        radius,
+                        height: size.z,
+                    },
+                    material.clone(),
+                ),
+                "Cone",
+            );
+            ui.selectable_value(
+                shape_type,
+                Primitive(extrusion, material.clone()),
+                "Extrusion",
+            );
+            ui.selectable_value(
+                shape_type,
+                Primitive(revolution, material.clone()),
+                "Revolution",
+            );
+            ui.selectable_value(shape_type, Primitive(tube, material.clone()), "Tube");
+            ui.selectable_value(shape_type, Primitive(custom, material.clone()), "Custom");
+            ui.selectable_value(
+                shape_type,
+                Primitive(Metaballs { threshold }, material.clone()),
+                "Metaballs",
+            );
             for (name, handle) in images.iter() {
                 ui.selectable_value(
                     shape_type,
@@ -232,12 +825,124 @@ fn shape_type_ui(ui: &mut Ui, images: &Images, shape_type: &mut ShapeType) {
                     name,
                 );
             }
+            for (name, handle) in octrees.iter() {
+                ui.selectable_value(
+                    shape_type,
+                    Primitive(Octree(handle.clone()), material.clone()),
+                    format!("{name} (Octree)"),
+                );
+            }
+            for (name, handle) in heightfields.iter() {
+                ui.selectable_value(
+                    shape_type,
+                    Primitive(Heightfield(handle.clone()), material.clone()),
+                    format!("{name} (Heightfield)"),
+                );
+            }
             ui.selectable_value(shape_type, Compound(Union), "Union");
             ui.selectable_value(shape_type, Compound(Intersection), "Intersection");
             ui.selectable_value(shape_type, Compound(SmoothUnion), "SmoothUnion");
+            ui.selectable_value(
+                shape_type,
+                Compound(Subtraction { cutter_material }),
+                "Subtraction",
+            );
+            ui.selectable_value(
+                shape_type,
+                Compound(SmoothIntersection),
+                "SmoothIntersection",
+            );
+            ui.selectable_value(
+                shape_type,
+                Compound(SmoothSubtraction { cutter_material }),
+                "SmoothSubtraction",
+            );
+            ui.selectable_value(shape_type, Compound(ChamferUnion), "ChamferUnion");
+            ui.selectable_value(
+                shape_type,
+                Compound(ChamferIntersection),
+                "ChamferIntersection",
+            );
+            ui.selectable_value(shape_type, Compound(StairsUnion), "StairsUnion");
+            ui.selectable_value(
+                shape_type,
+                Compound(StairsIntersection),
+                "StairsIntersection",
+            );
+            ui.selectable_value(shape_type, Compound(ColumnsUnion), "ColumnsUnion");
+            ui.selectable_value(
+                shape_type,
+                Compound(ColumnsIntersection),
+                "ColumnsIntersection",
+            );
+            ui.selectable_value(shape_type, Compound(Engrave), "Engrave");
+            ui.selectable_value(shape_type, Compound(Groove), "Groove");
+            ui.selectable_value(shape_type, Compound(Tongue), "Tongue");
+            ui.selectable_value(shape_type, Compound(Morph), "Morph");
         });
 }
 
+fn blend_ui(ui: &mut Ui, operation: Operation, blend: &mut Blend) {
+    match operation {
+        SmoothUnion | SmoothIntersection | SmoothSubtraction { .. } => {
+            ui.label("Smoothing:");
+            ui.with_layout(Layout::left_to_right(Align::Center), |ui| {
+                scalar_ui(ui, &mut blend.k);
+                ComboBox::new("smooth_kernel", "")
+                    .selected_text(match blend.kernel {
+                        SmoothKernel::Polynomial => "Polynomial",
+                        SmoothKernel::Exponential => "Exponential",
+                        SmoothKernel::Circular => "Circular",
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(
+                            &mut blend.kernel,
+                            SmoothKernel::Polynomial,
+                            "Polynomial",
+                        );
+                        ui.selectable_value(
+                            &mut blend.kernel,
+                            SmoothKernel::Exponential,
+                            "Exponential",
+                        );
+                        ui.selectable_value(&mut blend.kernel, SmoothKernel::Circular, "Circular");
+                    });
+            });
+            ui.end_row();
+        }
+        ChamferUnion | ChamferIntersection | StairsUnion | StairsIntersection | ColumnsUnion
+        | ColumnsIntersection => {
+            ui.label("Radius:");
+            scalar_ui(ui, &mut blend.k);
+            ui.end_row();
+        }
+        Groove | Tongue => {
+            ui.label("Width:");
+            scalar_ui(ui, &mut blend.k);
+            ui.end_row();
+        }
+        Morph => {
+            ui.label("T:");
+            scalar_ui(ui, &mut blend.t);
+            ui.end_row();
+        }
+        Union | Intersection | Subtraction { .. } | Engrave => {}
+    }
+    blend.k = blend.k.max(0.0);
+
+    if let StairsUnion | StairsIntersection | ColumnsUnion | ColumnsIntersection = operation {
+        ui.label("Count:");
+        ui.add(DragValue::new(&mut blend.count));
+        blend.count = blend.count.max(1);
+        ui.end_row();
+    }
+    if let Engrave | Groove | Tongue = operation {
+        ui.label("Depth:");
+        scalar_ui(ui, &mut blend.depth);
+        ui.end_row();
+    }
+}
+
 fn scalar_ui(ui: &mut Ui, scalar: &mut f32) {
     ui.add(DragValue::new(scalar).speed(0.01));
 }
@@ -253,6 +958,28 @@ fn vec_ui(ui: &mut Ui, vec: &mut Vec3) {
     });
 }
 
+fn vec2_ui(ui: &mut Ui, vec: &mut Vec2) {
+    ui.with_layout(Layout::left_to_right(Align::Center), |ui| {
+        ui.label("X:");
+        scalar_ui(ui, &mut vec.x);
+        ui.label("Y:");
+        scalar_ui(ui, &mut vec.y);
+    });
+}
+
+fn vec4_ui(ui: &mut Ui, vec: &mut Vec4) {
+    ui.with_layout(Layout::left_to_right(Align::Center), |ui| {
+        ui.label("X:");
+        scalar_ui(ui, &mut vec.x);
+        ui.label("Y:");
+        scalar_ui(ui, &mut vec.y);
+        ui.label("Z:");
+        scalar_ui(ui, &mut vec.z);
+        ui.label("W:");
+        scalar_ui(ui, &mut vec.w);
+    });
+}
+
 fn quat_ui(ui: &mut Ui, quat: &mut Quat) {
     let mut vec = Vec3::from(quat.to_euler(EulerRot::XYZ));
     vec_ui(ui, &mut vec);
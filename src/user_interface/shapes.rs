@@ -1,7 +1,14 @@
 use super::SelectedShape;
 use crate::ray_marching::{
-    Operation::{Intersection, SmoothUnion, Union},
-    Primitive::{Cube, Image, Plane, Sphere},
+    Operation::{
+        ChamferIntersection, ChamferUnion, ColumnsIntersection, ColumnsUnion, Engrave, Groove,
+        Intersection, Morph, SmoothIntersection, SmoothSubtraction, SmoothUnion,
+        StairsIntersection, StairsUnion, Subtraction, Tongue, Union,
+    },
+    Primitive::{
+        Capsule, Cone, Cube, Custom, Cylinder, Extrusion, Heightfield, Image, Metaballs, Octree,
+        Plane, Revolution, Sphere, Torus, Tube,
+    },
     Shape,
     ShapeType::{Compound, Primitive},
 };
@@ -14,11 +21,13 @@ use bevy_egui::{
     EguiContexts,
 };
 
+type RootFilter = (With<Shape>, With<Transform>, Without<Parent>);
+
 pub fn ui(
     mut commands: Commands,
     mut egui_contexts: EguiContexts,
     mut selected_shape: ResMut<SelectedShape>,
-    roots: Query<Entity, (With<Shape>, With<Transform>, Without<Parent>)>,
+    roots: Query<Entity, RootFilter>,
     shapes: Query<(Entity, &Name, &Shape, Option<&Children>)>,
 ) {
     Window::new("Shapes")
@@ -80,10 +89,34 @@ fn shape_label_ui(
             Primitive(Plane, ..) => format!("{name} (Plane)"),
             Primitive(Sphere { .. }, ..) => format!("{name} (Sphere)"),
             Primitive(Cube { .. }, ..) => format!("{name} (Cube)"),
+            Primitive(Torus { .. }, ..) => format!("{name} (Torus)"),
+            Primitive(Cylinder { .. }, ..) => format!("{name} (Cylinder)"),
+            Primitive(Capsule { .. }, ..) => format!("{name} (Capsule)"),
+            Primitive(Cone { .. }, ..) => format!("{name} (Cone)"),
+            Primitive(Extrusion { .. }, ..) => format!("{name} (Extrusion)"),
+            Primitive(Revolution { .. }, ..) => format!("{name} (Revolution)"),
+            Primitive(Tube { .. }, ..) => format!("{name} (Tube)"),
             Primitive(Image { .. }, ..) => format!("{name} (Image)"),
+            Primitive(Octree { .. }, ..) => format!("{name} (Octree)"),
+            Primitive(Heightfield { .. }, ..) => format!("{name} (Heightfield)"),
+            Primitive(Custom { .. }, ..) => format!("{name} (Custom)"),
+            Primitive(Metaballs { .. }, ..) => format!("{name} (Metaballs)"),
             Compound(Union) => format!("{name} (Union)"),
             Compound(Intersection) => format!("{name} (Intersection)"),
             Compound(SmoothUnion) => format!("{name} (SmoothUnion)"),
+            Compound(Subtraction { .. }) => format!("{name} (Subtraction)"),
+            Compound(SmoothIntersection) => format!("{name} (SmoothIntersection)"),
+            Compound(SmoothSubtraction { .. }) => format!("{name} (SmoothSubtraction)"),
+            Compound(ChamferUnion) => format!("{name} (ChamferUnion)"),
+            Compound(ChamferIntersection) => format!("{name} (ChamferIntersection)"),
+            Compound(StairsUnion) => format!("{name} (StairsUnion)"),
+            Compound(StairsIntersection) => format!("{name} (StairsIntersection)"),
+            Compound(ColumnsUnion) => format!("{name} (ColumnsUnion)"),
+            Compound(ColumnsIntersection) => format!("{name} (ColumnsIntersection)"),
+            Compound(Engrave) => format!("{name} (Engrave)"),
+            Compound(Groove) => format!("{name} (Groove)"),
+            Compound(Tongue) => format!("{name} (Tongue)"),
+            Compound(Morph) => format!("{name} (Morph)"),
         },
     );
 }
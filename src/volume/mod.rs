@@ -0,0 +1,277 @@
+mod nrrd;
+
+pub use nrrd::{ByteSkip, Encoding, NrrdHeader};
+
+use bevy::{asset::Error, prelude::Vec3, render::render_resource::Extent3d};
+
+use crate::ray_marching::ShapeImage;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ValueType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Endian {
+    Little,
+    Big,
+}
+
+#[derive(Clone, Debug)]
+pub struct RawHeader {
+    pub resolution: Extent3d,
+    pub spacing: Vec3,
+    pub value_type: ValueType,
+    pub endian: Endian,
+}
+
+pub struct ScalarVolume {
+    resolution: Extent3d,
+    spacing: Vec3,
+    data: Vec<f32>,
+}
+
+impl RawHeader {
+    pub fn byte_count(&self) -> usize {
+        texel_count(self.resolution) * self.value_type.size()
+    }
+
+    // Reads the resolution and the value type from file names like the ones of the open scivis
+    // datasets, e.g. "bonsai_256x256x256_uint8.raw"
+    pub fn from_file_name(file_name: &str) -> Result<Self, Error> {
+        let stem = file_name.split('.').next().unwrap_or_default();
+        let mut resolution = None;
+        let mut value_type = None;
+        for part in stem.split('_') {
+            if let Ok(type_from_name) = ValueType::from_name(part) {
+                value_type = Some(type_from_name);
+            } else if let [Ok(width), Ok(height), Ok(depth)] = part
+                .split('x')
+                .map(|size| size.parse::<u32>())
+                .collect::<Vec<_>>()[..]
+            {
+                resolution = Some(Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: depth,
+                });
+            }
+        }
+
+        match (resolution, value_type) {
+            (Some(resolution), Some(value_type)) => Ok(Self {
+                resolution,
+                spacing: Vec3::ONE,
+                value_type,
+                endian: Endian::Little,
+            }),
+            _ => Err(Error::msg(format!(
+                "missing resolution or value type in the raw file name {file_name}, \
+                 expected <name>_<width>x<height>x<depth>_<type>.raw"
+            ))),
+        }
+    }
+}
+
+impl ScalarVolume {
+    pub fn from_raw(bytes: &[u8], header: &RawHeader) -> Result<Self, Error> {
+        let value_size = header.value_type.size();
+        let byte_count = header.byte_count();
+        if bytes.len() < byte_count {
+            return Err(Error::msg(format!(
+                "expected {byte_count} bytes of volume data, found {}",
+                bytes.len()
+            )));
+        }
+
+        let data = bytes[..byte_count]
+            .chunks(value_size)
+            .map(|chunk| header.value_type.read(chunk, header.endian))
+            .collect();
+
+        Ok(Self {
+            resolution: header.resolution,
+            spacing: header.spacing,
+            data,
+        })
+    }
+
+    pub fn from_ascii(string: &str, resolution: Extent3d, spacing: Vec3) -> Result<Self, Error> {
+        let data = string
+            .split_whitespace()
+            .take(texel_count(resolution))
+            .map(|value| value.parse::<f32>())
+            .collect::<Result<Vec<_>, _>>()?;
+        if data.len() < texel_count(resolution) {
+            return Err(Error::msg(format!(
+                "expected {} volume values, found {}",
+                texel_count(resolution),
+                data.len()
+            )));
+        }
+
+        Ok(Self {
+            resolution,
+            spacing,
+            data,
+        })
+    }
+
+    // Loads a volume with an attached header, use NrrdHeader for detached ones
+    pub fn from_nrrd(bytes: &[u8]) -> Result<(Self, NrrdHeader), Error> {
+        let (header, offset) = NrrdHeader::parse(bytes)?;
+        if header.data_file.is_some() {
+            return Err(Error::msg("the nrrd header is detached from its data"));
+        }
+        Ok((header.load(&bytes[offset..])?, header))
+    }
+
+    pub fn resolution(&self) -> Extent3d {
+        self.resolution
+    }
+
+    pub fn spacing(&self) -> Vec3 {
+        self.spacing
+    }
+
+    pub fn value_range(&self) -> (f32, f32) {
+        self.data
+            .iter()
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), value| {
+                (min.min(*value), max.max(*value))
+            })
+    }
+
+    // Values above the iso value are inside the shape. Volumes that are all inside or all outside
+    // have no surface to measure the distances to, so they're rejected
+    pub fn to_shape_image(&self, iso_value: f32) -> Result<ShapeImage, Error> {
+        let (min, max) = self.value_range();
+        if max <= iso_value || min > iso_value {
+            return Err(Error::msg(format!(
+                "the volume has no surface at the iso value {iso_value}, \
+                 its values are {min} - {max}"
+            )));
+        }
+
+        let size = self.spacing
+            * Vec3::new(
+                self.resolution.width as f32,
+                self.resolution.height as f32,
+                self.resolution.depth_or_array_layers as f32,
+            );
+
+        let mut shape_image = ShapeImage {
+            size,
+            resolution: self.resolution,
+            data: self.data.iter().map(|value| iso_value - value).collect(),
+        };
+        shape_image.redistance();
+        Ok(shape_image)
+    }
+}
+
+impl ValueType {
+    // The type names of nrrd headers, along with the float32 and float64 of raw file names
+    pub fn from_name(name: &str) -> Result<Self, Error> {
+        Ok(match name {
+            "signed char" | "int8" | "int8_t" => Self::I8,
+            "uchar" | "unsigned char" | "uint8" | "uint8_t" => Self::U8,
+            "short" | "short int" | "signed short" | "signed short int" | "int16" | "int16_t" => {
+                Self::I16
+            }
+            "ushort" | "unsigned short" | "unsigned short int" | "uint16" | "uint16_t" => Self::U16,
+            "int" | "signed int" | "int32" | "int32_t" => Self::I32,
+            "uint" | "unsigned int" | "uint32" | "uint32_t" => Self::U32,
+            "float" | "float32" => Self::F32,
+            "double" | "float64" => Self::F64,
+            _ => return Err(Error::msg(format!("unsupported type: {name}"))),
+        })
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            Self::I8 | Self::U8 => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+
+    fn read(&self, bytes: &[u8], endian: Endian) -> f32 {
+        macro_rules! read {
+            ($type:ty) => {
+                match endian {
+                    Endian::Little => <$type>::from_le_bytes(bytes.try_into().unwrap()) as f32,
+                    Endian::Big => <$type>::from_be_bytes(bytes.try_into().unwrap()) as f32,
+                }
+            };
+        }
+
+        match self {
+            Self::I8 => read!(i8),
+            Self::U8 => read!(u8),
+            Self::I16 => read!(i16),
+            Self::U16 => read!(u16),
+            Self::I32 => read!(i32),
+            Self::U32 => read!(u32),
+            Self::F32 => read!(f32),
+            Self::F64 => read!(f64),
+        }
+    }
+}
+
+fn texel_count(resolution: Extent3d) -> usize {
+    resolution.width as usize
+        * resolution.height as usize
+        * resolution.depth_or_array_layers as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_raw_headers_from_file_names() {
+        let header = RawHeader::from_file_name("bonsai_256x128x64_uint8.raw").unwrap();
+        assert_eq!(header.resolution.width, 256);
+        assert_eq!(header.resolution.height, 128);
+        assert_eq!(header.resolution.depth_or_array_layers, 64);
+        assert_eq!(header.value_type, ValueType::U8);
+        assert_eq!(header.endian, Endian::Little);
+
+        let header = RawHeader::from_file_name("skull_8x8x8_float32.raw").unwrap();
+        assert_eq!(header.value_type, ValueType::F32);
+
+        assert!(RawHeader::from_file_name("bonsai.raw").is_err());
+        assert!(RawHeader::from_file_name("bonsai_256x256_uint8.raw").is_err());
+    }
+
+    #[test]
+    fn converts_volumes_to_shape_images() {
+        let header = RawHeader {
+            resolution: Extent3d {
+                width: 4,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            spacing: Vec3::splat(2.0),
+            value_type: ValueType::U8,
+            endian: Endian::Little,
+        };
+        let volume = ScalarVolume::from_raw(&[0, 0, 255, 255], &header).unwrap();
+        let shape_image = volume.to_shape_image(127.5).unwrap();
+        assert_eq!(shape_image.size, Vec3::new(8.0, 2.0, 2.0));
+        assert!(shape_image.data[0] > 0.0 && shape_image.data[3] < 0.0);
+
+        assert!(volume.to_shape_image(255.0).is_err());
+        let constant = ScalarVolume::from_raw(&[7, 7, 7, 7], &header).unwrap();
+        assert!(constant.to_shape_image(7.0).is_err());
+    }
+}
@@ -0,0 +1,366 @@
+use bevy::{asset::Error, prelude::Vec3, render::render_resource::Extent3d};
+use flate2::read::GzDecoder;
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use super::{Endian, RawHeader, ScalarVolume, ValueType};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Encoding {
+    Raw,
+    Ascii,
+    Gzip,
+}
+
+#[derive(Clone, Debug)]
+pub struct NrrdHeader {
+    pub raw: RawHeader,
+    pub encoding: Encoding,
+    pub data_file: Option<String>,
+    pub iso_value: Option<f32>,
+    // Skipped at the start of the data, first the lines and then the bytes, which gzip data
+    // skips after decompressing
+    pub line_skip: usize,
+    pub byte_skip: ByteSkip,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ByteSkip {
+    Bytes(usize),
+    // A byte skip of -1, the raw data is at the end of the file
+    ToEnd,
+}
+
+impl NrrdHeader {
+    // Returns the header and the offset of the attached data
+    pub fn parse(bytes: &[u8]) -> Result<(Self, usize), Error> {
+        let mut offset = 0;
+        let mut next_line = || {
+            if offset > bytes.len() {
+                return None;
+            }
+            let line = bytes[offset..].split(|byte| *byte == b'\n').next()?;
+            offset += line.len() + 1;
+            Some(std::str::from_utf8(line).map(|line| line.trim_end_matches('\r')))
+        };
+
+        match next_line() {
+            Some(Ok(magic)) if magic.starts_with("NRRD") => {}
+            _ => return Err(Error::msg("not a nrrd file")),
+        }
+
+        let mut value_type = None;
+        let mut dimension = None;
+        let mut sizes = None;
+        let mut spacing = Vec3::ONE;
+        let mut endian = Endian::Little;
+        let mut encoding = None;
+        let mut data_file = None;
+        let mut iso_value = None;
+        let mut line_skip = 0;
+        let mut byte_skip = ByteSkip::Bytes(0);
+
+        while let Some(line) = next_line() {
+            let line = line?;
+            if line.is_empty() {
+                break;
+            }
+            if line.starts_with('#') {
+                continue;
+            }
+
+            if let Some((key, value)) = line.split_once(":=") {
+                if key == "iso" {
+                    iso_value = Some(value.trim().parse()?);
+                }
+                continue;
+            }
+
+            let Some((field, value)) = line.split_once(':') else {
+                return Err(Error::msg(format!("invalid header line: {line}")));
+            };
+            let value = value.trim();
+            match field {
+                "type" => value_type = Some(ValueType::from_name(value)?),
+                "dimension" => dimension = Some(value.parse::<u32>()?),
+                "sizes" => {
+                    sizes = Some(
+                        value
+                            .split_whitespace()
+                            .map(|size| size.parse::<u32>())
+                            .collect::<Result<Vec<_>, _>>()?,
+                    )
+                }
+                "spacings" => spacing = parse_spacings(value)?,
+                "space directions" => spacing = parse_space_directions(value)?,
+                "endian" => {
+                    endian = match value {
+                        "little" => Endian::Little,
+                        "big" => Endian::Big,
+                        _ => return Err(Error::msg(format!("invalid endian: {value}"))),
+                    }
+                }
+                "encoding" => {
+                    encoding = Some(match value {
+                        "raw" => Encoding::Raw,
+                        "ascii" | "text" | "txt" => Encoding::Ascii,
+                        "gzip" | "gz" => Encoding::Gzip,
+                        _ => return Err(Error::msg(format!("unsupported encoding: {value}"))),
+                    })
+                }
+                "data file" | "datafile" => {
+                    if value.starts_with("LIST") || value.split_whitespace().count() > 1 {
+                        return Err(Error::msg(format!("unsupported data file: {value}")));
+                    }
+                    data_file = Some(String::from(value))
+                }
+                "line skip" | "lineskip" => line_skip = value.parse()?,
+                "byte skip" | "byteskip" => {
+                    byte_skip = match value {
+                        "-1" => ByteSkip::ToEnd,
+                        _ => ByteSkip::Bytes(value.parse()?),
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if dimension != Some(3) {
+            return Err(Error::msg("only 3 dimensional volumes are supported"));
+        }
+        let resolution = match sizes.as_deref() {
+            Some(&[width, height, depth]) => Extent3d {
+                width,
+                height,
+                depth_or_array_layers: depth,
+            },
+            _ => return Err(Error::msg("missing or invalid sizes")),
+        };
+
+        let encoding = encoding.ok_or_else(|| Error::msg("missing encoding"))?;
+        if byte_skip == ByteSkip::ToEnd && encoding != Encoding::Raw {
+            return Err(Error::msg("a byte skip of -1 needs raw encoding"));
+        }
+
+        Ok((
+            Self {
+                raw: RawHeader {
+                    resolution,
+                    spacing,
+                    value_type: value_type.ok_or_else(|| Error::msg("missing type"))?,
+                    endian,
+                },
+                encoding,
+                data_file,
+                iso_value,
+                line_skip,
+                byte_skip,
+            },
+            offset.min(bytes.len()),
+        ))
+    }
+
+    // The path of the detached data, relative to the directory of the header
+    pub fn data_path(&self, header_path: &Path) -> Option<PathBuf> {
+        let data_file = self.data_file.as_ref()?;
+        Some(
+            header_path
+                .parent()
+                .map_or_else(|| data_file.into(), |parent| parent.join(data_file)),
+        )
+    }
+
+    pub fn load(&self, data: &[u8]) -> Result<ScalarVolume, Error> {
+        let mut data = data;
+        for _ in 0..self.line_skip {
+            let Some(end) = data.iter().position(|byte| *byte == b'\n') else {
+                return Err(Error::msg("the data is shorter than its line skip"));
+            };
+            data = &data[end + 1..];
+        }
+
+        match self.encoding {
+            Encoding::Raw => ScalarVolume::from_raw(self.skip_bytes(data)?, &self.raw),
+            Encoding::Gzip => {
+                let mut bytes = Vec::new();
+                GzDecoder::new(data).read_to_end(&mut bytes)?;
+                ScalarVolume::from_raw(self.skip_bytes(&bytes)?, &self.raw)
+            }
+            Encoding::Ascii => ScalarVolume::from_ascii(
+                std::str::from_utf8(self.skip_bytes(data)?)?,
+                self.raw.resolution,
+                self.raw.spacing,
+            ),
+        }
+    }
+
+    fn skip_bytes<'a>(&self, data: &'a [u8]) -> Result<&'a [u8], Error> {
+        let skip = match self.byte_skip {
+            ByteSkip::Bytes(skip) => skip,
+            ByteSkip::ToEnd => data.len().saturating_sub(self.raw.byte_count()),
+        };
+        data.get(skip..)
+            .ok_or_else(|| Error::msg("the data is shorter than its byte skip"))
+    }
+}
+
+fn parse_spacings(value: &str) -> Result<Vec3, Error> {
+    let spacings = value
+        .split_whitespace()
+        .map(|spacing| spacing.parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()?;
+    match spacings[..] {
+        [x, y, z] => Ok(Vec3::new(x, y, z)),
+        _ => Err(Error::msg(format!("invalid spacings: {value}"))),
+    }
+}
+
+fn parse_space_directions(value: &str) -> Result<Vec3, Error> {
+    let lengths = value
+        .split(')')
+        .map(|direction| direction.trim().trim_start_matches('('))
+        .filter(|direction| !direction.is_empty())
+        .map(|direction| {
+            let components = direction
+                .split(',')
+                .map(|component| component.trim().parse::<f32>())
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(components
+                .iter()
+                .map(|component| component * component)
+                .sum::<f32>()
+                .sqrt())
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    match lengths[..] {
+        [x, y, z] => Ok(Vec3::new(x, y, z)),
+        _ => Err(Error::msg(format!("invalid space directions: {value}"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    fn nrrd(fields: &str) -> Vec<u8> {
+        format!("NRRD0004\ndimension: 3\nsizes: 2 1 1\n{fields}\n\n").into_bytes()
+    }
+
+    #[test]
+    fn parses_the_header_fields() {
+        let bytes = nrrd("type: ushort\nencoding: raw\nspacings: 0.5 1 2\n# comment\niso:=100");
+        let (header, offset) = NrrdHeader::parse(&bytes).unwrap();
+        assert_eq!(offset, bytes.len());
+        assert_eq!(header.raw.value_type, ValueType::U16);
+        assert_eq!(header.raw.resolution.width, 2);
+        assert_eq!(header.raw.spacing, Vec3::new(0.5, 1.0, 2.0));
+        assert_eq!(header.raw.endian, Endian::Little);
+        assert_eq!(header.encoding, Encoding::Raw);
+        assert_eq!(header.iso_value, Some(100.0));
+        assert!(header.data_file.is_none());
+    }
+
+    #[test]
+    fn reads_both_endians() {
+        let mut little = nrrd("type: short\nencoding: raw\nendian: little");
+        little.extend_from_slice(&[0x01, 0x02, 0xff, 0xff]);
+        let (volume, _) = ScalarVolume::from_nrrd(&little).unwrap();
+        assert_eq!(volume.value_range(), (-1.0, 513.0));
+
+        let mut big = nrrd("type: short\nencoding: raw\nendian: big");
+        big.extend_from_slice(&[0x01, 0x02, 0xff, 0xff]);
+        let (volume, _) = ScalarVolume::from_nrrd(&big).unwrap();
+        assert_eq!(volume.value_range(), (-1.0, 258.0));
+    }
+
+    #[test]
+    fn reads_ascii_and_gzip_data() {
+        let mut ascii = nrrd("type: float\nencoding: ascii");
+        ascii.extend_from_slice(b"1.5 -2\n");
+        let (volume, _) = ScalarVolume::from_nrrd(&ascii).unwrap();
+        assert_eq!(volume.value_range(), (-2.0, 1.5));
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&[3, 7]).unwrap();
+        let mut gzip = nrrd("type: uchar\nencoding: gzip");
+        gzip.extend_from_slice(&encoder.finish().unwrap());
+        let (volume, header) = ScalarVolume::from_nrrd(&gzip).unwrap();
+        assert_eq!(header.encoding, Encoding::Gzip);
+        assert_eq!(volume.value_range(), (3.0, 7.0));
+    }
+
+    #[test]
+    fn resolves_detached_data_next_to_the_header() {
+        let bytes = nrrd("type: uchar\nencoding: raw\ndata file: volume.raw");
+        let (header, _) = NrrdHeader::parse(&bytes).unwrap();
+        assert_eq!(
+            header.data_path(Path::new("assets/volume.nhdr")),
+            Some(PathBuf::from("assets/volume.raw"))
+        );
+        assert!(ScalarVolume::from_nrrd(&bytes).is_err());
+
+        let list = nrrd("type: uchar\nencoding: raw\ndata file: LIST");
+        assert!(NrrdHeader::parse(&list).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_headers() {
+        for bytes in [
+            nrrd("type: uchar\nencoding: bzip2"),
+            nrrd("type: uchar\nencoding: hex"),
+            nrrd("type: long\nencoding: raw"),
+            nrrd("type: uchar"),
+            b"NRRD0004\ndimension: 3\ntype: uchar\nencoding: raw\n".to_vec(),
+            b"P6\n".to_vec(),
+        ] {
+            assert!(NrrdHeader::parse(&bytes).is_err());
+        }
+
+        let error = NrrdHeader::parse(&nrrd("type: uchar\nencoding: bzip2")).unwrap_err();
+        assert_eq!(error.to_string(), "unsupported encoding: bzip2");
+    }
+
+    #[test]
+    fn skips_lines_and_bytes_before_the_data() {
+        let mut bytes = nrrd("type: uchar\nencoding: raw\nline skip: 2\nbyte skip: 3");
+        bytes.extend_from_slice(b"first\nsecond\n");
+        bytes.extend_from_slice(&[9, 9, 9, 4, 6]);
+        let (volume, _) = ScalarVolume::from_nrrd(&bytes).unwrap();
+        assert_eq!(volume.value_range(), (4.0, 6.0));
+
+        let mut bytes = nrrd("type: uchar\nencoding: raw\nbyte skip: -1");
+        bytes.extend_from_slice(&[9, 9, 2, 5]);
+        let (volume, header) = ScalarVolume::from_nrrd(&bytes).unwrap();
+        assert_eq!(header.byte_skip, ByteSkip::ToEnd);
+        assert_eq!(volume.value_range(), (2.0, 5.0));
+
+        // The bytes of gzip data are skipped after decompressing it
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&[9, 1, 8]).unwrap();
+        let mut gzip = nrrd("type: uchar\nencoding: gzip\nbyte skip: 1");
+        gzip.extend_from_slice(&encoder.finish().unwrap());
+        let (volume, _) = ScalarVolume::from_nrrd(&gzip).unwrap();
+        assert_eq!(volume.value_range(), (1.0, 8.0));
+
+        let mut ascii = nrrd("type: float\nencoding: ascii\nline skip: 1");
+        ascii.extend_from_slice(b"values\n1.5 -2\n");
+        let (volume, _) = ScalarVolume::from_nrrd(&ascii).unwrap();
+        assert_eq!(volume.value_range(), (-2.0, 1.5));
+
+        assert!(NrrdHeader::parse(&nrrd("type: uchar\nencoding: gzip\nbyte skip: -1")).is_err());
+        let mut short = nrrd("type: uchar\nencoding: raw\nline skip: 3");
+        short.extend_from_slice(b"one\ntwo\n");
+        assert!(ScalarVolume::from_nrrd(&short).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_data() {
+        let mut bytes = nrrd("type: float\nencoding: raw");
+        bytes.extend_from_slice(&1.0f32.to_le_bytes());
+        assert!(ScalarVolume::from_nrrd(&bytes).is_err());
+    }
+}